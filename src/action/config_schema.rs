@@ -0,0 +1,95 @@
+use clap::ArgMatches;
+use schemars::schema_for;
+
+use crate::config::Config;
+
+/// Invoke config schema command.
+///
+/// Prints the JSON schema of the configuration file, for editor validation and autocompletion.
+pub fn invoke(_matches: &ArgMatches) {
+    println!("{}", schema());
+}
+
+/// Generate the JSON schema of the configuration file from the config types.
+pub fn schema() -> String {
+    serde_json::to_string_pretty(&schema_for!(Config)).expect("failed to serialize config schema")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Find the schema node for the given dotted key path, resolving definition references.
+    fn schema_node<'a>(schema: &'a Value, path: &str) -> Option<&'a Value> {
+        let mut node = schema;
+        for name in path.split('.') {
+            node = resolve(schema, node)?.get("properties")?.get(name)?;
+        }
+        Some(node)
+    }
+
+    /// Resolve a schema node to the node listing its properties, following references and
+    /// subschemas.
+    fn resolve<'a>(schema: &'a Value, node: &'a Value) -> Option<&'a Value> {
+        if node.get("properties").is_some() {
+            return Some(node);
+        }
+        if let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+            let name = reference.strip_prefix("#/definitions/")?;
+            return resolve(schema, schema["definitions"].get(name)?);
+        }
+        ["allOf", "anyOf", "oneOf"]
+            .iter()
+            .filter_map(|key| node.get(key).and_then(Value::as_array))
+            .flatten()
+            .find_map(|node| resolve(schema, node))
+    }
+
+    #[test]
+    fn schema_covers_example_config() {
+        let schema: Value = serde_json::from_str(&schema()).unwrap();
+
+        // Collect all keys from the example config, including commented ones
+        let mut section = String::new();
+        let mut missing = vec![];
+        for line in include_str!("../../res/lazymc.toml").lines() {
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.into();
+                continue;
+            }
+            let Some((key, _)) = line.trim_start_matches('#').split_once(" = ") else {
+                continue;
+            };
+            if !key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            {
+                continue;
+            }
+
+            let path = format!("{section}.{key}");
+            if schema_node(&schema, &path).is_none() {
+                missing.push(path);
+            }
+        }
+
+        assert!(
+            missing.is_empty(),
+            "example config keys missing in schema: {missing:?}"
+        );
+    }
+
+    #[test]
+    fn schema_has_defaults() {
+        let schema: Value = serde_json::from_str(&schema()).unwrap();
+        let default = |path| schema_node(&schema, path).and_then(|node| node.get("default"));
+
+        assert_eq!(default("time.sleep_after"), Some(&json!(60)));
+        assert_eq!(default("server.start_timeout"), Some(&json!(300)));
+        assert_eq!(default("server.address"), Some(&json!(["127.0.0.1:25566"])));
+        assert_eq!(default("join.methods"), Some(&json!(["hold", "kick"])));
+        assert_eq!(default("advanced.preflight"), Some(&json!("warn")));
+    }
+}
@@ -0,0 +1,150 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::BytesMut;
+use clap::ArgMatches;
+use minecraft_protocol::decoder::Decoder;
+use minecraft_protocol::version::v1_14_4::handshake::Handshake;
+use minecraft_protocol::version::v1_14_4::login::{LoginDisconnect, LoginStart, SetCompression};
+use tokio::net::TcpStream;
+use tokio::time;
+
+use crate::config::{self, Config};
+use crate::proto::client::{Client, ClientInfo, ClientState};
+use crate::proto::packets::play::join_game::JoinGameData;
+use crate::proto::{packet, packets};
+
+/// Minecraft username used by the synthetic client.
+const TEST_USER: &str = "_lazymc_test";
+
+/// Extra time on top of the lobby timeout to wait for the whole test to complete.
+const TEST_TIMEOUT_MARGIN: Duration = Duration::from_secs(60);
+
+/// Stage of the lobby flow the synthetic client reached.
+#[derive(Debug, Copy, Clone)]
+enum Stage {
+    Connect,
+    Login,
+    JoinGame,
+    Respawn,
+}
+
+/// Invoke lobby test command.
+///
+/// Connects a synthetic client to the public address and walks through the full lobby flow,
+/// reporting the stage at which it failed.
+#[tokio::main(flavor = "multi_thread")]
+pub async fn invoke(matches: &ArgMatches) -> Result<(), ()> {
+    let config = config::load(matches);
+
+    let timeout = Duration::from_secs(config.join.lobby.timeout as u64) + TEST_TIMEOUT_MARGIN;
+    let mut stage = Stage::Connect;
+    let result = time::timeout(timeout, run(&config, &mut stage)).await;
+
+    match result {
+        Ok(Ok(())) => {
+            eprintln!("Lobby test succeeded, client was moved to the server");
+            Ok(())
+        }
+        Ok(Err(err)) => {
+            eprintln!("Lobby test failed at {:?} stage: {}", stage, err);
+            Err(())
+        }
+        Err(_) => {
+            eprintln!(
+                "Lobby test failed at {:?} stage: timed out after {}s",
+                stage,
+                timeout.as_secs()
+            );
+            Err(())
+        }
+    }
+}
+
+/// Run the lobby flow with a synthetic client, updating the reached stage.
+async fn run(config: &Config, stage: &mut Stage) -> Result<(), String> {
+    // Connect to public address, use localhost for unspecified address
+    let mut addr = config.public.address;
+    if addr.ip().is_unspecified() {
+        addr = SocketAddr::new("127.0.0.1".parse().unwrap(), addr.port());
+    }
+    eprintln!("Connecting synthetic client to {}...", addr);
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .map_err(|err| format!("failed to connect: {}", err))?;
+    let (mut reader, mut writer) = stream.split();
+
+    let client = Client::dummy();
+    let mut client_info = ClientInfo::empty();
+    client_info.protocol.replace(config.public.protocol);
+
+    // Send handshake and login start
+    *stage = Stage::Login;
+    packet::write_packet(
+        Handshake {
+            protocol_version: config.public.protocol as i32,
+            server_addr: addr.ip().to_string(),
+            server_port: addr.port(),
+            next_state: ClientState::Login.to_id(),
+        },
+        &client,
+        &mut writer,
+    )
+    .await
+    .map_err(|_| "failed to send handshake")?;
+    client.set_state(ClientState::Login);
+    packet::write_packet(
+        LoginStart {
+            name: TEST_USER.into(),
+        },
+        &client,
+        &mut writer,
+    )
+    .await
+    .map_err(|_| "failed to send login start")?;
+
+    let mut buf = BytesMut::new();
+    loop {
+        let (packet, _raw) = match packet::read_packet(&client, &mut buf, &mut reader).await {
+            Ok(Some(packet)) => packet,
+            Ok(None) => return Err("connection closed".into()),
+            Err(_) => return Err("failed to read packet".into()),
+        };
+
+        match client.state() {
+            ClientState::Login if packet.id == packets::login::CLIENT_SET_COMPRESSION => {
+                let set_compression = SetCompression::decode(&mut packet.data.as_slice())
+                    .map_err(|err| format!("failed to decode set compression: {:?}", err))?;
+                client.set_compression(set_compression.threshold);
+            }
+            ClientState::Login if packet.id == packets::login::CLIENT_DISCONNECT => {
+                let reason = LoginDisconnect::decode(&mut packet.data.as_slice())
+                    .map(|p| format!("{:?}", p.reason))
+                    .unwrap_or_else(|_| "unknown".into());
+                return Err(format!("disconnected: {}", reason));
+            }
+            ClientState::Login if packet.id == packets::login::CLIENT_ENCRYPTION_REQUEST => {
+                return Err("got encryption request, server must be in offline mode".into());
+            }
+            ClientState::Login if packet.id == packets::login::CLIENT_LOGIN_SUCCESS => {
+                eprintln!("Login succeeded, waiting for join game...");
+                client.set_state(ClientState::Play);
+                *stage = Stage::JoinGame;
+            }
+            ClientState::Play if packets::play::join_game::is_packet(&client_info, packet.id) => {
+                // Only the first join game is for the lobby
+                if let Stage::JoinGame = stage {
+                    JoinGameData::from_packet(&client_info, packet)
+                        .map_err(|err| format!("failed to parse join game: {:?}", err))?;
+                    eprintln!("Joined lobby, waiting for server to become ready...");
+                    *stage = Stage::Respawn;
+                }
+            }
+            ClientState::Play if packets::play::respawn::is_packet(&client_info, packet.id) => {
+                eprintln!("Got respawn into server world");
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,3 +1,6 @@
 pub mod config_generate;
+pub mod config_schema;
 pub mod config_test;
+#[cfg(feature = "dev")]
+pub mod lobby_test;
 pub mod start;
@@ -1,12 +1,15 @@
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use clap::ArgMatches;
 
 use crate::config::{self, Config, Server as ConfigServer};
+use crate::forge;
 use crate::mc::server_properties;
 use crate::proto;
 use crate::service;
+use crate::util::error::{quit_error_msg, ErrorHintsBuilder};
 
 /// RCON randomized password length.
 #[cfg(feature = "rcon")]
@@ -18,10 +21,49 @@ pub fn invoke(matches: &ArgMatches) -> Result<(), ()> {
     #[allow(unused_mut)]
     let mut config = config::load(matches);
 
+    // Addresses must not conflict, or we'd proxy to ourselves
+    check_addresses(&config);
+
+    // Show resolved server directory
+    log_server_directory(&config);
+
+    // Warn about features that require a managed server process
+    if !config.server.is_managed() {
+        warn_unmanaged(&config);
+    }
+
+    // CPU idle detection requires process stats
+    if config.time.sleep_when_idle_cpu_below.is_some() && !cfg!(feature = "proc-stats") {
+        warn!(target: "lazymc", "CPU idle detection is not supported in this lazymc build, ignoring 'time.sleep_when_idle_cpu_below'");
+    }
+
+    // AFK detection requires RCON
+    if config.time.afk_sleep && !(cfg!(feature = "rcon") && config.rcon.enabled) {
+        warn!(target: "lazymc", "AFK detection requires RCON to be enabled, ignoring 'time.afk_sleep'");
+    }
+
+    // Lobby can't connect to online mode servers
+    if config.join.uses_lobby() {
+        warn_lobby_online_mode(&config);
+    }
+
+    // Mod handshake must be supported by the lobby and client brand detection
+    if forge::is_configuration_handshake(config.server.mod_loader(), config.public.protocol) {
+        check_configuration_handshake(&config);
+    }
+
+    // Player info forwarding must be supported and configured properly
+    if config.server.forwarding != config::Forwarding::None {
+        check_forwarding(&config);
+    }
+
     // Prepare RCON if enabled
     #[cfg(feature = "rcon")]
     prepare_rcon(&mut config);
 
+    // Use server compression threshold for lobby and probe connections
+    load_compression_threshold(&config);
+
     // Rewrite server server.properties file
     rewrite_server_properties(&config);
 
@@ -30,17 +72,199 @@ pub fn invoke(matches: &ArgMatches) -> Result<(), ()> {
     service::server::service(config)
 }
 
+/// Check for conflicting public, server and RCON addresses, quit if any conflict.
+fn check_addresses(config: &Config) {
+    let mut conflicts = Vec::new();
+
+    for addr in &config.server.addresses {
+        if addrs_conflict(config.public.address, *addr) {
+            conflicts.push(format!(
+                "server address {} is the same as public address {}, lazymc would proxy to itself",
+                addr, config.public.address
+            ));
+        }
+    }
+
+    if cfg!(feature = "rcon") && config.rcon.enabled {
+        let rcon = SocketAddr::new(config.server.address().ip(), config.rcon.port);
+        if addrs_conflict(config.public.address, rcon) {
+            conflicts.push(format!(
+                "RCON port {} is the same as public port",
+                config.rcon.port
+            ));
+        }
+        if config.server.address() == rcon {
+            conflicts.push(format!(
+                "RCON port {} is the same as server port",
+                config.rcon.port
+            ));
+        }
+    }
+
+    if conflicts.is_empty() {
+        return;
+    }
+
+    let mut hints = ErrorHintsBuilder::default();
+    hints.config(true).config_test(true);
+    for conflict in &conflicts {
+        hints = hints.add_info(conflict.clone());
+    }
+    quit_error_msg(
+        "Conflicting addresses in config, all of public, server and RCON must use a different port or host",
+        hints.build().unwrap(),
+    );
+}
+
+/// Check whether a public address we bind to conflicts with the given other address.
+///
+/// If the public address binds to all interfaces, it conflicts with any local address on the same
+/// port. Remote hosts can't be detected and are assumed not to conflict.
+fn addrs_conflict(public: SocketAddr, other: SocketAddr) -> bool {
+    if public.port() != other.port() {
+        return false;
+    }
+
+    public.ip() == other.ip()
+        || (public.ip().is_unspecified()
+            && (other.ip().is_loopback() || other.ip().is_unspecified()))
+        || (other.ip().is_unspecified() && public.ip().is_loopback())
+}
+
+/// Log the resolved server directory, warn if it doesn't exist.
+fn log_server_directory(config: &Config) {
+    match ConfigServer::server_directory(config) {
+        Some(dir) if dir.is_dir() => {
+            info!(target: "lazymc", "Using server directory: {}", dir.display())
+        }
+        Some(dir) => {
+            warn!(target: "lazymc", "Configured server directory doesn't exist: {}", dir.display())
+        }
+        None => {
+            debug!(target: "lazymc", "No server directory configured (server.directory)")
+        }
+    }
+}
+
+/// Warn about configured features that don't work without a server command.
+fn warn_unmanaged(config: &Config) {
+    info!(target: "lazymc", "No server command configured, lazymc won't start or stop the server");
+
+    if config.server.wake_on_start || config.server.probe_mode() == config::Probe::Always {
+        warn!(target: "lazymc", "Server command not configured, ignoring 'server.wake_on_start' and 'server.probe'");
+    }
+
+    if config.join.has_method(config::Method::Lobby)
+        || config.join.has_method(config::Method::HoldThenLobby)
+    {
+        warn!(target: "lazymc", "Server command not configured, lobby join method can't start server and will keep clients waiting until it is started elsewhere");
+    }
+}
+
+/// Warn if the server runs in online mode while a lobby join method is used.
+///
+/// The lobby connects to the server on behalf of the client, which is impossible in online mode.
+/// The server then authenticates the connection with Mojang using the session of the client,
+/// which only the client itself has.
+fn warn_lobby_online_mode(config: &Config) {
+    let Some(dir) = ConfigServer::server_directory(config) else {
+        return;
+    };
+    let online_mode =
+        server_properties::read_property(dir.join(server_properties::FILE), "online-mode")
+            .map(|v| v.trim() == "true");
+
+    if online_mode == Some(true) {
+        warn!(target: "lazymc", "Server has 'online-mode' enabled in {}, lobby join methods won't be able to connect clients to it", server_properties::FILE);
+        warn!(target: "lazymc", "Set 'online-mode=false' and use an authenticating proxy such as Velocity in front of lazymc, or don't use the lobby");
+    }
+}
+
+/// Load the compression threshold from the server `server.properties` file.
+///
+/// Falls back to the Minecraft default if not configured.
+fn load_compression_threshold(config: &Config) {
+    let Some(dir) = ConfigServer::server_directory(config) else {
+        return;
+    };
+    let file = dir.join(server_properties::FILE);
+    if !file.is_file() {
+        return;
+    }
+    let property = server_properties::read_property(file, "network-compression-threshold");
+
+    match property.as_deref().map(str::trim).map(str::parse::<i32>) {
+        Some(Ok(threshold)) => {
+            debug!(target: "lazymc", "Using compression threshold of {} from {}", threshold, server_properties::FILE);
+            proto::set_compression_threshold(threshold);
+        }
+        Some(Err(_)) => {
+            warn!(target: "lazymc", "Invalid 'network-compression-threshold' in {}, assuming default of {}", server_properties::FILE, proto::DEFAULT_COMPRESSION_THRESHOLD);
+        }
+        None => {}
+    }
+}
+
+/// Check player info forwarding configuration, quit if it can't be used.
+fn check_forwarding(config: &Config) {
+    if !cfg!(feature = "forwarding") {
+        warn!(target: "lazymc", "Player info forwarding is configured, but not supported in this lazymc build, ignoring 'server.forwarding'");
+        return;
+    }
+
+    if config.server.forwarding == config::Forwarding::Velocity
+        && config.server.forwarding_secret.is_empty()
+    {
+        quit_error_msg(
+            "Velocity forwarding requires a forwarding secret",
+            ErrorHintsBuilder::default()
+                .add_info("set 'server.forwarding_secret' in the config file, it must match the secret configured on the server".into())
+                .build()
+                .unwrap(),
+        );
+    }
+}
+
+/// Quit if features are used that require a mod handshake in the login phase.
+///
+/// NeoForge on Minecraft 1.20.2 and above negotiates mods in the configuration phase, which isn't
+/// supported. The lobby can't replay it, and clients can't be detected as modded.
+fn check_configuration_handshake(config: &Config) {
+    if config.join.uses_lobby() {
+        quit_error_msg(
+            "Lobby join method doesn't support NeoForge on Minecraft 1.20.2 and above",
+            ErrorHintsBuilder::default()
+                .add_info("NeoForge negotiates mods in the configuration phase, which lazymc can't replay".into())
+                .add_info("remove 'lobby' from 'join.methods' in the config file".into())
+                .build()
+                .unwrap(),
+        );
+    }
+
+    if !config.server.wake_brands.is_empty() {
+        quit_error_msg(
+            "Client brands can't be detected with NeoForge on Minecraft 1.20.2 and above",
+            ErrorHintsBuilder::default()
+                .add_info("NeoForge clients are always detected as 'vanilla'".into())
+                .add_info("remove 'server.wake_brands' from the config file".into())
+                .build()
+                .unwrap(),
+        );
+    }
+}
+
 /// Prepare RCON.
 #[cfg(feature = "rcon")]
 fn prepare_rcon(config: &mut Config) {
     use crate::util::error::{quit_error_msg, ErrorHintsBuilder};
 
-    // On Windows, this must be enabled
-    if cfg!(windows) && !config.rcon.enabled {
+    // On Windows, this must be enabled unless stopping through console input
+    if cfg!(windows) && !config.rcon.enabled && config.server.stop_command.is_none() {
         quit_error_msg(
             "RCON must be enabled on Windows",
             ErrorHintsBuilder::default()
                 .add_info("change 'rcon.enabled' to 'true' in the config file".into())
+                .add_info("or set 'server.stop_command' to stop through console input".into())
                 .build()
                 .unwrap(),
         );
@@ -52,7 +276,7 @@ fn prepare_rcon(config: &mut Config) {
     }
 
     // Must configure RCON password with no randomization
-    if config.server.address.port() == config.rcon.port {
+    if config.server.address().port() == config.rcon.port {
         quit_error_msg(
             "RCON port cannot be the same as the server",
             ErrorHintsBuilder::default()
@@ -131,25 +355,17 @@ fn rewrite_server_properties(config: &Config) {
     // Build list of changes
     #[allow(unused_mut)]
     let mut changes = HashMap::from([
-        ("server-ip", config.server.address.ip().to_string()),
-        ("server-port", config.server.address.port().to_string()),
+        ("server-ip", config.server.address().ip().to_string()),
+        ("server-port", config.server.address().port().to_string()),
         ("enable-status", "true".into()),
-        ("query.port", config.server.address.port().to_string()),
+        ("query.port", config.server.address().port().to_string()),
     ]);
 
     // If connecting to server over non-loopback address, disable proxy blocking
-    if !config.server.address.ip().is_loopback() {
+    if !config.server.address().ip().is_loopback() {
         changes.extend([("prevent-proxy-connections", "false".into())]);
     }
 
-    // Update network compression threshold for lobby mode
-    if config.join.methods.contains(&config::Method::Lobby) {
-        changes.extend([(
-            "network-compression-threshold",
-            proto::COMPRESSION_THRESHOLD.to_string(),
-        )]);
-    }
-
     // Add RCON configuration
     #[cfg(feature = "rcon")]
     if config.rcon.enabled {
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::mc::rcon::Rcon;
+
+/// Interval to poll player activity at through RCON.
+///
+/// The Minecraft RCON implementation is very broken and brittle, don't poll it too often.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Player activity tracker.
+///
+/// Tracks player positions to detect players that are AFK.
+#[derive(Debug, Default)]
+pub struct Activity {
+    /// Last known player position, and the time it last changed, by player name.
+    players: HashMap<String, (String, Instant)>,
+
+    /// Number of online players at the last successful poll.
+    online: u32,
+
+    /// Last time player activity was polled.
+    last_poll: Option<Instant>,
+}
+
+impl Activity {
+    /// Check whether activity should be polled now.
+    ///
+    /// If `true` is returned, this is remembered as the last poll time.
+    pub fn try_poll(&mut self) -> bool {
+        let poll = self
+            .last_poll
+            .map(|t| t.elapsed() >= POLL_INTERVAL)
+            .unwrap_or(true);
+        if poll {
+            self.last_poll.replace(Instant::now());
+        }
+        poll
+    }
+
+    /// Update tracked players with their current positions.
+    ///
+    /// Players not in the given list are forgotten.
+    pub fn update(&mut self, positions: Positions) {
+        let now = Instant::now();
+        let mut players = HashMap::with_capacity(positions.players.len());
+
+        for (name, pos) in positions.players {
+            let since = match self.players.remove(&name) {
+                Some((last_pos, since)) if last_pos == pos => since,
+                _ => now,
+            };
+            players.insert(name, (pos, since));
+        }
+
+        self.players = players;
+        self.online = positions.online;
+    }
+
+    /// Forget tracked player positions, keeping the last poll time.
+    ///
+    /// Used when a poll failed or was skipped, so stale positions don't age into AFK.
+    pub fn invalidate(&mut self) {
+        self.players.clear();
+        self.online = 0;
+    }
+
+    /// Forget all tracked players.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Check whether all online players are AFK, not having moved for the given duration.
+    ///
+    /// Returns `false` if no players are online, or if not every online player is tracked.
+    pub fn all_afk(&self, afk_after: Duration) -> bool {
+        self.online > 0
+            && self.players.len() == self.online as usize
+            && self
+                .players
+                .values()
+                .all(|(_, since)| since.elapsed() >= afk_after)
+    }
+}
+
+/// Player positions polled through RCON.
+#[derive(Debug, Default)]
+pub struct Positions {
+    /// Number of online players, as reported by the server.
+    pub online: u32,
+
+    /// Player names with their position.
+    ///
+    /// Players whose position couldn't be determined are not listed.
+    pub players: Vec<(String, String)>,
+}
+
+/// Poll the position of all online players through RCON.
+pub async fn poll_positions(config: &Config) -> Result<Positions, Box<dyn Error>> {
+    let mut rcon = Rcon::connect_config(config).await?;
+
+    // Get position for each online player
+    let list = rcon.list_players().await?;
+    let mut players = Vec::with_capacity(list.names.len());
+    for name in list.names {
+        if let Some(pos) = rcon.entity_pos(&name).await? {
+            players.push((name, pos));
+        }
+    }
+
+    // Gracefully close connection
+    rcon.close().await;
+
+    Ok(Positions {
+        online: list.online,
+        players,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(online: u32, names: &[&str]) -> Positions {
+        Positions {
+            online,
+            players: names
+                .iter()
+                .map(|name| (name.to_string(), "0 64 0".into()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn all_afk_when_all_online_tracked() {
+        let mut activity = Activity::default();
+        activity.update(positions(2, &["a", "b"]));
+        assert!(activity.all_afk(Duration::ZERO));
+        assert!(!activity.all_afk(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn not_afk_with_untracked_players() {
+        let mut activity = Activity::default();
+        activity.update(positions(3, &["a", "b"]));
+        assert!(!activity.all_afk(Duration::ZERO));
+    }
+
+    #[test]
+    fn not_afk_without_players() {
+        let mut activity = Activity::default();
+        assert!(!activity.all_afk(Duration::ZERO));
+        activity.update(positions(0, &[]));
+        assert!(!activity.all_afk(Duration::ZERO));
+    }
+
+    #[test]
+    fn not_afk_after_invalidate() {
+        let mut activity = Activity::default();
+        activity.update(positions(1, &["a"]));
+        activity.invalidate();
+        assert!(!activity.all_afk(Duration::ZERO));
+    }
+}
@@ -2,7 +2,8 @@ use clap::{Arg, Command};
 
 /// The clap app for CLI argument parsing.
 pub fn app() -> Command {
-    Command::new(crate_name!())
+    #[allow(unused_mut)]
+    let mut app = Command::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
@@ -22,7 +23,8 @@ pub fn app() -> Command {
                         .alias("gen")
                         .about("Generate config"),
                 )
-                .subcommand(Command::new("test").about("Test config")),
+                .subcommand(Command::new("test").about("Test config"))
+                .subcommand(Command::new("schema").about("Print config JSON schema")),
         )
         .arg(
             Arg::new("config")
@@ -34,5 +36,15 @@ pub fn app() -> Command {
                 .default_value(crate::config::CONFIG_FILE)
                 .help("Use config file")
                 .num_args(1),
-        )
+        );
+
+    #[cfg(feature = "dev")]
+    {
+        app = app.subcommand(
+            Command::new("lobby-test")
+                .about("Test lobby flow with a synthetic client, lazymc must be running"),
+        );
+    }
+
+    app
 }
@@ -1,15 +1,24 @@
+use std::cmp::Ordering;
 use std::fs;
 use std::io;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 
+use chrono::NaiveTime;
 use clap::ArgMatches;
-use serde::Deserialize;
+use ipnetwork::IpNetwork;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use version_compare::Cmp;
 
+use crate::net;
 use crate::proto;
 use crate::util::error::{quit_error, quit_error_msg, ErrorHintsBuilder};
-use crate::util::serde::to_socket_addrs;
+use crate::util::serde::{
+    to_client_brands, to_regex, to_regex_option, to_socket_addrs, to_socket_addrs_list,
+    to_time_option, to_time_ranges, to_times,
+};
 
 /// Default configuration file location.
 pub const CONFIG_FILE: &str = "lazymc.toml";
@@ -61,7 +70,8 @@ pub fn load(matches: &ArgMatches) -> Config {
 }
 
 /// Configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Config {
     /// Configuration path if known.
     ///
@@ -92,10 +102,26 @@ pub struct Config {
     #[serde(default)]
     pub lockout: Lockout,
 
+    /// Access configuration.
+    #[serde(default)]
+    pub access: Access,
+
     /// RCON configuration.
     #[serde(default)]
     pub rcon: Rcon,
 
+    /// API configuration.
+    #[serde(default)]
+    pub api: Api,
+
+    /// Webhook configuration.
+    #[serde(default)]
+    pub webhook: Webhook,
+
+    /// Hook commands configuration.
+    #[serde(default)]
+    pub hooks: Hooks,
+
     /// Advanced configuration.
     #[serde(default)]
     pub advanced: Advanced,
@@ -131,8 +157,9 @@ impl Config {
 }
 
 /// Public configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct Public {
     /// Public address.
     #[serde(deserialize_with = "to_socket_addrs")]
@@ -143,6 +170,42 @@ pub struct Public {
 
     /// Minecraft protocol version hint.
     pub protocol: u32,
+
+    /// Hostnames clients may connect with, any if empty.
+    pub hosts: Vec<String>,
+
+    /// What to do with clients connecting with a hostname not listed in `hosts`.
+    pub unknown_host: UnknownHost,
+
+    /// Accept PROXY headers from trusted proxies, to know the real client address.
+    pub accept_proxy_header: bool,
+
+    /// Proxies trusted to send PROXY headers.
+    #[schemars(with = "Vec<String>")]
+    pub trusted_proxies: Vec<IpNetwork>,
+}
+
+impl Public {
+    /// Whether the given handshake server address is a served host.
+    ///
+    /// Ignores case, a trailing dot and data appended by Forge or proxies.
+    pub fn serves_host(&self, server_addr: &str) -> bool {
+        if self.hosts.is_empty() {
+            return true;
+        }
+
+        let host = server_addr.split('\0').next().unwrap_or_default();
+        let host = host.trim_end_matches('.');
+        self.hosts.iter().any(|h| h.eq_ignore_ascii_case(host))
+    }
+
+    /// Whether the given IP is a proxy trusted to send PROXY headers.
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        let ip = net::canonical_ip(ip);
+        self.trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    }
 }
 
 impl Default for Public {
@@ -151,13 +214,37 @@ impl Default for Public {
             address: "0.0.0.0:25565".parse().unwrap(),
             version: proto::PROTO_DEFAULT_VERSION.to_string(),
             protocol: proto::PROTO_DEFAULT_PROTOCOL,
+            hosts: vec![],
+            unknown_host: UnknownHost::default(),
+            accept_proxy_header: false,
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap(), "::1/128".parse().unwrap()],
         }
     }
 }
 
+/// Unknown host actions.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownHost {
+    /// Drop connection without response.
+    Drop,
+
+    /// Handle client like any other.
+    #[default]
+    Default,
+
+    /// Respond with unknown host status, kick with message on login.
+    Status,
+}
+
 /// Server configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Server {
+    /// Server name, included in log targets to tell servers apart.
+    #[serde(default)]
+    pub name: Option<String>,
+
     /// Server directory.
     ///
     /// Private because you should use `Server::server_directory()` instead.
@@ -165,17 +252,31 @@ pub struct Server {
     directory: Option<PathBuf>,
 
     /// Start command.
-    pub command: String,
+    ///
+    /// If not set, lazymc does not manage the server process. It won't start, stop or probe the
+    /// server, and only reflects the state of a server managed elsewhere.
+    #[serde(default)]
+    pub command: Option<String>,
 
-    /// Server address.
+    /// Command to write to the server console input to stop it, if not stopped through RCON.
+    #[serde(default)]
+    pub stop_command: Option<String>,
+
+    /// Server addresses.
+    ///
+    /// The first is the server started and monitored by lazymc, others are fallbacks connected to
+    /// in order if the first is unreachable. Fallbacks never affect the server state.
+    ///
     #[serde(
-        deserialize_with = "to_socket_addrs",
+        rename = "address",
+        deserialize_with = "to_socket_addrs_list",
         default = "server_address_default"
     )]
-    pub address: SocketAddr,
+    #[schemars(with = "OneOrMany<SocketAddr>")]
+    pub addresses: Vec<SocketAddr>,
 
     /// Freeze the server process instead of restarting it when no players online, making it start up faster.
-    /// Only works on Unix (Linux or MacOS)
+    /// Only works on Unix (Linux or MacOS) and Windows
     #[serde(default = "bool_true")]
     pub freeze_process: bool,
 
@@ -187,14 +288,61 @@ pub struct Server {
     #[serde(default)]
     pub wake_on_crash: bool,
 
+    /// Seconds to wait before restarting a crashed server, doubled on each consecutive crash.
+    #[serde(default = "u32_5")]
+    pub crash_backoff: u32,
+
+    /// Maximum number of consecutive crash restarts, 0 for unlimited.
+    ///
+    /// The count is reset once the server stayed up for a while.
+    #[serde(default = "u32_5")]
+    pub max_crash_restarts: u32,
+
+    /// Server process exit codes that are considered a clean stop rather than a crash.
+    ///
+    /// - 143: https://github.com/timvisee/lazymc/issues/26#issuecomment-1435670029
+    /// - 130: https://unix.stackexchange.com/q/386836/61092
+    #[serde(default = "allowed_exit_codes_default")]
+    pub allowed_exit_codes: Vec<i32>,
+
     /// Probe required server details when starting lazymc, wakes server on start.
+    ///
+    /// Same as `probe = "always"`, kept for compatibility.
     #[serde(default)]
     pub probe_on_start: bool,
 
+    /// When to probe server details.
+    #[serde(default)]
+    pub probe: Probe,
+
+    /// Maximum time in seconds the probe waits for the server to come online.
+    #[serde(default = "u32_600")]
+    pub probe_timeout: u32,
+
+    /// Number of times to retry probing after it failed.
+    #[serde(default = "u32_2")]
+    pub probe_retries: u32,
+
+    /// Username the probe logs in to the server with.
+    #[serde(default = "probe_username_default")]
+    pub probe_username: String,
+
+    /// Re-probe server if its protocol version changed since it was probed.
+    #[serde(default = "bool_true")]
+    pub reprobe_on_version_change: bool,
+
     /// Whether this server runs forge.
+    ///
+    /// Same as `mod_loader = "forge"`, kept for compatibility.
     #[serde(default)]
     pub forge: bool,
 
+    /// Mod loader this server runs.
+    ///
+    /// Private because you should use `Server::mod_loader()` instead.
+    #[serde(default)]
+    mod_loader: ModLoader,
+
     /// Server starting timeout. Force kill server process if it takes longer.
     #[serde(default = "u32_300")]
     pub start_timeout: u32,
@@ -203,10 +351,67 @@ pub struct Server {
     #[serde(default = "u32_150")]
     pub stop_timeout: u32,
 
+    /// Force kill server process if start or stop timeout is reached.
+    ///
+    /// If disabled, a warning is logged and the timeout is extended instead.
+    #[serde(default = "bool_true")]
+    pub force_kill_on_timeout: bool,
+
+    /// Log start diagnostics if the server isn't reachable after this number of seconds while
+    /// starting, 0 to disable.
+    ///
+    /// Helps to tell a slow start apart from a stuck start command.
+    #[serde(default = "u32_60")]
+    pub start_watchdog: u32,
+
+    /// Pattern (regular expression) matching the server console line printed once it is ready.
+    ///
+    /// If set, the server is marked as started once matched, instead of once it responds to
+    /// status requests. Validated when the config is loaded.
+    #[serde(default, deserialize_with = "to_regex_option")]
+    #[schemars(with = "Option<String>")]
+    pub ready_regex: Option<Regex>,
+
+    /// Time in seconds after starting the server process to wait for `ready_regex` to match.
+    ///
+    /// Once passed, the server is marked as started once it responds to status requests.
+    #[serde(default = "u32_180")]
+    pub ready_regex_timeout: u32,
+
+    /// Minimum available system memory in megabytes required to start the server, 0 to disable.
+    #[serde(default)]
+    pub min_free_memory_mb: u64,
+
+    /// Priority of the server process as nice value, from -20 (highest) to 19 (lowest).
+    ///
+    /// On Windows this maps to the closest process priority class.
+    #[serde(default)]
+    pub nice: Option<i32>,
+
+    /// CPU cores to bind the server process to, any core if empty.
+    #[serde(default)]
+    pub cpu_affinity: Vec<usize>,
+
+    /// Maximum number of concurrent connections lazymc opens to the server, unlimited if not set.
+    #[serde(default)]
+    pub max_connections: Option<usize>,
+
     /// To wake server, user must be in server whitelist if enabled on server.
     #[serde(default = "bool_true")]
     pub wake_whitelist: bool,
 
+    /// Client brands allowed to wake the server, any if empty.
+    #[serde(default, deserialize_with = "to_client_brands")]
+    pub wake_brands: Vec<String>,
+
+    /// Allow each IP to wake the server once per number of seconds, 0 to disable.
+    #[serde(default)]
+    pub wake_throttle: u32,
+
+    /// Wake server when a client requests the server status, not only on login.
+    #[serde(default)]
+    pub wake_on_status_ping: bool,
+
     /// Block banned IPs as listed in banned-ips.json in server directory.
     #[serde(default = "bool_true")]
     pub block_banned_ips: bool,
@@ -215,27 +420,106 @@ pub struct Server {
     #[serde(default)]
     pub drop_banned_ips: bool,
 
-    /// Add HAProxy v2 header to proxied connections.
+    /// Kick banned players as listed in banned-players.json in server directory.
+    #[serde(default = "bool_true")]
+    pub block_banned_players: bool,
+
+    /// Add HAProxy header to proxied connections.
+    #[serde(default)]
+    send_proxy: SendProxy,
+
+    /// Add HAProxy v2 header to proxied connections, same as `send_proxy = "v2"`.
+    #[serde(default)]
+    send_proxy_v2: bool,
+
+    /// Forward player IP and UUID to the server, for servers behind a BungeeCord or Velocity
+    /// proxy network.
+    #[serde(default)]
+    pub forwarding: Forwarding,
+
+    /// Secret shared with the server for Velocity modern forwarding.
     #[serde(default)]
-    pub send_proxy_v2: bool,
+    pub forwarding_secret: String,
 }
 
 impl Server {
+    /// Whether clients with the given brand may wake the server, see `wake_brands`.
+    pub fn may_wake_brand(&self, brand: &str) -> bool {
+        self.wake_brands.is_empty() || self.wake_brands.iter().any(|b| b == brand)
+    }
+
+    /// Get the primary server address.
+    ///
+    /// This is the address of the server started by lazymc.
+    pub fn address(&self) -> SocketAddr {
+        self.addresses[0]
+    }
+
+    /// Get the probe mode, `probe_on_start` enables probing.
+    pub fn probe_mode(&self) -> Probe {
+        if self.probe_on_start {
+            Probe::Always
+        } else {
+            self.probe
+        }
+    }
+
+    /// Get the mod loader this server runs, `forge` selects Forge.
+    pub fn mod_loader(&self) -> ModLoader {
+        match self.mod_loader {
+            ModLoader::Vanilla if self.forge => ModLoader::Forge,
+            loader => loader,
+        }
+    }
+
+    /// Get the HAProxy header version to send, `send_proxy_v2` selects v2.
+    pub fn send_proxy(&self) -> SendProxy {
+        self.send_proxy.or_v2(self.send_proxy_v2)
+    }
+
+    /// Whether this server runs a mod loader that requires a mod handshake.
+    pub fn is_modded(&self) -> bool {
+        self.mod_loader() != ModLoader::Vanilla
+    }
+
+    /// Get log target for the given base target, tagged with the server name if set.
+    ///
+    /// For example: `lazymc::monitor[survival]`.
+    pub fn log_target(&self, base: &str) -> String {
+        match &self.name {
+            Some(name) => format!("{}[{}]", base, name),
+            None => base.into(),
+        }
+    }
+
+    /// Whether lazymc manages the server process.
+    ///
+    /// This is `false` if no start command is configured.
+    pub fn is_managed(&self) -> bool {
+        self.command.is_some()
+    }
+
     /// Get the server directory.
     ///
+    /// Relative to the config file directory if known. Made absolute if it exists, so all users
+    /// agree on the same directory regardless of the current working directory.
+    ///
     /// This does not check whether it exists.
     pub fn server_directory(config: &Config) -> Option<PathBuf> {
         // Get directory, relative to config directory if known
-        match config.path.as_ref().and_then(|p| p.parent()) {
-            Some(config_dir) => Some(config_dir.join(config.server.directory.as_ref()?)),
-            None => config.server.directory.clone(),
-        }
+        let dir = match config.path.as_ref().and_then(|p| p.parent()) {
+            Some(config_dir) => config_dir.join(config.server.directory.as_ref()?),
+            None => config.server.directory.clone()?,
+        };
+
+        Some(dir.canonicalize().unwrap_or(dir))
     }
 }
 
 /// Time configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct Time {
     /// Sleep after number of seconds.
     pub sleep_after: u32,
@@ -243,6 +527,66 @@ pub struct Time {
     /// Minimum time in seconds to stay online when server is started.
     #[serde(default, alias = "minimum_online_time")]
     pub min_online_time: u32,
+
+    /// Sleep after number of seconds if started without a player, and no player joined since.
+    ///
+    /// Overrides `sleep_after` and `min_online_time` in that case.
+    pub sleep_after_boot: Option<u32>,
+
+    /// Allow server to sleep when all online players are AFK, detected through RCON.
+    pub afk_sleep: bool,
+
+    /// Number of seconds a player must not move to be considered AFK.
+    pub afk_after: u32,
+
+    /// Never sleep while any connection is proxied to the server.
+    pub keep_online_while_connected: bool,
+
+    /// Minimum time in seconds between the server process quitting and starting it again.
+    pub restart_cooldown: u32,
+
+    /// Times of day in local time to start the server at.
+    #[serde(deserialize_with = "to_times")]
+    #[schemars(with = "Vec<String>")]
+    pub prestart_schedule: Vec<NaiveTime>,
+
+    /// Keep server online for number of seconds after a scheduled start.
+    pub prestart_keep_online: u32,
+
+    /// Time of day in local time to restart the server at daily, if running.
+    #[serde(deserialize_with = "to_time_option")]
+    #[schemars(with = "Option<String>")]
+    pub daily_restart: Option<NaiveTime>,
+
+    /// Number of seconds to wait for players to leave before a daily restart.
+    pub daily_restart_drain: u32,
+
+    /// Message sent to online players before a daily restart.
+    pub daily_restart_message: String,
+
+    /// Daily time ranges in local time to start the server at and keep it awake during.
+    #[serde(deserialize_with = "to_time_ranges")]
+    #[schemars(with = "Vec<String>")]
+    pub wake_schedule: Vec<TimeRange>,
+
+    /// Daily time ranges in local time to not let the server sleep during.
+    #[serde(deserialize_with = "to_time_ranges")]
+    #[schemars(with = "Vec<String>")]
+    pub sleep_blocked_schedule: Vec<TimeRange>,
+
+    /// Only sleep once the server process CPU usage in percent stayed below this for a while.
+    pub sleep_when_idle_cpu_below: Option<f32>,
+}
+
+impl Time {
+    /// Whether the server must not sleep at the given time of day, because of the wake or sleep
+    /// blocked schedule.
+    pub fn is_sleep_blocked(&self, time: NaiveTime) -> bool {
+        self.wake_schedule
+            .iter()
+            .chain(&self.sleep_blocked_schedule)
+            .any(|range| range.contains(time))
+    }
 }
 
 impl Default for Time {
@@ -250,13 +594,50 @@ impl Default for Time {
         Self {
             sleep_after: 60,
             min_online_time: 60,
+            sleep_after_boot: None,
+            afk_sleep: false,
+            afk_after: 5 * 60,
+            keep_online_while_connected: false,
+            restart_cooldown: 3,
+            prestart_schedule: vec![],
+            prestart_keep_online: 30 * 60,
+            daily_restart: None,
+            daily_restart_drain: 5 * 60,
+            daily_restart_message: "Server restarts in {seconds} seconds for maintenance.".into(),
+            wake_schedule: vec![],
+            sleep_blocked_schedule: vec![],
+            sleep_when_idle_cpu_below: None,
+        }
+    }
+}
+
+/// Daily time range, may wrap around midnight.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TimeRange {
+    /// Start time of day, inclusive.
+    pub start: NaiveTime,
+
+    /// End time of day, exclusive.
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    /// Whether the given time of day is within this range.
+    ///
+    /// A range with equal start and end covers the whole day.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        match self.start.cmp(&self.end) {
+            Ordering::Less => self.start <= time && time < self.end,
+            Ordering::Greater => time >= self.start || time < self.end,
+            Ordering::Equal => true,
         }
     }
 }
 
 /// MOTD configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct Motd {
     /// MOTD when server is sleeping.
     pub sleeping: String,
@@ -267,8 +648,40 @@ pub struct Motd {
     /// MOTD when server is stopping.
     pub stopping: String,
 
+    /// MOTD when server is sleeping or starting, and hasn't been online since lazymc started.
+    pub cold_start: Option<String>,
+
+    /// Version name shown in server list, keeps the protocol version.
+    pub version_name: Option<String>,
+
+    /// Server icon when server is sleeping, relative to server directory.
+    pub icon_sleeping: Option<PathBuf>,
+
+    /// Server icon when server is starting, relative to server directory.
+    pub icon_starting: Option<PathBuf>,
+
     /// Use MOTD from Minecraft server once known.
     pub from_server: bool,
+
+    /// Line shown in the player list hover when server is sleeping.
+    pub sleeping_sample: Option<String>,
+
+    /// Patterns matching server start progress in the server console output.
+    ///
+    /// Used for the `{progress}` placeholder in the starting MOTD.
+    pub progress_patterns: Vec<String>,
+}
+
+impl Motd {
+    /// Whether the start progress placeholder is used.
+    pub fn uses_progress(&self) -> bool {
+        self.starting.contains("{progress}")
+            || self
+                .cold_start
+                .as_ref()
+                .map(|motd| motd.contains("{progress}"))
+                .unwrap_or(false)
+    }
 }
 
 impl Default for Motd {
@@ -277,13 +690,112 @@ impl Default for Motd {
             sleeping: "☠ Server is sleeping\n§2☻ Join to start it up".into(),
             starting: "§2☻ Server is starting...\n§7⌛ Please wait...".into(),
             stopping: "☠ Server going to sleep...\n⌛ Please wait...".into(),
+            cold_start: None,
+            version_name: None,
+            icon_sleeping: None,
+            icon_starting: None,
             from_server: false,
+            sleeping_sample: None,
+            progress_patterns: vec![
+                r"Preparing spawn area: \d+%".into(),
+                r#"Preparing level "[^"]*""#.into(),
+                r"Loading \d+ mods".into(),
+            ],
         }
     }
 }
 
+/// Server probe modes.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Probe {
+    /// Only probe when required by other features, such as the lobby with Forge.
+    #[default]
+    Auto,
+
+    /// Always probe on start.
+    Always,
+
+    /// Never probe, the lobby uses the bundled dimension codec.
+    Never,
+}
+
+/// Server mod loaders.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModLoader {
+    /// No mod loader.
+    #[default]
+    Vanilla,
+
+    /// Forge.
+    Forge,
+
+    /// NeoForge.
+    NeoForge,
+}
+
+/// HAProxy header versions to send.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SendProxy {
+    /// Do not send a HAProxy header.
+    #[default]
+    None,
+
+    /// Send a HAProxy v1 (text) header.
+    V1,
+
+    /// Send a HAProxy v2 (binary) header.
+    V2,
+}
+
+impl SendProxy {
+    /// Select v2 if `v2` is set and no version is selected.
+    fn or_v2(self, v2: bool) -> Self {
+        match self {
+            SendProxy::None if v2 => SendProxy::V2,
+            send_proxy => send_proxy,
+        }
+    }
+
+    /// Get the proxy protocol version to use, `None` if no header should be sent.
+    pub fn version(self) -> Option<ProxyVersion> {
+        match self {
+            SendProxy::None => None,
+            SendProxy::V1 => Some(ProxyVersion::V1),
+            SendProxy::V2 => Some(ProxyVersion::V2),
+        }
+    }
+}
+
+/// Proxy protocol version.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProxyVersion {
+    /// Human-readable text header.
+    V1,
+
+    /// Binary header.
+    V2,
+}
+
+/// Player info forwarding modes.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Forwarding {
+    /// Do not forward player info.
+    #[default]
+    None,
+
+    /// BungeeCord legacy IP forwarding, player info is appended to the handshake.
+    Bungeecord,
+
+    /// Velocity modern forwarding, player info is sent in a signed login plugin response.
+    Velocity,
+}
+
 /// Join method types.
-#[derive(Debug, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Method {
     /// Kick client with message.
@@ -297,14 +809,96 @@ pub enum Method {
 
     /// Keep client in temporary fake lobby until server is ready.
     Lobby,
+
+    /// Keep client in temporary fake lobby showing its queue position while server is full.
+    Queue,
+
+    /// Hold client connection for a short time, then move it into the lobby if server is not
+    /// ready yet.
+    #[serde(rename = "hold_then_lobby")]
+    HoldThenLobby,
+}
+
+/// Join method entry.
+///
+/// Either a plain join method, or a table with the method type and inline options.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum JoinMethod {
+    /// Plain join method, uses options from its own section.
+    Simple(Method),
+
+    /// Join method with inline options, overriding options from its own section.
+    Inline {
+        /// Join method type.
+        #[serde(rename = "type")]
+        method: Method,
+
+        /// Timeout in seconds, for the hold, lobby, queue and hold then lobby methods.
+        #[serde(default)]
+        timeout: Option<u32>,
+    },
+}
+
+impl JoinMethod {
+    /// Get the join method type.
+    pub fn method(&self) -> Method {
+        match self {
+            Self::Simple(method) | Self::Inline { method, .. } => *method,
+        }
+    }
+
+    /// Get the inline timeout, if set.
+    pub fn timeout(&self) -> Option<u32> {
+        match self {
+            Self::Simple(_) => None,
+            Self::Inline { timeout, .. } => *timeout,
+        }
+    }
+}
+
+impl From<Method> for JoinMethod {
+    fn from(method: Method) -> Self {
+        Self::Simple(method)
+    }
+}
+
+/// Action for joining clients with an invalid username.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UsernameCheck {
+    /// Don't check usernames.
+    Off,
+
+    /// Log invalid usernames, allow client to continue.
+    #[default]
+    Log,
+
+    /// Kick client with message.
+    Kick,
+
+    /// Drop connection without message.
+    Drop,
 }
 
 /// Join configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct Join {
     /// Join methods.
-    pub methods: Vec<Method>,
+    pub methods: Vec<JoinMethod>,
+
+    /// What to do with joining clients that have an invalid username.
+    pub username_check: UsernameCheck,
+
+    /// Regular expression valid usernames must match, validated when the config is loaded.
+    #[serde(deserialize_with = "to_regex")]
+    #[schemars(with = "String")]
+    pub username_pattern: Regex,
+
+    /// Only allow the client that woke the server to join while it is starting.
+    pub exclusive_start: bool,
 
     /// Join kick configuration.
     #[serde(default)]
@@ -318,32 +912,68 @@ pub struct Join {
     #[serde(default)]
     pub forward: JoinForward,
 
+    /// Join hold then lobby configuration.
+    #[serde(default)]
+    pub hold_then_lobby: JoinHoldThenLobby,
+
     /// Join lobby configuration.
     #[serde(default)]
     pub lobby: JoinLobby,
+
+    /// Join queue configuration.
+    #[serde(default)]
+    pub queue: JoinQueue,
+
+    /// Join fallback configuration.
+    #[serde(default)]
+    pub fallback: JoinFallback,
+}
+
+impl Join {
+    /// Whether the given join method is used.
+    pub fn has_method(&self, method: Method) -> bool {
+        self.methods.iter().any(|m| m.method() == method)
+    }
+
+    /// Whether a join method using the lobby world is used.
+    pub fn uses_lobby(&self) -> bool {
+        self.has_method(Method::Lobby)
+            || self.has_method(Method::Queue)
+            || self.has_method(Method::HoldThenLobby)
+    }
 }
 
 impl Default for Join {
     fn default() -> Self {
         Self {
-            methods: vec![Method::Hold, Method::Kick],
+            methods: vec![Method::Hold.into(), Method::Kick.into()],
+            username_check: UsernameCheck::default(),
+            username_pattern: Regex::new("^[a-zA-Z0-9_]{1,16}$").unwrap(),
+            exclusive_start: false,
             kick: Default::default(),
             hold: Default::default(),
             forward: Default::default(),
+            hold_then_lobby: Default::default(),
             lobby: Default::default(),
+            queue: Default::default(),
+            fallback: Default::default(),
         }
     }
 }
 
 /// Join kick configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct JoinKick {
     /// Kick message when server is starting.
     pub starting: String,
 
     /// Kick message when server is stopping.
     pub stopping: String,
+
+    /// Kick message when server is starting, and hasn't been online since lazymc started.
+    pub cold_start: Option<String>,
 }
 
 impl Default for JoinKick {
@@ -351,72 +981,295 @@ impl Default for JoinKick {
         Self {
             starting: "Server is starting... §c♥§r\n\nThis may take some time.\n\nPlease try to reconnect in a minute.".into(),
             stopping: "Server is going to sleep... §7☠§r\n\nPlease try to reconnect in a minute to wake it again.".into(),
+            cold_start: None,
         }
     }
 }
 
 /// Join hold configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct JoinHold {
     /// Hold client for number of seconds on connect while server starts.
     pub timeout: u32,
+
+    /// Chat message sent to held client once it joined the woken server.
+    pub wake_message: Option<String>,
+
+    /// Stop holding clients that disconnect while being held.
+    pub detect_disconnect: bool,
+
+    /// Send keep-alive requests to held clients, so they don't time out.
+    pub keep_alive: bool,
 }
 
 impl Default for JoinHold {
     fn default() -> Self {
-        Self { timeout: 25 }
+        Self {
+            timeout: 25,
+            wake_message: None,
+            detect_disconnect: true,
+            keep_alive: true,
+        }
+    }
+}
+
+/// Join hold then lobby configuration.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct JoinHoldThenLobby {
+    /// Hold client for number of seconds on connect before moving it into the lobby.
+    pub hold_timeout: u32,
+}
+
+impl Default for JoinHoldThenLobby {
+    fn default() -> Self {
+        Self { hold_timeout: 5 }
     }
 }
 
 /// Join forward configuration.
-#[derive(Debug, Deserialize)]
+// Unknown fields are allowed in the schema, to accept the `address` alias
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct JoinForward {
+    /// IPs and ports to forward to, the first reachable one is used.
+    #[serde(alias = "address", deserialize_with = "to_socket_addrs_list")]
+    #[schemars(with = "OneOrMany<SocketAddr>")]
+    pub addresses: Vec<SocketAddr>,
+
+    /// Add HAProxy header to proxied connections.
+    #[serde(default)]
+    send_proxy: SendProxy,
+
+    /// Add HAProxy v2 header to proxied connections, same as `send_proxy = "v2"`.
+    #[serde(default)]
+    send_proxy_v2: bool,
+}
+
+impl JoinForward {
+    /// Get the HAProxy header version to send, `send_proxy_v2` selects v2.
+    pub fn send_proxy(&self) -> SendProxy {
+        self.send_proxy.or_v2(self.send_proxy_v2)
+    }
+}
+
+impl Default for JoinForward {
+    fn default() -> Self {
+        Self {
+            addresses: vec!["127.0.0.1:25565".parse().unwrap()],
+            send_proxy: SendProxy::default(),
+            send_proxy_v2: false,
+        }
+    }
+}
+
+/// Join fallback actions.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FallbackAction {
+    /// Close connection without message.
+    #[default]
+    Disconnect,
+
+    /// Kick client with message.
+    Kick,
+
+    /// Forward connection to another host.
+    Forward,
+}
+
+/// Join fallback configuration.
+///
+/// Used when no join method occupied the client.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct JoinFallback {
+    /// Fallback action.
+    pub action: FallbackAction,
+
+    /// Kick message.
+    pub message: String,
+
     /// IP and port to forward to.
     #[serde(deserialize_with = "to_socket_addrs")]
     pub address: SocketAddr,
 
-    /// Add HAProxy v2 header to proxied connections.
-    #[serde(default)]
-    pub send_proxy_v2: bool,
+    /// Add HAProxy header to forwarded connections.
+    send_proxy: SendProxy,
+
+    /// Add HAProxy v2 header to forwarded connections, same as `send_proxy = "v2"`.
+    send_proxy_v2: bool,
 }
 
-impl Default for JoinForward {
+impl JoinFallback {
+    /// Get the HAProxy header version to send, `send_proxy_v2` selects v2.
+    pub fn send_proxy(&self) -> SendProxy {
+        self.send_proxy.or_v2(self.send_proxy_v2)
+    }
+}
+
+impl Default for JoinFallback {
     fn default() -> Self {
         Self {
+            action: FallbackAction::default(),
+            message: "Could not start the server. §c☹§r\n\nPlease try to reconnect in a minute."
+                .into(),
             address: "127.0.0.1:25565".parse().unwrap(),
+            send_proxy: SendProxy::default(),
             send_proxy_v2: false,
         }
     }
 }
+
 /// Join lobby configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct JoinLobby {
     /// Hold client in lobby for number of seconds on connect while server starts.
     pub timeout: u32,
 
+    /// Disconnect lobby clients that send nothing for number of seconds while waiting. 0 to disable.
+    pub idle_timeout: u32,
+
+    /// Maximum lobby session duration in seconds, including connecting to the server. 0 to disable.
+    pub max_duration: u32,
+
     /// Message banner in lobby shown to client.
     pub message: String,
 
+    /// Show message banner in the action bar instead of as title.
+    pub use_action_bar: bool,
+
     /// Sound effect to play when server is ready.
     pub ready_sound: Option<String>,
+
+    /// Show the server console as chat messages to OPs in the lobby while the server starts.
+    pub console_ops: bool,
+
+    /// Only use the bundled dimension codec for clients on the Minecraft version it is made for.
+    pub strict_codec: bool,
+
+    /// Handle chat commands from lobby clients, such as `!cancel` to leave.
+    pub chat_commands: bool,
+
+    /// Chat message sent to client when it enters the lobby.
+    pub welcome_message: Option<String>,
+
+    /// Chat message sent to client when the server is ready.
+    pub ready_message: Option<String>,
+
+    /// Game mode of client in lobby.
+    pub gamemode: LobbyGameMode,
+
+    /// Spawn position and look of client in lobby.
+    pub spawn: LobbySpawn,
 }
 
 impl Default for JoinLobby {
     fn default() -> Self {
         Self {
             timeout: 10 * 60,
+            idle_timeout: 0,
+            max_duration: 0,
             message: "§2Server is starting\n§7⌛ Please wait...".into(),
+            use_action_bar: false,
             ready_sound: Some("block.note_block.chime".into()),
+            console_ops: false,
+            strict_codec: true,
+            chat_commands: true,
+            welcome_message: None,
+            ready_message: None,
+            gamemode: LobbyGameMode::default(),
+            spawn: LobbySpawn::default(),
+        }
+    }
+}
+
+/// Lobby game modes.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LobbyGameMode {
+    /// Adventure mode, client can't break or place blocks.
+    Adventure,
+
+    /// Spectator mode, client can fly through everything.
+    #[default]
+    Spectator,
+}
+
+impl LobbyGameMode {
+    /// Get the game mode ID as used in the protocol.
+    pub fn id(self) -> u8 {
+        match self {
+            LobbyGameMode::Adventure => 2,
+            LobbyGameMode::Spectator => 3,
+        }
+    }
+}
+
+/// Lobby spawn position and look.
+#[derive(Debug, Deserialize, JsonSchema, Copy, Clone)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct LobbySpawn {
+    /// X coordinate.
+    pub x: f64,
+
+    /// Y coordinate.
+    pub y: f64,
+
+    /// Z coordinate.
+    pub z: f64,
+
+    /// Horizontal look angle in degrees.
+    pub yaw: f32,
+
+    /// Vertical look angle in degrees, 90 looks straight down.
+    pub pitch: f32,
+}
+
+impl Default for LobbySpawn {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            yaw: 0.0,
+            pitch: 90.0,
+        }
+    }
+}
+
+/// Join queue configuration.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct JoinQueue {
+    /// Maximum time in seconds in the queue, 0 to disable.
+    pub timeout: u32,
+
+    /// Message banner in queue shown to client, `{position}` is replaced with the queue position.
+    pub message: String,
+}
+
+impl Default for JoinQueue {
+    fn default() -> Self {
+        Self {
+            timeout: 0,
+            message: "§2Server is full\n§7You are #{position} in queue".into(),
         }
     }
 }
 
 /// Lockout configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct Lockout {
     /// Enable to prevent everybody from connecting through lazymc. Instantly kicks player.
     pub enabled: bool,
@@ -434,9 +1287,35 @@ impl Default for Lockout {
     }
 }
 
+/// Access configuration.
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct Access {
+    /// IP ranges allowed to connect, everybody if empty.
+    #[schemars(with = "Vec<String>")]
+    pub allow: Vec<IpNetwork>,
+
+    /// IP ranges denied to connect, takes precedence over `allow`.
+    #[schemars(with = "Vec<String>")]
+    pub deny: Vec<IpNetwork>,
+}
+
+impl Access {
+    /// Whether the given IP may connect.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let ip = net::canonical_ip(ip);
+        if self.deny.iter().any(|network| network.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|network| network.contains(ip))
+    }
+}
+
 /// RCON configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct Rcon {
     /// Enable sleeping server through RCON.
     pub enabled: bool,
@@ -450,8 +1329,30 @@ pub struct Rcon {
     /// Randomize server RCON password on each start.
     pub randomize_password: bool,
 
-    /// Add HAProxy v2 header to RCON connections.
-    pub send_proxy_v2: bool,
+    /// Add HAProxy header to RCON connections.
+    send_proxy: SendProxy,
+
+    /// Add HAProxy v2 header to RCON connections, same as `send_proxy = "v2"`.
+    send_proxy_v2: bool,
+
+    /// Delay in milliseconds after sending the HAProxy header, before the RCON handshake.
+    pub proxy_header_delay: u32,
+
+    /// Count online players through RCON instead of the server status.
+    pub player_count: bool,
+
+    /// Command to stop the server with.
+    pub stop_command: String,
+
+    /// Commands to run before the stop command, such as to save the world.
+    pub pre_stop_commands: Vec<String>,
+}
+
+impl Rcon {
+    /// Get the HAProxy header version to send, `send_proxy_v2` selects v2.
+    pub fn send_proxy(&self) -> SendProxy {
+        self.send_proxy.or_v2(self.send_proxy_v2)
+    }
 }
 
 impl Default for Rcon {
@@ -461,47 +1362,228 @@ impl Default for Rcon {
             port: 25575,
             password: "".into(),
             randomize_password: true,
+            send_proxy: SendProxy::default(),
             send_proxy_v2: false,
+            proxy_header_delay: 0,
+            player_count: false,
+            stop_command: "stop".into(),
+            pre_stop_commands: Vec::new(),
+        }
+    }
+}
+
+/// API configuration.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct Api {
+    /// Enable HTTP API.
+    pub enabled: bool,
+
+    /// Address to serve the API on.
+    pub address: SocketAddr,
+
+    /// Unix socket to also serve the API on.
+    pub socket: Option<PathBuf>,
+
+    /// Bearer token required for all requests.
+    pub token: String,
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:25580".parse().unwrap(),
+            socket: None,
+            token: "".into(),
         }
     }
 }
 
+/// Webhook configuration.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct Webhook {
+    /// URL to send server state changes to, disabled if not set.
+    pub url: Option<String>,
+
+    /// Send messages formatted for Discord webhooks.
+    pub discord: bool,
+
+    /// Minimum time in seconds between webhook requests.
+    pub min_interval: u32,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            url: None,
+            discord: false,
+            min_interval: 10,
+        }
+    }
+}
+
+/// Hook commands configuration.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(deny_unknown_fields)]
+pub struct Hooks {
+    /// Command to run when the server is started.
+    pub on_start: Option<String>,
+
+    /// Command to run when the server is stopped.
+    pub on_stop: Option<String>,
+
+    /// Command to run when the server state changes.
+    pub on_state_change: Option<String>,
+}
+
 /// Advanced configuration.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct Advanced {
     /// Rewrite server.properties.
     pub rewrite_server_properties: bool,
+
+    /// Strictness of preflight checks before starting.
+    pub preflight: Preflight,
+
+    /// Handle lazymc commands from console input, forward other input to the server.
+    pub stdin_commands: bool,
+
+    /// Show a terminal user interface when run interactively, if supported by this build.
+    pub tui: bool,
+
+    /// Kill the server process when lazymc quits unexpectedly.
+    pub kill_server_on_exit: bool,
+
+    /// Poll server status more frequently while players are online, to keep it fresh.
+    pub live_status: bool,
+
+    /// Interval in milliseconds to poll the server status at in live status mode.
+    pub live_status_interval_ms: u32,
+
+    /// Bandwidth limit in bytes per second for each proxied connection, unlimited if not set.
+    pub max_bandwidth_per_conn: Option<u64>,
+
+    /// Maximum time in seconds to receive a client packet in once its first bytes arrived, 0 to
+    /// disable.
+    pub packet_read_timeout: u32,
+
+    /// Maximum number of connections per IP within the rate limit window, 0 to disable.
+    pub rate_limit_per_ip: u32,
+
+    /// Rate limit window in seconds.
+    pub rate_limit_window: u32,
+
+    /// Interval in seconds to poll the server status at while it is started.
+    pub poll_interval: u32,
+
+    /// Interval in seconds to poll the server status at while it is sleeping.
+    pub poll_interval_sleeping: u32,
+
+    /// Interval in seconds to poll the server status at while it is starting or stopping.
+    pub poll_interval_transition: u32,
 }
 
 impl Default for Advanced {
     fn default() -> Self {
         Self {
             rewrite_server_properties: true,
+            preflight: Preflight::default(),
+            stdin_commands: false,
+            tui: true,
+            kill_server_on_exit: true,
+            live_status: false,
+            live_status_interval_ms: 500,
+            max_bandwidth_per_conn: None,
+            packet_read_timeout: 10,
+            rate_limit_per_ip: 0,
+            rate_limit_window: 10,
+            poll_interval: 2,
+            poll_interval_sleeping: 5,
+            poll_interval_transition: 1,
         }
     }
 }
 
+/// Preflight check strictness.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Preflight {
+    /// Quit on problems.
+    Strict,
+
+    /// Show warning on problems.
+    #[default]
+    Warn,
+
+    /// Don't check.
+    Off,
+}
+
 /// Config configuration.
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, JsonSchema, Default)]
 #[serde(default)]
+#[schemars(deny_unknown_fields)]
 pub struct ConfigConfig {
     /// Configuration for lazymc version.
     pub version: Option<String>,
 }
 
+/// Schema for a value that may be given once or as a list.
+#[derive(JsonSchema)]
+#[schemars(untagged)]
+#[allow(unused)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
 fn option_pathbuf_dot() -> Option<PathBuf> {
     Some(".".into())
 }
 
-fn server_address_default() -> SocketAddr {
-    "127.0.0.1:25566".parse().unwrap()
+fn server_address_default() -> Vec<SocketAddr> {
+    vec!["127.0.0.1:25566".parse().unwrap()]
+}
+
+fn allowed_exit_codes_default() -> Vec<i32> {
+    vec![130, 143]
+}
+
+fn probe_username_default() -> String {
+    "_lazymc_probe".into()
+}
+
+fn u32_2() -> u32 {
+    2
+}
+
+fn u32_5() -> u32 {
+    5
+}
+
+fn u32_600() -> u32 {
+    600
+}
+
+fn u32_60() -> u32 {
+    60
 }
 
 fn u32_300() -> u32 {
     300
 }
 
+fn u32_180() -> u32 {
+    180
+}
+
 fn u32_150() -> u32 {
     300
 }
@@ -509,3 +1591,54 @@ fn u32_150() -> u32 {
 fn bool_true() -> bool {
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse config with the given server directory, as if loaded from the given path.
+    fn with_directory(path: Option<PathBuf>, directory: &str) -> Config {
+        let mut config: Config =
+            toml::from_str(&format!("[server]\ndirectory = {directory:?}\n")).unwrap();
+        config.path = path;
+        config
+    }
+
+    #[test]
+    fn server_directory_relative_to_config() {
+        let temp = tempfile::tempdir().unwrap();
+        let base = temp.path();
+        fs::create_dir_all(base.join("server")).unwrap();
+
+        let config = with_directory(Some(base.join("lazymc.toml")), "server");
+        let dir = Server::server_directory(&config).unwrap();
+        assert!(dir.is_absolute());
+        assert_eq!(dir, base.join("server").canonicalize().unwrap());
+    }
+
+    #[test]
+    fn server_directory_missing_kept_as_is() {
+        let temp = tempfile::tempdir().unwrap();
+        let base = temp.path();
+
+        let config = with_directory(Some(base.join("lazymc.toml")), "server");
+        assert_eq!(Server::server_directory(&config), Some(base.join("server")));
+
+        let config = with_directory(None, "server");
+        assert_eq!(
+            Server::server_directory(&config),
+            Some(PathBuf::from("server"))
+        );
+    }
+
+    #[test]
+    fn may_wake_brand() {
+        let config: Config = toml::from_str("[server]\n").unwrap();
+        assert!(config.server.may_wake_brand("vanilla"));
+        assert!(config.server.may_wake_brand("forge"));
+
+        let config: Config = toml::from_str("[server]\nwake_brands = [\"forge\"]\n").unwrap();
+        assert!(!config.server.may_wake_brand("vanilla"));
+        assert!(config.server.may_wake_brand("forge"));
+    }
+}
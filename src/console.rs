@@ -0,0 +1,159 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use regex::Regex;
+use tokio::io::{self, AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::ChildStdout;
+
+use crate::config::Config;
+use crate::server::{Server, State};
+
+/// Whether console output is shown in the TUI, instead of forwarded to our own output.
+static TUI: AtomicBool = AtomicBool::new(false);
+
+/// Show console output in the TUI, instead of forwarding it to our own output.
+pub fn set_tui(tui: bool) {
+    TUI.store(tui, Ordering::Relaxed);
+}
+
+/// Whether server console output must be captured.
+///
+/// Capturing is only done when required by configured features, as it changes how the server
+/// process sees its output.
+pub fn must_capture(config: &Config) -> bool {
+    config.motd.uses_progress()
+        || config.server.ready_regex.is_some()
+        || (config.join.lobby.console_ops && config.join.uses_lobby())
+        || TUI.load(Ordering::Relaxed)
+}
+
+/// Capture server console output.
+///
+/// Forwards each line to our own stdout and processes it, until the output is closed. Lines that
+/// aren't valid UTF-8 are processed lossily, so the pipe is always drained.
+pub async fn capture(config: Arc<Config>, server: Arc<Server>, output: ChildStdout) {
+    let progress = Progress::from_config(&config);
+    let ready = config.server.ready_regex.as_ref();
+    let ready_timeout = Duration::from_secs(config.server.ready_regex_timeout as u64);
+    server
+        .set_awaiting_ready(ready.map(|_| ready_timeout))
+        .await;
+    let mut output = BufReader::new(output);
+    let mut buf = Vec::new();
+    let mut stdout = io::stdout();
+
+    loop {
+        let line = match read_line(&mut output, &mut buf).await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                warn!(target: "lazymc", "Failed to read server console output, no longer capturing: {}", err);
+                break;
+            }
+        };
+
+        // Forward line to our own output, unless shown in TUI
+        if !TUI.load(Ordering::Relaxed) {
+            let _ = stdout.write_all(line.as_bytes()).await;
+            let _ = stdout.write_all(b"\n").await;
+            let _ = stdout.flush().await;
+        }
+
+        // Track start progress
+        if server.state() == State::Starting {
+            if let Some(progress) = progress.parse(&line) {
+                trace!(target: "lazymc", "Server start progress: {}", progress);
+                server.set_progress(progress).await;
+            }
+        }
+
+        // Mark server ready as soon as it reports to be
+        if server.state() == State::Starting && ready.map(|r| r.is_match(&line)).unwrap_or(false) {
+            debug!(target: "lazymc", "Server reported to be ready in console output");
+            server.mark_ready(&config).await;
+        }
+
+        // Share console with subscribers, such as lobby OPs and the TUI
+        let _ = server.console.send(line);
+    }
+
+    server.set_awaiting_ready(None).await;
+}
+
+/// Read a line from the given reader, without line ending.
+///
+/// Invalid UTF-8 is replaced rather than failing. Returns `None` at the end of the output.
+async fn read_line<R>(reader: &mut R, buf: &mut Vec<u8>) -> Result<Option<String>, io::Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    buf.clear();
+    if reader.read_until(b'\n', buf).await? == 0 {
+        return Ok(None);
+    }
+
+    let line: &[u8] = buf;
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok(Some(String::from_utf8_lossy(line).into_owned()))
+}
+
+/// Server start progress parser.
+struct Progress {
+    /// Patterns matching progress lines.
+    patterns: Vec<Regex>,
+}
+
+impl Progress {
+    /// Build progress parser from configured patterns.
+    ///
+    /// Invalid patterns are skipped with a warning.
+    fn from_config(config: &Config) -> Self {
+        let patterns = config
+            .motd
+            .progress_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    warn!(target: "lazymc", "Ignoring invalid progress pattern '{}': {}", pattern, err);
+                    None
+                }
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// Parse progress from a console line.
+    ///
+    /// Returns the first capture group of the first matching pattern, or the whole match if the
+    /// pattern has no groups.
+    fn parse(&self, line: &str) -> Option<String> {
+        self.patterns.iter().find_map(|pattern| {
+            let captures = pattern.captures(line)?;
+            captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map(|m| m.as_str().to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_line_lossy() {
+        let mut output: &[u8] = b"Starting\r\ninvalid \xff line\nDone (1.0s)!";
+        let mut buf = Vec::new();
+
+        let mut lines = vec![];
+        while let Some(line) = read_line(&mut output, &mut buf).await.unwrap() {
+            lines.push(line);
+        }
+
+        assert_eq!(lines, ["Starting", "invalid \u{fffd} line", "Done (1.0s)!"]);
+    }
+}
@@ -18,6 +18,7 @@ use tokio::net::TcpStream;
 #[cfg(feature = "lobby")]
 use tokio::time;
 
+use crate::config::ModLoader;
 use crate::forge;
 use crate::proto::client::Client;
 #[cfg(feature = "lobby")]
@@ -27,10 +28,28 @@ use crate::proto::packet::RawPacket;
 #[cfg(feature = "lobby")]
 use crate::proto::packets;
 #[cfg(feature = "lobby")]
+use crate::proto::unhandled::Unhandled;
+#[cfg(feature = "lobby")]
 use crate::server::Server;
 
-/// Forge status magic.
-pub const STATUS_MAGIC: &str = "\0FML2\0";
+/// Forge handshake server address marker for Minecraft 1.13 to 1.17.
+const MARKER_FML2: &str = "\0FML2\0";
+
+/// Forge and NeoForge handshake server address marker for Minecraft 1.18 to 1.20.1.
+const MARKER_FML3: &str = "\0FML3\0";
+
+/// Forge handshake server address marker for Minecraft 1.20.2 and above.
+const MARKER_FORGE: &str = "\0FORGE";
+
+/// Forge handshake server address markers, any version.
+pub const HANDSHAKE_MARKERS: [&str; 2] = ["\0FML", MARKER_FORGE];
+
+/// Minimum protocol version using the FML3 handshake, Minecraft 1.18.
+const PROTOCOL_V1_18: u32 = 757;
+
+/// Minimum protocol version using the configuration phase for the mod handshake, Minecraft
+/// 1.20.2.
+const PROTOCOL_V1_20_2: u32 = 764;
 
 /// Forge plugin wrapper login plugin request channel.
 pub const CHANNEL_LOGIN_WRAPPER: &str = "fml:loginwrapper";
@@ -38,6 +57,40 @@ pub const CHANNEL_LOGIN_WRAPPER: &str = "fml:loginwrapper";
 /// Forge handshake channel.
 pub const CHANNEL_HANDSHAKE: &str = "fml:handshake";
 
+/// NeoForge handshake channel.
+pub const CHANNEL_HANDSHAKE_NEOFORGE: &str = "neoforge:handshake";
+
+/// Get the handshake server address marker for the given mod loader and protocol version.
+///
+/// Returns `None` if no marker is used. NeoForge on Minecraft 1.20.2 and above negotiates mods
+/// in the configuration phase and doesn't use a marker.
+pub fn handshake_marker(loader: ModLoader, protocol: u32) -> Option<&'static str> {
+    match loader {
+        ModLoader::Vanilla => None,
+        ModLoader::Forge if protocol >= PROTOCOL_V1_20_2 => Some(MARKER_FORGE),
+        ModLoader::NeoForge if protocol >= PROTOCOL_V1_20_2 => None,
+        _ if protocol >= PROTOCOL_V1_18 => Some(MARKER_FML3),
+        _ => Some(MARKER_FML2),
+    }
+}
+
+/// Whether the mod handshake happens in the configuration phase for the given mod loader and
+/// protocol version.
+///
+/// NeoForge on Minecraft 1.20.2 and above negotiates mods in the configuration phase, which is
+/// not supported. There is no login payload to probe or replay, and clients can't be detected as
+/// modded by their handshake.
+pub fn is_configuration_handshake(loader: ModLoader, protocol: u32) -> bool {
+    loader == ModLoader::NeoForge && protocol >= PROTOCOL_V1_20_2
+}
+
+/// Whether the given login plugin request is a Forge login wrapper request.
+///
+/// Only these are handled as part of the Forge login payload.
+pub fn is_login_wrapper(request: &LoginPluginRequest) -> bool {
+    request.channel == CHANNEL_LOGIN_WRAPPER
+}
+
 /// Timeout for draining Forge plugin responses from client.
 #[cfg(feature = "lobby")]
 const CLIENT_DRAIN_FORGE_TIMEOUT: Duration = Duration::from_secs(5);
@@ -90,7 +143,8 @@ pub async fn respond_login_plugin_request(
         forge::decode_forge_login_packet(client, packet).await?;
 
     // Determine whether we received the mod list
-    let is_unknown_header = login_wrapper.channel != forge::CHANNEL_HANDSHAKE;
+    let is_unknown_header = login_wrapper.channel != forge::CHANNEL_HANDSHAKE
+        && login_wrapper.channel != forge::CHANNEL_HANDSHAKE_NEOFORGE;
     let is_mod_list = !is_unknown_header && packet.id == ModList::PACKET_ID;
 
     // If not the mod list, just acknowledge
@@ -142,7 +196,10 @@ pub async fn decode_forge_login_packet(
     plugin_request: LoginPluginRequest,
 ) -> Result<(i32, LoginWrapper, RawPacket), ()> {
     // Validate channel
-    assert_eq!(plugin_request.channel, CHANNEL_LOGIN_WRAPPER);
+    if !is_login_wrapper(&plugin_request) {
+        error!(target: "lazymc::forge", "Unexpected login plugin request channel for Forge: {}", plugin_request.channel);
+        return Err(());
+    }
 
     // Decode login wrapped packet
     let login_wrapper =
@@ -193,6 +250,7 @@ async fn drain_forge_responses(
     mut count: usize,
 ) -> Result<(), ()> {
     let (mut reader, mut _writer) = inbound.split();
+    let mut unhandled = Unhandled::new("lazymc::forge", "drain_forge_responses");
 
     loop {
         // We're done if count is zero
@@ -202,7 +260,7 @@ async fn drain_forge_responses(
         }
 
         // Read packet from stream with timeout
-        let read_packet_task = packet::read_packet(client, buf, &mut reader);
+        let read_packet_task = packet::read_inbound_packet(client, buf, &mut reader);
         let timeout = time::timeout(CLIENT_DRAIN_FORGE_TIMEOUT, read_packet_task).await;
         let read_packet_task = match timeout {
             Ok(result) => result,
@@ -245,10 +303,7 @@ async fn drain_forge_responses(
         //     return Ok(forge_payload);
         // }
 
-        // Show unhandled packet warning
-        debug!(target: "lazymc::forge", "Got unhandled packet from server in record_forge_response:");
-        debug!(target: "lazymc::forge", "- State: {:?}", client_state);
-        debug!(target: "lazymc::forge", "- Packet ID: 0x{:02X} ({})", packet.id, packet.id);
+        unhandled.record(client_state, packet.id);
     }
 
     Err(())
@@ -0,0 +1,300 @@
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+
+use bytes::BytesMut;
+use hmac::{Hmac, Mac};
+use minecraft_protocol::decoder::Decoder;
+use minecraft_protocol::encoder::Encoder;
+use minecraft_protocol::version::v1_14_4::handshake::Handshake;
+use minecraft_protocol::version::v1_14_4::login::{
+    LoginPluginRequest, LoginPluginResponse, LoginStart, SetCompression,
+};
+use sha2::Sha256;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+use crate::config::{Config, Forwarding};
+use crate::mc::uuid::offline_player_uuid;
+use crate::proto::client::{Client, ClientState};
+use crate::proto::packet::{self, RawPacket};
+use crate::proto::packets;
+use crate::proxy::{self, ProxyHeader};
+use crate::types;
+
+/// Velocity player info login plugin channel.
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+
+/// Velocity modern forwarding version we send, the default version without player key.
+const VELOCITY_VERSION: i32 = 1;
+
+/// Player info forwarded to the server.
+pub struct PlayerInfo {
+    /// Real IP of the player.
+    pub ip: IpAddr,
+
+    /// Player username.
+    pub username: String,
+}
+
+impl PlayerInfo {
+    /// Construct new player info.
+    pub fn new(ip: IpAddr, username: String) -> Self {
+        Self { ip, username }
+    }
+
+    /// Get the player UUID.
+    ///
+    /// This is the offline mode UUID, lazymc doesn't authenticate players.
+    pub fn uuid(&self) -> Uuid {
+        offline_player_uuid(&self.username)
+    }
+}
+
+/// Rewrite the handshake to forward player info, if BungeeCord forwarding is used.
+///
+/// Appends the player IP, UUID and properties to the server address, as BungeeCord does.
+pub fn rewrite_handshake(config: &Config, handshake: &mut Handshake, player: &PlayerInfo) {
+    if config.server.forwarding != Forwarding::Bungeecord {
+        return;
+    }
+
+    let host = handshake
+        .server_addr
+        .split('\0')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    handshake.server_addr = format!("{host}\0{}\0{}\0[]", player.ip, player.uuid().simple());
+}
+
+/// Build response to the given login plugin request, if Velocity forwarding is used.
+///
+/// Returns `None` if this is not a Velocity player info request. The response holds the player
+/// info, signed with the forwarding secret.
+pub fn plugin_response(
+    config: &Config,
+    request: &LoginPluginRequest,
+    player: &PlayerInfo,
+) -> Result<Option<LoginPluginResponse>, ()> {
+    if config.server.forwarding != Forwarding::Velocity || request.channel != VELOCITY_CHANNEL {
+        return Ok(None);
+    }
+
+    // Encode player info, without properties
+    let mut payload = types::encode_var_int(VELOCITY_VERSION)?;
+    write_string(&mut payload, &player.ip.to_string())?;
+    payload.extend_from_slice(player.uuid().as_bytes());
+    write_string(&mut payload, &player.username)?;
+    payload.extend(types::encode_var_int(0)?);
+
+    // Sign player info with secret
+    let mut mac = Hmac::<Sha256>::new_from_slice(config.server.forwarding_secret.as_bytes())
+        .map_err(|_| ())?;
+    mac.update(&payload);
+    let mut data = mac.finalize().into_bytes().to_vec();
+    data.extend(payload);
+
+    Ok(Some(LoginPluginResponse {
+        message_id: request.message_id,
+        successful: true,
+        data,
+    }))
+}
+
+/// Proxy the inbound stream to a target address, forwarding player info to the server.
+///
+/// The queue must hold the client handshake and login start as received from the client, these
+/// are read from the inbound stream first if the queue is empty. Other connections, such as status
+/// requests, are proxied as-is.
+pub async fn proxy(
+    config: &Config,
+    mut inbound: TcpStream,
+    peer: SocketAddr,
+    proxy_header: ProxyHeader,
+    addrs_target: &[SocketAddr],
+    mut queue: BytesMut,
+) -> Result<(), Box<dyn Error>> {
+    // Proxy legacy server list pings as-is, these don't use a handshake
+    let mut first = [0];
+    if queue.is_empty()
+        && inbound.peek(&mut first).await? == 1
+        && first[0] == packets::handshake::LEGACY_PING
+    {
+        return proxy::proxy_with_queue(inbound, proxy_header, addrs_target, &queue).await;
+    }
+
+    if queue.is_empty() {
+        queue = read_login(&mut inbound)
+            .await
+            .map_err(|_| "failed to read client login")?;
+        if queue.is_empty() {
+            return Ok(());
+        }
+    }
+
+    // Rewrite login with player info, proxy as-is if not logging in
+    let (queue, player) = match rewrite_login(config, &queue, peer.ip())
+        .map_err(|_| "failed to parse client login")?
+    {
+        Some(login) => login,
+        None => return proxy::proxy_with_queue(inbound, proxy_header, addrs_target, &queue).await,
+    };
+
+    let (mut outbound, _permit) =
+        proxy::connect_outbound(&inbound, proxy_header, addrs_target).await?;
+    outbound.write_all(&queue).await?;
+
+    // Respond to Velocity player info request in place of client
+    let inbound_queue = if config.server.forwarding == Forwarding::Velocity {
+        velocity_login(config, &player, &mut inbound, &mut outbound)
+            .await
+            .map_err(|_| "failed to forward player info")?
+    } else {
+        BytesMut::new()
+    };
+
+    proxy::proxy_inbound_outbound_with_queue(inbound, outbound, &inbound_queue, &[]).await
+}
+
+/// Read client handshake and login start from the inbound stream.
+///
+/// Only reads the handshake if the client is not logging in. Returns all data read.
+async fn read_login(inbound: &mut TcpStream) -> Result<BytesMut, ()> {
+    let client = Client::dummy();
+    let (mut reader, _) = inbound.split();
+    let mut buf = BytesMut::new();
+    let mut queue = BytesMut::new();
+
+    // Read handshake, stop if client is not logging in
+    if let Some((packet, raw)) = packet::read_inbound_packet(&client, &mut buf, &mut reader).await?
+    {
+        queue.extend(raw);
+
+        let handshake = Handshake::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
+        if ClientState::from_id(handshake.next_state) == Some(ClientState::Login) {
+            // Read login start
+            client.set_state(ClientState::Login);
+            if let Some((_, raw)) =
+                packet::read_inbound_packet(&client, &mut buf, &mut reader).await?
+            {
+                queue.extend(raw);
+            }
+        }
+    }
+
+    queue.extend(buf);
+    Ok(queue)
+}
+
+/// Rewrite queued client login to forward player info.
+///
+/// Returns `None` if the queue doesn't start with a login handshake.
+fn rewrite_login(
+    config: &Config,
+    queue: &[u8],
+    ip: IpAddr,
+) -> Result<Option<(BytesMut, PlayerInfo)>, ()> {
+    let client = Client::dummy();
+
+    // Parse handshake, must be logging in
+    let handshake_len = packet_len(queue)?;
+    let packet = RawPacket::decode_with_len(&client, &queue[..handshake_len])?;
+    let mut handshake = Handshake::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
+    if ClientState::from_id(handshake.next_state) != Some(ClientState::Login) {
+        return Ok(None);
+    }
+
+    // Parse login start for username
+    let rest = &queue[handshake_len..];
+    let packet = RawPacket::decode_with_len(&client, &rest[..packet_len(rest)?])?;
+    if packet.id != packets::login::SERVER_LOGIN_START {
+        return Err(());
+    }
+    let login_start = LoginStart::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
+    let player = PlayerInfo::new(ip, login_start.name);
+
+    // Rebuild queue with rewritten handshake
+    rewrite_handshake(config, &mut handshake, &player);
+    let mut data = Vec::new();
+    handshake.encode(&mut data).map_err(|_| ())?;
+    let mut rewritten = BytesMut::from(
+        RawPacket::new(packets::handshake::SERVER_HANDSHAKE, data)
+            .encode_with_len(&client)?
+            .as_slice(),
+    );
+    rewritten.extend_from_slice(rest);
+
+    Ok(Some((rewritten, player)))
+}
+
+/// Relay server login packets to the client until the server requested Velocity player info.
+///
+/// Responds to the request in place of the client. Returns buffered server data still to relay to
+/// the client.
+async fn velocity_login(
+    config: &Config,
+    player: &PlayerInfo,
+    inbound: &mut TcpStream,
+    outbound: &mut TcpStream,
+) -> Result<BytesMut, ()> {
+    let (_, mut wi) = inbound.split();
+    let (mut ro, mut wo) = outbound.split();
+
+    // Track server connection state as seen by the client
+    let client = Client::dummy();
+    client.set_state(ClientState::Login);
+    let mut buf = BytesMut::new();
+
+    loop {
+        let (packet, raw) = match packet::read_packet(&client, &mut buf, &mut ro).await? {
+            Some(packet) => packet,
+            None => return Ok(buf),
+        };
+
+        // Respond to player info request
+        if packet.id == packets::login::CLIENT_LOGIN_PLUGIN_REQUEST {
+            let request =
+                LoginPluginRequest::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
+            if let Some(response) = plugin_response(config, &request, player)? {
+                trace!(target: "lazymc", "Forwarding player info to server for '{}'", player.username);
+                packet::write_packet(response, &client, &mut wo).await?;
+                return Ok(buf);
+            }
+        }
+
+        wi.write_all(&raw).await.map_err(|_| ())?;
+
+        match packet.id {
+            packets::login::CLIENT_SET_COMPRESSION => {
+                let set_compression =
+                    SetCompression::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
+                client.set_compression(set_compression.threshold);
+            }
+            packets::login::CLIENT_LOGIN_SUCCESS
+            | packets::login::CLIENT_ENCRYPTION_REQUEST
+            | packets::login::CLIENT_DISCONNECT => {
+                warn!(target: "lazymc", "Server did not request player info, make sure Velocity forwarding is enabled on the server");
+                return Ok(buf);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Get the length of the first packet in the buffer, including its length prefix.
+fn packet_len(buf: &[u8]) -> Result<usize, ()> {
+    let (read, len) = types::read_var_int(buf).map_err(|_| ())?;
+    let total = read + usize::try_from(len).map_err(|_| ())?;
+    if total > buf.len() {
+        return Err(());
+    }
+    Ok(total)
+}
+
+/// Write a string with var-int length prefix to the buffer.
+fn write_string(buf: &mut Vec<u8>, value: &str) -> Result<(), ()> {
+    buf.extend(types::encode_var_int(value.len() as i32)?);
+    buf.extend_from_slice(value.as_bytes());
+    Ok(())
+}
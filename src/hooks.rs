@@ -0,0 +1,105 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use tokio::process::Command;
+
+use crate::config::{Config, Server as ConfigServer};
+use crate::server::State;
+
+/// Server lifecycle hooks.
+///
+/// Implement this to be notified of server lifecycle events, and register it with
+/// `Server::add_hooks`. All methods have a default implementation that does nothing.
+///
+/// Hooks are invoked synchronously from the lifecycle code and must not block. Spawn a task for
+/// any long running work.
+pub trait Hooks: Debug + Send + Sync {
+    /// Invoked when the server is started, with the username of the player that woke it if any.
+    fn on_start(&self, username: Option<&str>) {
+        let _ = username;
+    }
+
+    /// Invoked when the server is stopped.
+    fn on_stop(&self) {}
+
+    /// Invoked when the server state changes.
+    fn on_state_change(&self, old: State, new: State) {
+        let _ = (old, new);
+    }
+}
+
+/// Hooks running the commands configured in the `hooks` section.
+#[derive(Debug)]
+pub struct CommandHooks {
+    config: Arc<Config>,
+}
+
+impl CommandHooks {
+    /// Construct command hooks, returns `None` if no hook command is configured.
+    pub fn new(config: Arc<Config>) -> Option<Self> {
+        let hooks = &config.hooks;
+        if hooks.on_start.is_none() && hooks.on_stop.is_none() && hooks.on_state_change.is_none() {
+            return None;
+        }
+        Some(Self { config })
+    }
+
+    /// Run the given hook command in the background, with the given environment variables.
+    fn run(&self, command: Option<&String>, envs: &[(&str, &str)]) {
+        let Some(command) = command else {
+            return;
+        };
+        let args = match shlex::split(command) {
+            Some(args) if !args.is_empty() => args,
+            _ => {
+                warn!(target: "lazymc::hooks", "Not running invalid hook command: {}", command);
+                return;
+            }
+        };
+
+        let mut cmd = Command::new(&args[0]);
+        cmd.args(&args[1..]);
+        cmd.envs(envs.iter().copied());
+        if let Some(dir) = ConfigServer::server_directory(&self.config) {
+            cmd.current_dir(dir);
+        }
+
+        let command = command.clone();
+        tokio::spawn(async move {
+            match cmd.status().await {
+                Ok(status) if status.success() => {
+                    debug!(target: "lazymc::hooks", "Ran hook command: {}", command)
+                }
+                Ok(status) => {
+                    warn!(target: "lazymc::hooks", "Hook command '{}' failed: {}", command, status)
+                }
+                Err(err) => {
+                    warn!(target: "lazymc::hooks", "Failed to run hook command '{}': {}", command, err)
+                }
+            }
+        });
+    }
+}
+
+impl Hooks for CommandHooks {
+    fn on_start(&self, username: Option<&str>) {
+        self.run(
+            self.config.hooks.on_start.as_ref(),
+            &[("LAZYMC_USERNAME", username.unwrap_or_default())],
+        );
+    }
+
+    fn on_stop(&self) {
+        self.run(self.config.hooks.on_stop.as_ref(), &[]);
+    }
+
+    fn on_state_change(&self, old: State, new: State) {
+        self.run(
+            self.config.hooks.on_state_change.as_ref(),
+            &[
+                ("LAZYMC_OLD_STATE", old.name()),
+                ("LAZYMC_NEW_STATE", new.name()),
+            ],
+        );
+    }
+}
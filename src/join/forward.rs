@@ -1,32 +1,62 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::BytesMut;
 use tokio::net::TcpStream;
 
 use crate::config::*;
-use crate::proxy::ProxyHeader;
-use crate::service;
+use crate::net;
+use crate::proto::client::Client;
+use crate::proxy::{self, ProxyHeader};
 
 use super::MethodResult;
 
+/// Time to wait for a forward target to accept a connection, before trying the next one.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Forward the client.
 pub async fn occupy(
+    client: &Client,
     config: Arc<Config>,
     inbound: TcpStream,
     inbound_history: &mut BytesMut,
 ) -> Result<MethodResult, ()> {
     trace!(target: "lazymc", "Using forward method to occupy joining client");
 
-    debug!(target: "lazymc", "Forwarding client to {:?}!", config.join.forward.address);
-
-    service::server::route_proxy_address_queue(
-        inbound,
-        ProxyHeader::Proxy.not_none(config.join.forward.send_proxy_v2),
-        config.join.forward.address,
-        inbound_history.clone(),
-    );
-
-    // TODO: do not consume, continue on proxy connect failure
+    // Connect to first reachable forward target, continue with next method if there is none
+    let addrs = &config.join.forward.addresses;
+    let (outbound, address, permit) = match net::connect_first_within(addrs, Some(CONNECT_TIMEOUT))
+        .await
+    {
+        Ok(connected) => connected,
+        Err(err) => {
+            warn!(target: "lazymc", "No forward target reachable, not forwarding client: {}", err);
+            return Ok(MethodResult::Continue(inbound));
+        }
+    };
+
+    // Targets are tried in order, all before the connected one failed
+    let failed: Vec<_> = addrs
+        .iter()
+        .take_while(|addr| **addr != address)
+        .map(|addr| addr.to_string())
+        .collect();
+    if !failed.is_empty() {
+        info!(target: "lazymc", "Forward target {} unreachable, using {}", failed.join(", "), address);
+    }
+
+    debug!(target: "lazymc", "Forwarding client to {}!", address);
+
+    let proxy_header = ProxyHeader::proxy(config.join.forward.send_proxy(), client.peer);
+    let queue = inbound_history.clone();
+    tokio::spawn(async move {
+        let _permit = permit;
+        if let Err(err) =
+            proxy::proxy_connected_with_queue(inbound, outbound, proxy_header, &queue).await
+        {
+            warn!(target: "lazymc", "Failed to proxy: {}", err);
+        }
+    });
 
     Ok(MethodResult::Consumed)
 }
@@ -1,23 +1,44 @@
+use std::future;
 use std::ops::Deref;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
+use minecraft_protocol::version::v1_14_4::login::LoginPluginRequest;
 use tokio::net::TcpStream;
 use tokio::time;
 
 use crate::config::*;
+use crate::proto::client::{Client, ClientInfo, ClientState};
+use crate::proto::packet::{self, RawPacket};
+use crate::proto::packets;
 use crate::server::{Server, State};
 use crate::service;
+use crate::types;
 
 use super::MethodResult;
 
+/// Minimum client protocol version supporting login plugin requests, Minecraft 1.13.
+const PROTOCOL_V1_13: u32 = 393;
+
+/// Interval to send keep-alive requests to held clients at.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time to wait for the response to the last keep-alive request once holding is done.
+const KEEP_ALIVE_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Login plugin channel used for keep-alive requests, clients respond it is not understood.
+const KEEP_ALIVE_CHANNEL: &str = "lazymc:hold";
+
 /// Hold the client.
 pub async fn occupy(
+    client: &Client,
+    client_info: &ClientInfo,
     config: Arc<Config>,
     server: Arc<Server>,
-    inbound: TcpStream,
+    mut inbound: TcpStream,
     inbound_history: &mut BytesMut,
+    timeout: u32,
 ) -> Result<MethodResult, ()> {
     trace!(target: "lazymc", "Using hold method to occupy joining client");
 
@@ -26,9 +47,61 @@ pub async fn occupy(
         return Ok(MethodResult::Continue(inbound));
     }
 
+    // Track held client, log its position
+    let _held = Server::track_held_client(&server);
+    debug!(target: "lazymc", "Holding client while server starts ({} held)", server.held_clients());
+
+    // Keep client alive with login plugin requests if supported
+    let keep_alive = config.join.hold.keep_alive
+        && matches!(client_info.protocol(), Some(p) if p >= PROTOCOL_V1_13);
+    let mut held = HeldStream::default();
+
     // Start holding, consume client
-    if hold(&config, &server).await? {
-        service::server::route_proxy_queue(inbound, config, inbound_history.clone());
+    let ready = {
+        let closed = async {
+            if keep_alive {
+                held.keep_alive(&mut inbound, KEEP_ALIVE_INTERVAL).await
+            } else if config.join.hold.detect_disconnect {
+                client_closed(&inbound).await
+            } else {
+                future::pending().await
+            }
+        };
+        tokio::select! {
+            ready = hold(&server, timeout) => ready?,
+            _ = closed => {
+                info!(target: "lazymc", "Held client disconnected, stopped holding");
+                return Ok(MethodResult::Consumed);
+            }
+        }
+    };
+
+    // Wait for last keep-alive response, keep client data read in the meantime
+    if !held.drain(&mut inbound).await {
+        info!(target: "lazymc", "Held client disconnected, stopped holding");
+        return Ok(MethodResult::Consumed);
+    }
+    inbound_history.extend(held.pending);
+    inbound_history.extend(held.buf);
+
+    if ready {
+        match wake_message(client_info, &config) {
+            Some(packet) => service::server::route_proxy_queue_inject(
+                inbound,
+                config,
+                &server,
+                client.peer,
+                inbound_history.clone(),
+                packet,
+            ),
+            None => service::server::route_proxy_queue(
+                inbound,
+                config,
+                &server,
+                client.peer,
+                inbound_history.clone(),
+            ),
+        }
         return Ok(MethodResult::Consumed);
     }
 
@@ -38,10 +111,12 @@ pub async fn occupy(
 /// Hold a client while server starts.
 ///
 /// Returns holding status. `true` if client is held and it should be proxied, `false` it was held
-/// but it timed out.
-async fn hold<'a>(config: &Config, server: &Server) -> Result<bool, ()> {
+/// but it timed out. The timeout is in seconds.
+async fn hold<'a>(server: &Server, timeout: u32) -> Result<bool, ()> {
     trace!(target: "lazymc", "Started holding client");
 
+    let started = Instant::now();
+
     // A task to wait for suitable server state
     // Waits for started state, errors if stopping/stopped state is reached
     let task_wait = async {
@@ -78,11 +153,13 @@ async fn hold<'a>(config: &Config, server: &Server) -> Result<bool, ()> {
     };
 
     // Wait for server state with timeout
-    let timeout = Duration::from_secs(config.join.hold.timeout as u64);
+    let timeout = Duration::from_secs(timeout as u64);
     match time::timeout(timeout, task_wait).await {
         // Relay client to proxy
         Ok(true) => {
-            info!(target: "lazymc", "Server ready for held client, relaying to server");
+            let waited = started.elapsed();
+            server.metrics.hold_wait.observe(waited);
+            info!(target: "lazymc", "Server ready for held client, relaying to server (waited {:.1}s)", waited.as_secs_f32());
             Ok(true)
         }
 
@@ -94,8 +171,200 @@ async fn hold<'a>(config: &Config, server: &Server) -> Result<bool, ()> {
 
         // Timeout reached, kick with starting message
         Err(_) => {
-            warn!(target: "lazymc", "Held client reached timeout of {}s", config.join.hold.timeout);
+            warn!(target: "lazymc", "Held client reached timeout of {}s", timeout.as_secs());
             Ok(false)
         }
     }
 }
+
+/// Wait until the held client closed its connection.
+///
+/// Never completes if the client sends more data, it is kept for the server once proxied.
+async fn client_closed(inbound: &TcpStream) {
+    let mut buf = [0; 1];
+    match inbound.peek(&mut buf).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => future::pending().await,
+    }
+}
+
+/// Held client stream state for sending keep-alive requests.
+#[derive(Default)]
+struct HeldStream {
+    /// Buffer of client data not yet parsed as packet.
+    buf: BytesMut,
+
+    /// Client packets read while holding that are not keep-alive responses.
+    pending: BytesMut,
+
+    /// Message ID of last keep-alive request, if not responded to yet.
+    outstanding: Option<i32>,
+
+    /// Message ID for next keep-alive request.
+    next_id: i32,
+}
+
+impl HeldStream {
+    /// Periodically send keep-alive requests to the held client at the given interval.
+    ///
+    /// Completes once the client closed its connection. Responses to our requests are consumed,
+    /// other client packets are kept for the server once proxied.
+    async fn keep_alive(&mut self, inbound: &mut TcpStream, interval: Duration) {
+        let client = login_client();
+        let (mut reader, mut writer) = inbound.split();
+        let mut interval = time::interval_at(time::Instant::now() + interval, interval);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    // Don't stack requests if client didn't respond yet
+                    if self.outstanding.is_some() {
+                        continue;
+                    }
+
+                    trace!(target: "lazymc", "Sending keep-alive to held client");
+                    let request = LoginPluginRequest {
+                        message_id: self.next_id,
+                        channel: KEEP_ALIVE_CHANNEL.into(),
+                        data: vec![],
+                    };
+                    if packet::write_packet(request, &client, &mut writer).await.is_err() {
+                        return;
+                    }
+                    self.outstanding = Some(self.next_id);
+                    self.next_id = self.next_id.wrapping_add(1);
+                }
+                result = packet::read_inbound_packet(&client, &mut self.buf, &mut reader) => {
+                    match result {
+                        Ok(Some((packet, raw))) => self.received(packet, raw),
+                        Ok(None) | Err(_) => return,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait for the response to the last keep-alive request, if any.
+    ///
+    /// The server must not receive responses to our requests. Returns `false` if the client
+    /// closed its connection.
+    async fn drain(&mut self, inbound: &mut TcpStream) -> bool {
+        let client = login_client();
+        let (mut reader, _) = inbound.split();
+
+        let drain = async {
+            while self.outstanding.is_some() {
+                match packet::read_inbound_packet(&client, &mut self.buf, &mut reader).await {
+                    Ok(Some((packet, raw))) => self.received(packet, raw),
+                    Ok(None) | Err(_) => return false,
+                }
+            }
+            true
+        };
+
+        match time::timeout(KEEP_ALIVE_DRAIN_TIMEOUT, drain).await {
+            Ok(open) => open,
+            Err(_) => {
+                warn!(target: "lazymc", "Held client did not respond to keep-alive, disconnecting");
+                false
+            }
+        }
+    }
+
+    /// Handle packet received from the held client.
+    fn received(&mut self, packet: RawPacket, raw: Vec<u8>) {
+        if packet.id == packets::login::SERVER_LOGIN_PLUGIN_RESPONSE {
+            let message_id = types::read_var_int(&packet.data).map(|(_, id)| id);
+            if message_id.ok() == self.outstanding {
+                self.outstanding = None;
+                return;
+            }
+        }
+        self.pending.extend(raw);
+    }
+}
+
+/// Build client tracking the held client connection, which is in login state.
+fn login_client() -> Client {
+    let client = Client::dummy();
+    client.set_state(ClientState::Login);
+    client
+}
+
+/// Build wake message packet to send to client once it joined the server, if configured.
+fn wake_message(client_info: &ClientInfo, config: &Config) -> Option<RawPacket> {
+    let msg = config.join.hold.wake_message.as_ref()?;
+    let packet = packets::play::chat::system_message(client_info, msg);
+    if packet.is_none() {
+        debug!(target: "lazymc", "Not sending wake message to client, not supported for its Minecraft version");
+    }
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use minecraft_protocol::decoder::Decoder;
+    use minecraft_protocol::version::v1_14_4::login::LoginPluginResponse;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Login acknowledged packet ID, the first packet a 1.20.2+ client sends in the lobby.
+    const LOGIN_ACKNOWLEDGED: u8 = 0x03;
+
+    /// Connected pair of TCP streams, the inbound stream held by lazymc and the client side.
+    async fn stream_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let remote = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (inbound, _) = listener.accept().await.unwrap();
+        (inbound, remote)
+    }
+
+    #[tokio::test]
+    async fn keep_alive_drained_before_lobby() {
+        let (mut inbound, mut remote) = stream_pair().await;
+        let client = login_client();
+        let mut held = HeldStream::default();
+
+        // Hold with keep-alive until the client received a request
+        let request = {
+            let (mut reader, _) = remote.split();
+            let mut buf = BytesMut::new();
+            tokio::select! {
+                _ = held.keep_alive(&mut inbound, Duration::from_millis(10)) => panic!("held client closed"),
+                request = packet::read_packet(&client, &mut buf, &mut reader) => request.unwrap().unwrap().0,
+            }
+        };
+        assert_eq!(request.id, packets::login::CLIENT_LOGIN_PLUGIN_REQUEST);
+        let request = LoginPluginRequest::decode(&mut request.data.as_slice()).unwrap();
+
+        // Server not ready, client responds late and continues in the lobby
+        let (_, mut writer) = remote.split();
+        let response = LoginPluginResponse {
+            message_id: request.message_id,
+            successful: false,
+            data: vec![],
+        };
+        packet::write_packet(response, &client, &mut writer)
+            .await
+            .unwrap();
+        let ack = RawPacket::new(LOGIN_ACKNOWLEDGED, vec![]);
+        writer
+            .write_all(&ack.encode_with_len(&client).unwrap())
+            .await
+            .unwrap();
+
+        // Response must be consumed, the lobby must read the client packet first
+        assert!(held.drain(&mut inbound).await);
+        assert!(held.pending.is_empty());
+        let (mut reader, _) = inbound.split();
+        let (packet, _) = packet::read_inbound_packet(&client, &mut held.buf, &mut reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.id, LOGIN_ACKNOWLEDGED);
+    }
+}
@@ -20,7 +20,10 @@ pub async fn occupy(
     // Select message and kick
     let msg = match server.state() {
         server::State::Starting | server::State::Stopped | server::State::Started => {
-            &config.join.kick.starting
+            match config.join.kick.cold_start {
+                Some(ref msg) if server.is_cold_start() => msg,
+                _ => &config.join.kick.starting,
+            }
         }
         server::State::Stopping => &config.join.kick.stopping,
     };
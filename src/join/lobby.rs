@@ -4,7 +4,8 @@ use bytes::BytesMut;
 use tokio::net::TcpStream;
 
 use crate::config::*;
-use crate::lobby;
+use crate::lobby::{self, LobbyWait};
+use crate::mc::dimension;
 use crate::proto::client::{Client, ClientInfo};
 use crate::server::Server;
 
@@ -18,17 +19,47 @@ pub async fn occupy(
     server: Arc<Server>,
     inbound: TcpStream,
     inbound_queue: BytesMut,
+    timeout: u32,
 ) -> Result<MethodResult, ()> {
     trace!(target: "lazymc", "Using lobby method to occupy joining client");
 
+    // Must be ready to lobby, probing failed before and won't be retried for this client
+    if must_still_probe(&config, &server).await && server.probe_failed() {
+        if server.report_probe_failed() {
+            warn!(target: "lazymc", "Client connected but lobby is not ready, probing the server failed, using next join method");
+            warn!(target: "lazymc", "The lobby requires a reachable server in offline mode ('online-mode=false'), check the probe errors above");
+        } else {
+            debug!(target: "lazymc", "Client connected but lobby is not ready, probing failed, using next join method");
+        }
+        return Ok(MethodResult::Continue(inbound));
+    }
+
     // Must be ready to lobby
     if must_still_probe(&config, &server).await {
         warn!(target: "lazymc", "Client connected but lobby is not ready, using next join method, probing not completed");
         return Ok(MethodResult::Continue(inbound));
     }
 
+    // Bundled dimension codec must match client version, if server codec is unknown
+    if config.join.lobby.strict_codec
+        && !has_probed_codec(&server).await
+        && !dimension::default_codec_matches(client_info.protocol())
+    {
+        warn!(target: "lazymc", "Client connected but lobby has no dimension codec for its Minecraft version, using next join method");
+        return Ok(MethodResult::Continue(inbound));
+    }
+
     // Start lobby
-    lobby::serve(client, client_info, inbound, config, server, inbound_queue).await?;
+    lobby::serve(
+        client,
+        client_info,
+        inbound,
+        config,
+        server,
+        inbound_queue,
+        LobbyWait::Server(timeout),
+    )
+    .await?;
 
     // TODO: do not consume client here, allow other join method on fail
 
@@ -36,11 +67,26 @@ pub async fn occupy(
 }
 
 /// Check whether we still have to probe before we can use the lobby.
-async fn must_still_probe(config: &Config, server: &Server) -> bool {
+pub async fn must_still_probe(config: &Config, server: &Server) -> bool {
     must_probe(config) && server.probed_join_game.read().await.is_none()
 }
 
+/// Check whether we have a dimension codec probed from the server.
+async fn has_probed_codec(server: &Server) -> bool {
+    server
+        .probed_join_game
+        .read()
+        .await
+        .as_ref()
+        .map(|join_game| join_game.dimension_codec.is_some())
+        .unwrap_or(false)
+}
+
 /// Check whether we must have probed data.
 fn must_probe(config: &Config) -> bool {
-    config.server.forge
+    match config.server.probe_mode() {
+        Probe::Auto => config.server.is_modded(),
+        Probe::Always => true,
+        Probe::Never => false,
+    }
 }
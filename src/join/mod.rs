@@ -5,14 +5,23 @@ use tokio::net::TcpStream;
 
 use crate::config::*;
 use crate::net;
+use crate::proto::action;
 use crate::proto::client::{Client, ClientInfo, ClientState};
+use crate::proxy::ProxyHeader;
 use crate::server::Server;
+use crate::service;
 
 pub mod forward;
 pub mod hold;
 pub mod kick;
 #[cfg(feature = "lobby")]
 pub mod lobby;
+#[cfg(feature = "lobby")]
+pub mod queue;
+
+/// Kick message for clients joining while the server is starting for another player.
+const EXCLUSIVE_START_MESSAGE: &str =
+    "Server is starting for another player.\n\nPlease try to reconnect in a minute.";
 
 /// A result returned by a join occupy method.
 pub enum MethodResult {
@@ -28,7 +37,7 @@ pub enum MethodResult {
 /// This assumes the login start packet has just been received.
 pub async fn occupy(
     client: Client,
-    #[allow(unused_variables)] client_info: ClientInfo,
+    client_info: ClientInfo,
     config: Arc<Config>,
     server: Arc<Server>,
     mut inbound: TcpStream,
@@ -42,27 +51,79 @@ pub async fn occupy(
         "when occupying client, it should be in login state"
     );
 
+    // Only allow the client that woke the server while starting, if exclusive
+    if !server
+        .may_join_starting(&config, client.peer.ip(), client_info.username.as_deref())
+        .await
+    {
+        info!(target: "lazymc", "Kicked {} because server is starting for another player", client_info.username.as_deref().unwrap_or("?"));
+        action::kick(&client, EXCLUSIVE_START_MESSAGE, &mut inbound.split().1).await?;
+        net::close_tcp_stream(inbound).await.map_err(|_| ())?;
+        return Ok(());
+    }
+
+    // Queue client if server is online and full, handled before any other method
+    #[cfg(feature = "lobby")]
+    if server.state() == crate::server::State::Started {
+        if let Some(method) = config
+            .join
+            .methods
+            .iter()
+            .find(|m| m.method() == Method::Queue)
+        {
+            let result = queue::occupy(
+                &client,
+                client_info.clone(),
+                config.clone(),
+                server.clone(),
+                inbound,
+                login_queue.clone(),
+                method.timeout().unwrap_or(config.join.queue.timeout),
+            )
+            .await?;
+            match result {
+                MethodResult::Consumed => return Ok(()),
+                MethodResult::Continue(stream) => inbound = stream,
+            }
+
+            // A slot became free in the meantime, proxy client
+            if !server.should_queue(&config).await {
+                service::server::route_proxy_queue(
+                    inbound,
+                    config,
+                    &server,
+                    client.peer,
+                    inbound_history,
+                );
+                return Ok(());
+            }
+        }
+    }
+
     // Go through all configured join methods
     for method in &config.join.methods {
         // Invoke method, take result
-        let result = match method {
+        let result = match method.method() {
             // Kick method, immediately kick client
             Method::Kick => kick::occupy(&client, &config, &server, inbound).await?,
 
             // Hold method, hold client connection while server starts
             Method::Hold => {
                 hold::occupy(
+                    &client,
+                    &client_info,
                     config.clone(),
                     server.clone(),
                     inbound,
                     &mut inbound_history,
+                    method.timeout().unwrap_or(config.join.hold.timeout),
                 )
                 .await?
             }
 
             // Forward method, forward client connection while server starts
             Method::Forward => {
-                forward::occupy(config.clone(), inbound, &mut inbound_history).await?
+                forward::occupy(&client, config.clone(), inbound, &mut inbound_history).await?
             }
 
             // Lobby method, keep client in lobby while server starts
@@ -75,6 +136,7 @@ pub async fn occupy(
                     server.clone(),
                     inbound,
                     login_queue.clone(),
+                    method.timeout().unwrap_or(config.join.lobby.timeout),
                 )
                 .await?
             }
@@ -85,6 +147,68 @@ pub async fn occupy(
                 error!(target: "lazymc", "Lobby join method not supported in this lazymc build");
                 MethodResult::Continue(inbound)
             }
+
+            // Hold then lobby method, hold client shortly, then move it into the lobby
+            #[cfg(feature = "lobby")]
+            Method::HoldThenLobby => {
+                let result = hold::occupy(
+                    &client,
+                    &client_info,
+                    config.clone(),
+                    server.clone(),
+                    inbound,
+                    &mut inbound_history,
+                    config.join.hold_then_lobby.hold_timeout,
+                )
+                .await?;
+
+                // Reuse the same connection in the lobby, the client is still in login state
+                // With keep-alive, the client already received login plugin requests. This relies
+                // on `HeldStream::drain` having consumed all responses, so the lobby won't see them
+                match result {
+                    MethodResult::Consumed => MethodResult::Consumed,
+                    MethodResult::Continue(inbound) => {
+                        debug!(target: "lazymc", "Server not ready for held client, moving it into lobby");
+                        lobby::occupy(
+                            &client,
+                            client_info.clone(),
+                            config.clone(),
+                            server.clone(),
+                            inbound,
+                            login_queue.clone(),
+                            method.timeout().unwrap_or(config.join.lobby.timeout),
+                        )
+                        .await?
+                    }
+                }
+            }
+
+            // Hold then lobby method, lobby not supported, only hold client
+            #[cfg(not(feature = "lobby"))]
+            Method::HoldThenLobby => {
+                error!(target: "lazymc", "Lobby join method not supported in this lazymc build, only holding client");
+                hold::occupy(
+                    &client,
+                    &client_info,
+                    config.clone(),
+                    server.clone(),
+                    inbound,
+                    &mut inbound_history,
+                    config.join.hold_then_lobby.hold_timeout,
+                )
+                .await?
+            }
+
+            // Queue method, only used while server is online, handled above
+            #[cfg(feature = "lobby")]
+            Method::Queue => MethodResult::Continue(inbound),
+
+            // Queue method, keep client in lobby while server is full
+            #[cfg(not(feature = "lobby"))]
+            Method::Queue => {
+                error!(target: "lazymc", "Queue join method not supported in this lazymc build");
+                MethodResult::Continue(inbound)
+            }
         };
 
         // Handle method result
@@ -97,7 +221,41 @@ pub async fn occupy(
         }
     }
 
-    debug!(target: "lazymc", "No method left to occupy joining client, disconnecting");
+    fallback(&client, &config, inbound, &mut inbound_history).await
+}
+
+/// Handle client with configured fallback action, when no join method occupied it.
+async fn fallback(
+    client: &Client,
+    config: &Config,
+    mut inbound: TcpStream,
+    inbound_history: &mut BytesMut,
+) -> Result<(), ()> {
+    match config.join.fallback.action {
+        FallbackAction::Disconnect => {
+            debug!(target: "lazymc", "No method left to occupy joining client, disconnecting");
+        }
+        FallbackAction::Kick => {
+            debug!(target: "lazymc", "No method left to occupy joining client, kicking");
+            action::kick(
+                client,
+                &config.join.fallback.message,
+                &mut inbound.split().1,
+            )
+            .await?;
+        }
+        FallbackAction::Forward => {
+            debug!(target: "lazymc", "No method left to occupy joining client, forwarding to {}", config.join.fallback.address);
+            service::server::route_proxy_address_queue(
+                inbound,
+                ProxyHeader::proxy(config.join.fallback.send_proxy(), client.peer),
+                vec![config.join.fallback.address],
+                inbound_history.clone(),
+                None,
+            );
+            return Ok(());
+        }
+    }
 
     // Gracefully close connection
     net::close_tcp_stream(inbound).await.map_err(|_| ())?;
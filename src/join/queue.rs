@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use bytes::BytesMut;
+use tokio::net::TcpStream;
+
+use crate::config::*;
+use crate::lobby::{self, LobbyWait};
+use crate::net;
+use crate::proto::action;
+use crate::proto::client::{Client, ClientInfo};
+use crate::server::{Server, State};
+
+use super::MethodResult;
+
+/// Kick message for clients joining a full server while the queue is not ready.
+const FULL_MESSAGE: &str = "Server is full.\n\nPlease try to reconnect in a minute.";
+
+/// Queue the client while the server is full.
+pub async fn occupy(
+    client: &Client,
+    client_info: ClientInfo,
+    config: Arc<Config>,
+    server: Arc<Server>,
+    mut inbound: TcpStream,
+    inbound_queue: BytesMut,
+    timeout: u32,
+) -> Result<MethodResult, ()> {
+    trace!(target: "lazymc", "Using queue method to occupy joining client");
+
+    // Server must be online and full
+    if server.state() != State::Started || !server.should_queue(&config).await {
+        return Ok(MethodResult::Continue(inbound));
+    }
+
+    // Must be ready to lobby, kick as full otherwise because other methods expect a starting server
+    if super::lobby::must_still_probe(&config, &server).await {
+        warn!(target: "lazymc", "Client connected but queue is not ready, kicking because server is full, probing not completed");
+        action::kick(client, FULL_MESSAGE, &mut inbound.split().1).await?;
+        net::close_tcp_stream(inbound).await.map_err(|_| ())?;
+        return Ok(MethodResult::Consumed);
+    }
+
+    // Queue client in lobby
+    let ticket = server.join_queue.join();
+    info!(target: "lazymc", "Server is full, queueing client at position {}", ticket.position());
+    lobby::serve(
+        client,
+        client_info,
+        inbound,
+        config,
+        server,
+        inbound_queue,
+        LobbyWait::Queue(ticket, timeout),
+    )
+    .await?;
+
+    Ok(MethodResult::Consumed)
+}
@@ -1,11 +1,14 @@
+use std::future::Future;
 use std::io::ErrorKind;
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
 use futures::FutureExt;
 use minecraft_protocol::decoder::Decoder;
+use minecraft_protocol::version::v1_14_4::handshake::Handshake;
 use minecraft_protocol::version::v1_14_4::login::{
     LoginPluginRequest, LoginPluginResponse, LoginStart, LoginSuccess, SetCompression,
 };
@@ -13,22 +16,31 @@ use tokio::io::AsyncWriteExt;
 use tokio::net::tcp::{ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
 use tokio::select;
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio::time;
 
 use crate::config::*;
 use crate::forge;
+#[cfg(feature = "forwarding")]
+use crate::forwarding;
 use crate::mc::uuid;
-use crate::net;
-use crate::proto;
+use crate::net::{self, OutboundPermit};
+use crate::proto::action;
 use crate::proto::client::{Client, ClientInfo, ClientState};
+use crate::proto::packet::RawPacket;
 use crate::proto::packets::play::join_game::JoinGameData;
+use crate::proto::unhandled::Unhandled;
 use crate::proto::{packet, packets};
 use crate::proxy;
-use crate::server::{Server, State};
+use crate::queue::{self, QueueTicket};
+use crate::server::{ActiveConnection, Server, State};
 
 /// Interval to send keep-alive packets at.
 pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
 
+/// Interval to update the queue position and to check for a free slot at, for queued clients.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 /// Timeout for creating new server connection for lobby client.
 const SERVER_CONNECT_TIMEOUT: Duration = Duration::from_secs(2 * 60);
 
@@ -38,6 +50,23 @@ const SERVER_CONNECT_TIMEOUT: Duration = Duration::from_secs(2 * 60);
 /// This defines the maximum timeout for waiting on it.
 const SERVER_JOIN_GAME_TIMEOUT: Duration = Duration::from_secs(20);
 
+/// Kick message shown when the maximum lobby session duration is reached.
+const MAX_DURATION_MESSAGE: &str =
+    "Server took too long to become ready.\n\nPlease try to reconnect in a minute.";
+
+/// Kick message shown when the client leaves the lobby through the cancel command.
+const CANCEL_MESSAGE: &str = "You left the lobby.";
+
+/// Kick message shown when the lobby client compression doesn't match the server.
+const COMPRESSION_MISMATCH_MESSAGE: &str =
+    "Server is now ready, but can't be joined from the lobby.\n\nPlease reconnect to join.";
+
+/// Chat command to leave the lobby.
+const CHAT_COMMAND_CANCEL: &str = "!cancel";
+
+/// Chat message sent in response to other chat, while waiting in the lobby.
+const CHAT_HELP_MESSAGE: &str = "Chat is not available while waiting. Type !cancel to leave.";
+
 /// Time to wait before responding to newly connected server.
 ///
 /// Notchian servers are slow, we must wait a little before sending play packets, because the
@@ -45,9 +74,33 @@ const SERVER_JOIN_GAME_TIMEOUT: Duration = Duration::from_secs(20);
 /// See warning at: <https://wiki.vg/Protocol#Login_Success>
 const SERVER_WARMUP: Duration = Duration::from_secs(1);
 
+/// Minimum client protocol version supported by the lobby, Minecraft 1.16.3.
+const PROTOCOL_MIN: i32 = 753;
+
+/// What a lobby client waits for.
+pub enum LobbyWait {
+    /// Wait for the server to come online, at most the given number of seconds.
+    Server(u32),
+
+    /// Wait for a free slot on the full server, showing the queue position. At most the given
+    /// number of seconds, no limit if 0.
+    Queue(QueueTicket, u32),
+}
+
+impl LobbyWait {
+    /// Get the queue ticket, if queued.
+    fn ticket(&self) -> Option<&QueueTicket> {
+        match self {
+            Self::Server(_) => None,
+            Self::Queue(ticket, _) => Some(ticket),
+        }
+    }
+}
+
 /// Serve lobby service for given client connection.
 ///
-/// The client must be in the login state, or this will error.
+/// The client must be in the login state, or this will error. The client waits for the server as
+/// defined by `wait`.
 // TODO: do not drop error here, return Box<dyn Error>
 // TODO: on error, nicely kick client with message
 pub async fn serve(
@@ -57,6 +110,7 @@ pub async fn serve(
     config: Arc<Config>,
     server: Arc<Server>,
     queue: BytesMut,
+    wait: LobbyWait,
 ) -> Result<(), ()> {
     let (mut reader, mut writer) = inbound.split();
 
@@ -74,18 +128,19 @@ pub async fn serve(
 
     // Incoming buffer
     let mut inbound_buf = queue;
+    let mut unhandled = Unhandled::new("lazymc", "lobby");
 
     loop {
         // Read packet from stream
-        let (packet, _raw) = match packet::read_packet(client, &mut inbound_buf, &mut reader).await
-        {
-            Ok(Some(packet)) => packet,
-            Ok(None) => break,
-            Err(_) => {
-                error!(target: "lazymc", "Closing connection, error occurred");
-                break;
-            }
-        };
+        let (packet, _raw) =
+            match packet::read_inbound_packet(client, &mut inbound_buf, &mut reader).await {
+                Ok(Some(packet)) => packet,
+                Ok(None) => break,
+                Err(_) => {
+                    error!(target: "lazymc", "Closing connection, error occurred");
+                    break;
+                }
+            };
 
         // Grab client state
         let client_state = client.state();
@@ -97,19 +152,23 @@ pub async fn serve(
 
             debug!(target: "lazymc::lobby", "Login on lobby server (user: {})", login_start.name);
 
+            let started = Instant::now();
+
             // Replay Forge payload
-            if config.server.forge {
+            if config.server.is_modded() {
                 forge::replay_login_payload(client, &mut inbound, server.clone(), &mut inbound_buf)
                     .await?;
-                let (_returned_reader, returned_writer) = inbound.split();
+                let (returned_reader, returned_writer) = inbound.split();
+                reader = returned_reader;
                 writer = returned_writer;
             }
 
-            // Respond with set compression if compression is enabled based on threshold
-            if proto::COMPRESSION_THRESHOLD >= 0 {
-                trace!(target: "lazymc::lobby", "Enabling compression for lobby client because server has it enabled (threshold: {})", proto::COMPRESSION_THRESHOLD);
-                respond_set_compression(client, &mut writer, proto::COMPRESSION_THRESHOLD).await?;
-                client.set_compression(proto::COMPRESSION_THRESHOLD);
+            // Respond with set compression if compression is enabled, adopt server threshold
+            let threshold = server.compression_threshold();
+            if threshold >= 0 {
+                trace!(target: "lazymc::lobby", "Enabling compression for lobby client because server has it enabled (threshold: {})", threshold);
+                respond_set_compression(client, &mut writer, threshold).await?;
+                client.set_compression(threshold);
             }
 
             // Respond with login success, switch to play state
@@ -119,33 +178,83 @@ pub async fn serve(
             trace!(target: "lazymc::lobby", "Client login success, sending required play packets for lobby world");
 
             // Send packets to client required to get into workable play state for lobby world
-            send_lobby_play_packets(client, &client_info, &mut writer, &server).await?;
+            send_lobby_play_packets(client, &client_info, &mut writer, &config, &server).await?;
+
+            // Enforce maximum lobby session duration across all following stages
+            let deadline = match config.join.lobby.max_duration {
+                0 => None,
+                max => Some(started + Duration::from_secs(max as u64)),
+            };
 
             // Wait for server to come online
-            stage_wait(client, &client_info, &server, &config, &mut writer).await?;
+            let waited = within_session(
+                deadline,
+                stage_wait(
+                    client,
+                    &client_info,
+                    &server,
+                    &config,
+                    &mut reader,
+                    &mut writer,
+                    &wait,
+                ),
+            )
+            .await?;
+            if waited.is_none() {
+                return kick_session_expired(client, &config, &mut writer).await;
+            }
 
             // Start new connection to server
             let server_client_info = client_info.clone();
-            let (server_client, mut outbound, mut server_buf) =
-                connect_to_server(&server_client_info, &inbound, &config).await?;
+            let connected = within_session(
+                deadline,
+                connect_to_server(&server_client_info, client.peer, &inbound, &config, &server),
+            )
+            .await?;
             let (returned_reader, returned_writer) = inbound.split();
             reader = returned_reader;
             writer = returned_writer;
+            let (server_client, mut outbound, mut server_buf, permit) = match connected {
+                Some(connected) => connected,
+                None => return kick_session_expired(client, &config, &mut writer).await,
+            };
+
+            // Client can't decode the server stream if compression doesn't match, kick it
+            // The server threshold is remembered, so the client can join when reconnecting
+            if server_client.compressed() != client.compressed() {
+                error!(
+                    target: "lazymc::lobby",
+                    "Compression threshold of lobby client does not match threshold from server, kicking client (client: {}, server: {})",
+                    client.compressed(),
+                    server_client.compressed()
+                );
+                return action::kick(client, COMPRESSION_MISMATCH_MESSAGE, &mut writer).await;
+            }
 
             // Grab join game packet from server
-            let join_game_data = wait_for_server_join_game(
-                &server_client,
-                &server_client_info,
-                &mut outbound,
-                &mut server_buf,
+            let join_game_data = match within_session(
+                deadline,
+                wait_for_server_join_game(
+                    &server_client,
+                    &server_client_info,
+                    &mut outbound,
+                    &mut server_buf,
+                ),
             )
-            .await?;
+            .await?
+            {
+                Some(join_game_data) => join_game_data,
+                None => return kick_session_expired(client, &config, &mut writer).await,
+            };
 
             // Reset lobby title
-            packets::play::title::send(client, &client_info, &mut writer, "").await?;
+            send_message(client, &client_info, &mut writer, &config, "").await?;
 
-            // Play ready sound if configured
+            // Play ready sound and send ready message if configured
             play_lobby_ready_sound(client, &client_info, &mut writer, &config).await?;
+            if let Some(msg) = config.join.lobby.ready_message.as_ref() {
+                send_chat(client, &client_info, &mut writer, msg).await?;
+            }
 
             // Wait a second because Notchian servers are slow
             // See: https://wiki.vg/Protocol#Login_Success
@@ -163,16 +272,16 @@ pub async fn serve(
             drain_stream(&mut reader).await?;
 
             // Client and server connection ready now, move client to proxy
-            debug!(target: "lazymc::lobby", "Server connection ready, relaying lobby client to proxy");
-            route_proxy(inbound, outbound, server_buf);
+            let waited = started.elapsed();
+            server.metrics.lobby_wait.observe(waited);
+            info!(target: "lazymc::lobby", "Server connection ready, relaying lobby client to proxy (waited {:.1}s)", waited.as_secs_f32());
+            let connection = Server::track_connection(&server);
+            route_proxy(inbound, outbound, server_buf, permit, connection);
 
             return Ok(());
         }
 
-        // Show unhandled packet warning
-        debug!(target: "lazymc", "Got unhandled packet:");
-        debug!(target: "lazymc", "- State: {:?}", client_state);
-        debug!(target: "lazymc", "- Packet ID: 0x{:02X} ({})", packet.id, packet.id);
+        unhandled.record(client_state, packet.id);
     }
 
     // Gracefully close connection
@@ -181,6 +290,32 @@ pub async fn serve(
     Ok(())
 }
 
+/// Run a lobby session stage, stop it if the session deadline is reached.
+///
+/// Returns `Ok(None)` if the deadline was reached before the stage completed.
+async fn within_session<T>(
+    deadline: Option<Instant>,
+    stage: impl Future<Output = Result<T, ()>>,
+) -> Result<Option<T>, ()> {
+    match deadline {
+        Some(deadline) => match time::timeout_at(deadline.into(), stage).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => stage.await.map(Some),
+    }
+}
+
+/// Kick lobby client because the maximum lobby session duration was reached.
+async fn kick_session_expired(
+    client: &Client,
+    config: &Config,
+    writer: &mut WriteHalf<'_>,
+) -> Result<(), ()> {
+    warn!(target: "lazymc::lobby", "Lobby client reached maximum session duration of {}s, kicking", config.join.lobby.max_duration);
+    action::kick(client, MAX_DURATION_MESSAGE, writer).await
+}
+
 /// Respond to client with a set compression packet.
 async fn respond_set_compression(
     client: &Client,
@@ -223,7 +358,8 @@ async fn play_lobby_ready_sound(
         }
 
         // Play sound effect
-        packets::play::player_pos::send(client, client_info, writer).await?;
+        packets::play::player_pos::send(client, client_info, writer, &config.join.lobby.spawn)
+            .await?;
         packets::play::sound::send(client, client_info, writer, sound_name).await?;
     }
 
@@ -235,18 +371,20 @@ async fn send_lobby_play_packets(
     client: &Client,
     client_info: &ClientInfo,
     writer: &mut WriteHalf<'_>,
+    config: &Config,
     server: &Server,
 ) -> Result<(), ()> {
     // See: https://wiki.vg/Protocol_FAQ#What.27s_the_normal_login_sequence_for_a_client.3F
 
     // Send initial game join
-    packets::play::join_game::lobby_send(client, client_info, writer, server).await?;
+    let game_mode = config.join.lobby.gamemode.id();
+    packets::play::join_game::lobby_send(client, client_info, writer, server, game_mode).await?;
 
     // Send server brand
     packets::play::server_brand::send(client, client_info, writer).await?;
 
     // Send spawn and player position, disables 'download terrain' screen
-    packets::play::player_pos::send(client, client_info, writer).await?;
+    packets::play::player_pos::send(client, client_info, writer, &config.join.lobby.spawn).await?;
 
     // Notify client of world time, required once before keep-alive packets
     packets::play::time_update::send(client, client_info, writer).await?;
@@ -257,49 +395,215 @@ async fn send_lobby_play_packets(
 /// An infinite keep-alive loop.
 ///
 /// This will keep sending keep-alive and title packets to the client until it is dropped.
+/// Queued clients are sent their queue position, more frequently. Server console lines are sent
+/// as chat messages if subscribed. Client chat commands are handled.
+///
+/// Returns `Err(())` if the client disconnected, left the lobby, or was idle for too long.
 async fn keep_alive_loop(
     client: &Client,
     client_info: &ClientInfo,
+    reader: &mut ReadHalf<'_>,
     writer: &mut WriteHalf<'_>,
     config: &Config,
+    ticket: Option<&QueueTicket>,
+    mut console: Option<broadcast::Receiver<String>>,
 ) -> Result<(), ()> {
-    let mut interval = time::interval(KEEP_ALIVE_INTERVAL);
+    let mut interval = time::interval(match ticket {
+        Some(_) => QUEUE_POLL_INTERVAL,
+        None => KEEP_ALIVE_INTERVAL,
+    });
+    let mut buf = BytesMut::new();
+
+    // Idle deadline, only reset when the client sends something
+    let idle_timeout = Duration::from_secs(config.join.lobby.idle_timeout as u64);
+    let idle = time::sleep_until(time::Instant::now() + idle_timeout);
+    tokio::pin!(idle);
 
     loop {
-        interval.tick().await;
+        // Forward console lines and handle client packets until next keep-alive
+        select! {
+            _ = interval.tick() => {},
+            _ = &mut idle, if !idle_timeout.is_zero() => {
+                info!(target: "lazymc::lobby", "Lobby client sent nothing for {}s, disconnecting idle client", idle_timeout.as_secs());
+                return Err(());
+            }
+            line = next_console_line(&mut console) => {
+                send_chat(client, client_info, writer, &line).await?;
+                continue;
+            }
+            packet = read_client_packet(client, reader, &mut buf) => {
+                let packet = packet?;
+                idle.as_mut().reset(time::Instant::now() + idle_timeout);
+                handle_client_packet(client, client_info, writer, config, &packet).await?;
+                continue;
+            }
+        }
 
         trace!(target: "lazymc::lobby", "Sending keep-alive sequence to lobby client");
 
         // Send keep alive and title packets
         packets::play::keep_alive::send(client, client_info, writer).await?;
-        packets::play::title::send(client, client_info, writer, &config.join.lobby.message).await?;
+        let message = match ticket {
+            Some(ticket) => config
+                .join
+                .queue
+                .message
+                .replace("{position}", &ticket.position().to_string()),
+            None => config.join.lobby.message.clone(),
+        };
+        send_message(client, client_info, writer, config, &message).await?;
 
         // TODO: verify we receive correct keep alive response
     }
 }
 
+/// Receive the next server console line.
+///
+/// Never returns if not subscribed or if the console is closed. Skips lines we lagged behind on.
+async fn next_console_line(console: &mut Option<broadcast::Receiver<String>>) -> String {
+    if let Some(console) = console {
+        loop {
+            match console.recv().await {
+                Ok(line) => return line,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+    futures::future::pending().await
+}
+
+/// Send system chat message to client.
+///
+/// Does nothing if not supported for the protocol version used by the client.
+async fn send_chat(
+    client: &Client,
+    client_info: &ClientInfo,
+    writer: &mut WriteHalf<'_>,
+    msg: &str,
+) -> Result<(), ()> {
+    if let Some(packet) = packets::play::chat::system_message(client_info, msg) {
+        let raw = packet.encode_with_len(client)?;
+        writer.write_all(&raw).await.map_err(|_| ())?;
+    }
+    Ok(())
+}
+
+/// Handle packet received from lobby client while waiting.
+///
+/// Responds to chat messages if chat commands are enabled, other packets are voided. Returns
+/// `Err(())` if the client left the lobby.
+async fn handle_client_packet(
+    client: &Client,
+    client_info: &ClientInfo,
+    writer: &mut WriteHalf<'_>,
+    config: &Config,
+    packet: &RawPacket,
+) -> Result<(), ()> {
+    if !config.join.lobby.chat_commands {
+        return Ok(());
+    }
+    let msg = match packets::play::chat::parse_message(client_info, packet) {
+        Some(msg) => msg,
+        None => return Ok(()),
+    };
+
+    if msg.trim() == CHAT_COMMAND_CANCEL {
+        info!(target: "lazymc::lobby", "Lobby client left the lobby through chat command");
+        action::kick(client, CANCEL_MESSAGE, writer).await?;
+        return Err(());
+    }
+
+    send_chat(client, client_info, writer, CHAT_HELP_MESSAGE).await
+}
+
+/// Send lobby message banner to client, as title or action bar as configured.
+///
+/// An empty string resets the banner.
+async fn send_message(
+    client: &Client,
+    client_info: &ClientInfo,
+    writer: &mut WriteHalf<'_>,
+    config: &Config,
+    text: &str,
+) -> Result<(), ()> {
+    if config.join.lobby.use_action_bar {
+        packets::play::action_bar::send(client, client_info, writer, text).await
+    } else {
+        packets::play::title::send(client, client_info, writer, text).await
+    }
+}
+
 /// Waiting stage.
 ///
-/// In this stage we wait for the server to come online.
+/// In this stage we wait for the server to come online, or for a free slot if queued.
 ///
 /// During this stage we keep sending keep-alive and title packets to the client to keep it active.
+/// Idle clients are disconnected if configured.
 async fn stage_wait(
     client: &Client,
     client_info: &ClientInfo,
     server: &Server,
     config: &Config,
+    reader: &mut ReadHalf<'_>,
     writer: &mut WriteHalf<'_>,
+    wait: &LobbyWait,
 ) -> Result<(), ()> {
+    let task_wait = async {
+        match wait {
+            LobbyWait::Server(timeout) => wait_for_server(server, *timeout).await,
+            LobbyWait::Queue(ticket, timeout) => {
+                wait_for_slot(config, server, ticket, *timeout).await
+            }
+        }
+    };
+
+    // Show server console to OPs waiting for the server, if enabled
+    let console = match (wait, client_info.username.as_deref()) {
+        (LobbyWait::Server(_), Some(username))
+            if config.join.lobby.console_ops && server.is_op(username).await =>
+        {
+            debug!(target: "lazymc::lobby", "Showing server console to OP '{}' in lobby", username);
+            Some(server.console.subscribe())
+        }
+        _ => None,
+    };
+
+    // Welcome client in lobby chat
+    if let Some(msg) = config.join.lobby.welcome_message.as_ref() {
+        send_chat(client, client_info, writer, msg).await?;
+    }
+
     select! {
-        a = keep_alive_loop(client, client_info, writer, config) => a,
-        b = wait_for_server(server, config) => b,
+        a = keep_alive_loop(client, client_info, reader, writer, config, wait.ticket(), console) => a,
+        b = task_wait => b,
+    }
+}
+
+/// Read the next packet from the lobby client.
+///
+/// Returns `Err(())` if the client disconnected.
+///
+/// Remaining client data is drained before relaying to the server anyway.
+async fn read_client_packet(
+    client: &Client,
+    reader: &mut ReadHalf<'_>,
+    buf: &mut BytesMut,
+) -> Result<RawPacket, ()> {
+    match packet::read_inbound_packet(client, buf, reader).await {
+        Ok(Some((packet, _))) => Ok(packet),
+        Ok(None) | Err(_) => {
+            debug!(target: "lazymc::lobby", "Lobby client disconnected while waiting");
+            Err(())
+        }
     }
 }
 
 /// Wait for the server to come online.
 ///
-/// Returns `Ok(())` once the server is online, returns `Err(())` if waiting failed.
-async fn wait_for_server(server: &Server, config: &Config) -> Result<(), ()> {
+/// Returns `Ok(())` once the server is online, returns `Err(())` if waiting failed or if the
+/// timeout in seconds is reached.
+async fn wait_for_server(server: &Server, timeout: u32) -> Result<(), ()> {
     debug!(target: "lazymc::lobby", "Waiting on server to come online...");
 
     // A task to wait for suitable server state
@@ -331,7 +635,7 @@ async fn wait_for_server(server: &Server, config: &Config) -> Result<(), ()> {
     };
 
     // Wait for server state with timeout
-    let timeout = Duration::from_secs(config.join.lobby.timeout as u64);
+    let timeout = Duration::from_secs(timeout as u64);
     match time::timeout(timeout, task_wait).await {
         // Relay client to proxy
         Ok(true) => {
@@ -351,17 +655,68 @@ async fn wait_for_server(server: &Server, config: &Config) -> Result<(), ()> {
     Err(())
 }
 
+/// Wait for a free slot on the full server.
+///
+/// Returns `Ok(())` once the queued client may join, returns `Err(())` if the server went offline
+/// or if the timeout in seconds is reached. There is no timeout if it is 0.
+async fn wait_for_slot(
+    config: &Config,
+    server: &Server,
+    ticket: &QueueTicket,
+    timeout: u32,
+) -> Result<(), ()> {
+    debug!(target: "lazymc::lobby", "Waiting for free slot on server...");
+    let cooldown = queue::admit_cooldown(config);
+
+    // A task to wait for a free slot, returns false if the server goes offline
+    let task_wait = async {
+        let mut interval = time::interval(QUEUE_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if server.state() != State::Started {
+                break false;
+            }
+            if !server.is_full().await && ticket.try_admit(cooldown) {
+                break true;
+            }
+        }
+    };
+
+    // Wait for free slot with optional timeout
+    let result = match timeout {
+        0 => Ok(task_wait.await),
+        timeout => time::timeout(Duration::from_secs(timeout as u64), task_wait).await,
+    };
+    match result {
+        Ok(true) => {
+            debug!(target: "lazymc::lobby", "Free slot on server for queued client");
+            Ok(())
+        }
+        Ok(false) => {
+            warn!(target: "lazymc::lobby", "Server went offline for queued client, disconnecting");
+            Err(())
+        }
+        Err(_) => {
+            warn!(target: "lazymc::lobby", "Queued client reached timeout of {}s", timeout);
+            Err(())
+        }
+    }
+}
+
 /// Create connection to the server, with timeout.
 ///
 /// This will initialize the connection to the play state. Client details are used.
 async fn connect_to_server(
     client_info: &ClientInfo,
+    peer: SocketAddr,
     inbound: &TcpStream,
     config: &Config,
-) -> Result<(Client, TcpStream, BytesMut), ()> {
+    server: &Server,
+) -> Result<(Client, TcpStream, BytesMut, OutboundPermit), ()> {
     time::timeout(
         SERVER_CONNECT_TIMEOUT,
-        connect_to_server_no_timeout(client_info, inbound, config),
+        connect_to_server_no_timeout(client_info, peer, inbound, config, server),
     )
     .await
     .map_err(|_| {
@@ -375,20 +730,22 @@ async fn connect_to_server(
 // TODO: clean this up
 async fn connect_to_server_no_timeout(
     client_info: &ClientInfo,
+    peer: SocketAddr,
     inbound: &TcpStream,
     config: &Config,
-) -> Result<(Client, TcpStream, BytesMut), ()> {
+    server: &Server,
+) -> Result<(Client, TcpStream, BytesMut, OutboundPermit), ()> {
     // Open connection
     // TODO: on connect fail, ping server and redirect to serve_status if offline
-    let mut outbound = TcpStream::connect(config.server.address)
+    let (mut outbound, permit) = net::connect_first(&config.server.addresses)
         .await
         .map_err(|_| ())?;
 
     // Add proxy header
-    if config.server.send_proxy_v2 {
+    if let Some(version) = config.server.send_proxy().version() {
         trace!(target: "lazymc::lobby", "Sending client proxy header for server connection");
         outbound
-            .write_all(&proxy::stream_proxy_header(inbound).map_err(|_| ())?)
+            .write_all(&proxy::stream_proxy_header(inbound, peer, version).map_err(|_| ())?)
             .await
             .map_err(|_| ())?;
     }
@@ -402,31 +759,24 @@ async fn connect_to_server_no_timeout(
 
     let (mut reader, mut writer) = outbound.split();
 
-    // Replay client handshake packet
-    assert_eq!(
-        client_info.handshake.as_ref().unwrap().next_state,
-        ClientState::Login.to_id(),
-        "Client handshake should have login as next state"
-    );
-    packet::write_packet(
-        client_info.handshake.clone().unwrap(),
-        &tmp_client,
-        &mut writer,
-    )
-    .await?;
+    // Player info to forward to server
+    let username = client_info.username.clone().ok_or(())?;
+    #[cfg(feature = "forwarding")]
+    let player = forwarding::PlayerInfo::new(peer.ip(), username.clone());
+
+    // Replay client handshake packet, server must see the real client protocol version
+    #[allow(unused_mut)]
+    let mut handshake = client_handshake(client_info)?;
+    #[cfg(feature = "forwarding")]
+    forwarding::rewrite_handshake(config, &mut handshake, &player);
+    packet::write_packet(handshake, &tmp_client, &mut writer).await?;
 
     // Request login start
-    packet::write_packet(
-        LoginStart {
-            name: client_info.username.clone().ok_or(())?,
-        },
-        &tmp_client,
-        &mut writer,
-    )
-    .await?;
+    packet::write_packet(LoginStart { name: username }, &tmp_client, &mut writer).await?;
 
     // Incoming buffer
     let mut buf = BytesMut::new();
+    let mut unhandled = Unhandled::new("lazymc::lobby", "lobby connect_to_server");
 
     loop {
         // Read packet from stream
@@ -449,15 +799,8 @@ async fn connect_to_server_no_timeout(
             let set_compression =
                 SetCompression::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
 
-            // Client and server compression threshold should match, show warning if not
-            if set_compression.threshold != proto::COMPRESSION_THRESHOLD {
-                error!(
-                    target: "lazymc::lobby",
-                    "Compression threshold sent to lobby client does not match threshold from server, this may cause errors (client: {}, server: {})",
-                    proto::COMPRESSION_THRESHOLD,
-                    set_compression.threshold
-                );
-            }
+            // Remember server threshold so next lobby clients adopt it
+            server.set_compression_threshold(set_compression.threshold);
 
             // Set client compression
             tmp_client.set_compression(set_compression.threshold);
@@ -470,7 +813,7 @@ async fn connect_to_server_no_timeout(
         {
             error!(
                 target: "lazymc::lobby",
-                "Got encryption request from server, this is unsupported. Server must be in offline mode to use lobby.",
+                "Got encryption request from server, this is unsupported. Server must be in offline mode to use lobby, lazymc can't authenticate with Mojang on behalf of the client.",
             );
 
             break;
@@ -486,8 +829,16 @@ async fn connect_to_server_no_timeout(
                     dbg!(err);
                 })?;
 
+            // Respond with forwarded player info
+            #[cfg(feature = "forwarding")]
+            if let Some(response) = forwarding::plugin_response(config, &plugin_request, &player)? {
+                trace!(target: "lazymc::lobby", "Got player info request from server, forwarding player info");
+                packet::write_packet(response, &tmp_client, &mut writer).await?;
+                continue;
+            }
+
             // Respond with Forge messages
-            if config.server.forge {
+            if config.server.is_modded() && forge::is_login_wrapper(&plugin_request) {
                 trace!(target: "lazymc::lobby", "Got login plugin request from server, responding with Forge reply");
 
                 // Respond to Forge login plugin request
@@ -528,12 +879,12 @@ async fn connect_to_server_no_timeout(
             // Switch to play state
             tmp_client.set_state(ClientState::Play);
 
-            // Server must enable compression if enabled for client, show warning otherwise
-            if tmp_client.is_compressed() != (proto::COMPRESSION_THRESHOLD >= 0) {
-                error!(target: "lazymc::lobby", "Compression enabled for lobby client while the server did not, this will cause errors");
+            // Server didn't enable compression, remember so next lobby clients don't either
+            if !tmp_client.is_compressed() {
+                server.set_compression_threshold(-1);
             }
 
-            return Ok((tmp_client, outbound, buf));
+            return Ok((tmp_client, outbound, buf, permit));
         }
 
         // Hijack disconnect
@@ -551,10 +902,7 @@ async fn connect_to_server_no_timeout(
             break;
         }
 
-        // Show unhandled packet warning
-        debug!(target: "lazymc::lobby", "Got unhandled packet from server in connect_to_server:");
-        debug!(target: "lazymc::lobby", "- State: {:?}", client_state);
-        debug!(target: "lazymc::lobby", "- Packet ID: 0x{:02X} ({})", packet.id, packet.id);
+        unhandled.record(client_state, packet.id);
     }
 
     // Gracefully close connection
@@ -563,6 +911,29 @@ async fn connect_to_server_no_timeout(
     Err(())
 }
 
+/// Get the original client handshake to replay to the server.
+///
+/// The handshake is replayed unchanged, so the server receives the protocol version of the real
+/// client rather than a configured one. Errors if the handshake is missing, isn't for login, or is
+/// for a protocol version older than the lobby supports.
+fn client_handshake(client_info: &ClientInfo) -> Result<Handshake, ()> {
+    let handshake = client_info.handshake.clone().ok_or_else(|| {
+        error!(target: "lazymc::lobby", "Client handshake unknown, cannot replay to server");
+    })?;
+
+    if ClientState::from_id(handshake.next_state) != Some(ClientState::Login) {
+        error!(target: "lazymc::lobby", "Client handshake should have login as next state, cannot replay to server");
+        return Err(());
+    }
+
+    if handshake.protocol_version < PROTOCOL_MIN {
+        error!(target: "lazymc::lobby", "Client handshake protocol version {} is not supported by lobby, cannot replay to server", handshake.protocol_version);
+        return Err(());
+    }
+
+    Ok(handshake)
+}
+
 /// Wait for join game packet on server connection, with timeout.
 ///
 /// This parses, consumes and returns the packet.
@@ -594,6 +965,7 @@ async fn wait_for_server_join_game_no_timeout(
     buf: &mut BytesMut,
 ) -> Result<JoinGameData, ()> {
     let (mut reader, mut _writer) = outbound.split();
+    let mut unhandled = Unhandled::new("lazymc::lobby", "lobby wait_for_server_join_game");
 
     loop {
         // Read packet from stream
@@ -616,9 +988,7 @@ async fn wait_for_server_join_game_no_timeout(
             return Ok(join_game_data);
         }
 
-        // Show unhandled packet warning
-        debug!(target: "lazymc::lobby", "Got unhandled packet from server in wait_for_server_join_game:");
-        debug!(target: "lazymc::lobby", "- Packet ID: 0x{:02X} ({})", packet.id, packet.id);
+        unhandled.record(ClientState::Play, packet.id);
     }
 
     // Gracefully close connection
@@ -630,11 +1000,19 @@ async fn wait_for_server_join_game_no_timeout(
 /// Route our lobby client through the proxy to the real server, spawning a new task.
 ///
 /// `inbound_queue` is used for data already received from the server, that needs to be pushed to
-/// the client.
+/// the client. The outbound connection permit and active connection are held until proxying ends.
 #[inline]
-pub fn route_proxy(inbound: TcpStream, outbound: TcpStream, inbound_queue: BytesMut) {
+pub fn route_proxy(
+    inbound: TcpStream,
+    outbound: TcpStream,
+    inbound_queue: BytesMut,
+    permit: OutboundPermit,
+    connection: ActiveConnection,
+) {
     // When server is online, proxy all
     let service = async move {
+        let _permit = permit;
+        let _connection = connection;
         proxy::proxy_inbound_outbound_with_queue(inbound, outbound, &inbound_queue, &[])
             .map(|r| {
                 if let Err(err) = r {
@@ -662,3 +1040,40 @@ async fn drain_stream(reader: &mut ReadHalf<'_>) -> Result<(), ()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Client info with a handshake for the given protocol version and next state.
+    fn client_info(protocol_version: i32, next_state: ClientState) -> ClientInfo {
+        ClientInfo {
+            handshake: Some(Handshake {
+                protocol_version,
+                server_addr: "localhost".into(),
+                server_port: 25565,
+                next_state: next_state.to_id(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn client_handshake_supported() {
+        let info = client_info(756, ClientState::Login);
+        assert_eq!(client_handshake(&info).unwrap().protocol_version, 756);
+        assert!(client_handshake(&client_info(PROTOCOL_MIN, ClientState::Login)).is_ok());
+    }
+
+    #[test]
+    fn client_handshake_too_old() {
+        assert!(client_handshake(&client_info(PROTOCOL_MIN - 1, ClientState::Login)).is_err());
+        assert!(client_handshake(&client_info(-1, ClientState::Login)).is_err());
+    }
+
+    #[test]
+    fn client_handshake_invalid() {
+        assert!(client_handshake(&client_info(756, ClientState::Status)).is_err());
+        assert!(client_handshake(&ClientInfo::default()).is_err());
+    }
+}
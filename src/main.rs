@@ -8,19 +8,30 @@ extern crate derive_builder;
 extern crate log;
 
 pub(crate) mod action;
+#[cfg(feature = "rcon")]
+pub(crate) mod afk;
 pub(crate) mod cli;
 pub(crate) mod config;
+pub(crate) mod console;
 pub(crate) mod forge;
+#[cfg(feature = "forwarding")]
+pub(crate) mod forwarding;
+pub(crate) mod hooks;
 pub(crate) mod join;
 #[cfg(feature = "lobby")]
 pub(crate) mod lobby;
 pub(crate) mod mc;
+pub(crate) mod metrics;
 pub(crate) mod monitor;
 pub(crate) mod net;
 pub(crate) mod os;
+pub(crate) mod peers;
+pub(crate) mod preflight;
 pub(crate) mod probe;
 pub(crate) mod proto;
 pub(crate) mod proxy;
+pub(crate) mod queue;
+pub(crate) mod ratelimit;
 pub(crate) mod server;
 pub(crate) mod service;
 pub(crate) mod status;
@@ -78,9 +89,20 @@ fn invoke_action(app: Command) -> Result<(), ()> {
             return Ok(());
         }
 
+        if let Some(matches) = matches.subcommand_matches("schema") {
+            action::config_schema::invoke(matches);
+            return Ok(());
+        }
+
         unreachable!();
     }
 
+    // Test lobby flow
+    #[cfg(feature = "dev")]
+    if matches.subcommand_matches("lobby-test").is_some() {
+        return action::lobby_test::invoke(&matches);
+    }
+
     // Start server
     action::start::invoke(&matches)
 }
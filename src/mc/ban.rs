@@ -10,9 +10,28 @@ use serde::Deserialize;
 /// File name.
 pub const FILE: &str = "banned-ips.json";
 
+/// Banned players file name.
+pub const PLAYERS_FILE: &str = "banned-players.json";
+
 /// The forever expiry literal.
 const EXPIRY_FOREVER: &str = "forever";
 
+/// Default ban reason if unknown.
+pub const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
+
+/// Action to take for a connecting client, based on bans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BanAction {
+    /// Not banned, allow the client.
+    Allow,
+
+    /// Banned, drop the connection without a message.
+    Drop(String),
+
+    /// Banned, kick the client on login with the given ban reason.
+    Kick(String),
+}
+
 /// List of banned IPs.
 #[derive(Debug, Default)]
 pub struct BannedIps {
@@ -58,32 +77,92 @@ pub struct BannedIp {
 }
 
 impl BannedIp {
+    /// Get the ban reason, or the default reason if unknown.
+    pub fn reason(&self) -> &str {
+        self.reason.as_deref().unwrap_or(DEFAULT_BAN_REASON)
+    }
+
     /// Check if this entry is currently banned.
     pub fn is_banned(&self) -> bool {
-        // Get expiry time
-        let expires = match &self.expires {
-            Some(expires) => expires,
-            None => return true,
-        };
-
-        // If expiry is forever, the user is banned
-        if expires.trim().to_lowercase() == EXPIRY_FOREVER {
-            return true;
-        }
+        is_active(self.expires.as_deref())
+    }
+}
 
-        // Parse expiry time, check if it has passed
-        let expiry = match DateTime::parse_from_str(expires, "%Y-%m-%d %H:%M:%S %z") {
-            Ok(expiry) => expiry,
-            Err(err) => {
-                error!(target: "lazymc", "Failed to parse ban expiry '{}', assuming still banned: {}", expires, err);
-                return true;
-            }
-        };
+/// List of banned players.
+#[derive(Debug, Default)]
+pub struct BannedPlayers {
+    /// List of banned players, by lowercase username.
+    players: HashMap<String, BannedPlayer>,
+}
 
-        expiry > Utc::now()
+impl BannedPlayers {
+    /// Get ban entry of player if it exists, username is case insensitive.
+    ///
+    /// This uses the latest known `banned-players.json` contents if known.
+    pub fn get(&self, username: &str) -> Option<BannedPlayer> {
+        self.players.get(&username.to_lowercase()).cloned()
     }
 }
 
+/// A banned player entry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BannedPlayer {
+    /// Banned player UUID.
+    pub uuid: Option<String>,
+
+    /// Banned player username.
+    pub name: String,
+
+    /// Ban creation time.
+    pub created: Option<String>,
+
+    /// Ban source.
+    pub source: Option<String>,
+
+    /// Ban expiry time.
+    pub expires: Option<String>,
+
+    /// Ban reason.
+    pub reason: Option<String>,
+}
+
+impl BannedPlayer {
+    /// Get the ban reason, or the default reason if unknown.
+    pub fn reason(&self) -> &str {
+        self.reason.as_deref().unwrap_or(DEFAULT_BAN_REASON)
+    }
+
+    /// Check if this entry is currently banned.
+    pub fn is_banned(&self) -> bool {
+        is_active(self.expires.as_deref())
+    }
+}
+
+/// Check if a ban with the given expiry time is currently active.
+fn is_active(expires: Option<&str>) -> bool {
+    // Get expiry time
+    let expires = match expires {
+        Some(expires) => expires,
+        None => return true,
+    };
+
+    // If expiry is forever, the user is banned
+    if expires.trim().to_lowercase() == EXPIRY_FOREVER {
+        return true;
+    }
+
+    // Parse expiry time, check if it has passed
+    let expiry = match DateTime::parse_from_str(expires, "%Y-%m-%d %H:%M:%S %z") {
+        Ok(expiry) => expiry,
+        Err(err) => {
+            error!(target: "lazymc", "Failed to parse ban expiry '{}', assuming still banned: {}", expires, err);
+            return true;
+        }
+    };
+
+    expiry > Utc::now()
+}
+
 /// Load banned IPs from file.
 pub fn load(path: &Path) -> Result<BannedIps, Box<dyn Error>> {
     // Load file contents
@@ -97,3 +176,20 @@ pub fn load(path: &Path) -> Result<BannedIps, Box<dyn Error>> {
     let ips = ips.into_iter().map(|ip| (ip.ip, ip)).collect();
     Ok(BannedIps { ips })
 }
+
+/// Load banned players from file.
+pub fn load_players(path: &Path) -> Result<BannedPlayers, Box<dyn Error>> {
+    // Load file contents
+    let contents = fs::read_to_string(path)?;
+
+    // Parse contents
+    let players: Vec<BannedPlayer> = serde_json::from_str(&contents)?;
+    debug!(target: "lazymc", "Loaded {} banned players", players.len());
+
+    // Transform into map
+    let players = players
+        .into_iter()
+        .map(|player| (player.name.to_lowercase(), player))
+        .collect();
+    Ok(BannedPlayers { players })
+}
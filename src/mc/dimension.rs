@@ -1,3 +1,6 @@
+use std::ops::RangeInclusive;
+
+use minecraft_protocol::version::v1_17;
 use nbt::CompoundTag;
 
 /// Create lobby dimension from the given codec.
@@ -48,7 +51,10 @@ fn lobby_base_dimension(dimension_types: &CompoundTag) -> CompoundTag {
         "minecraft:the_overworld",
     ];
 
-    let dimensions = dimension_types.get_compound_tag_vec("value").unwrap();
+    let dimensions = match dimension_types.get_compound_tag_vec("value") {
+        Ok(dimensions) => dimensions,
+        Err(_) => return lobby_default_dimension(),
+    };
 
     for name in preferred {
         if let Some(dimension) = dimensions
@@ -72,41 +78,166 @@ fn lobby_base_dimension(dimension_types: &CompoundTag) -> CompoundTag {
     lobby_default_dimension()
 }
 
+/// Protocol versions the bundled dimension codec is made for, Minecraft 1.17 to 1.17.1.
+const DEFAULT_CODEC_PROTOCOLS: RangeInclusive<u32> = v1_17::PROTOCOL..=756;
+
+/// Whether the bundled dimension codec matches the given client protocol version.
+pub fn default_codec_matches(protocol: Option<u32>) -> bool {
+    protocol
+        .map(|p| DEFAULT_CODEC_PROTOCOLS.contains(&p))
+        .unwrap_or(false)
+}
+
 /// Default lobby dimension codec from resource file.
 ///
 /// This likely breaks if the Minecraft version doesn't match exactly.
 /// Please use an up-to-date coded from the server instead.
+///
+/// Falls back to a minimal codec if the resource file can't be parsed.
 pub fn default_dimension_codec() -> CompoundTag {
-    snbt_to_compound_tag(include_str!("../../res/dimension_codec.snbt"))
+    snbt_to_compound_tag(include_str!("../../res/dimension_codec.snbt")).unwrap_or_else(|err| {
+        error!(target: "lazymc::lobby", "Failed to load default dimension codec, using minimal codec: {}", err);
+        minimal_dimension_codec()
+    })
 }
 
 /// Default lobby dimension from resource file.
 ///
 /// This likely breaks if the Minecraft version doesn't match exactly.
 /// Please use `lobby_dimension` with an up-to-date coded from the server instead.
+///
+/// Falls back to a minimal dimension if the resource file can't be parsed.
 fn lobby_default_dimension() -> CompoundTag {
-    snbt_to_compound_tag(include_str!("../../res/dimension.snbt"))
+    snbt_to_compound_tag(include_str!("../../res/dimension.snbt")).unwrap_or_else(|err| {
+        error!(target: "lazymc::lobby", "Failed to load default lobby dimension, using minimal dimension: {}", err);
+        minimal_dimension()
+    })
+}
+
+/// Minimal dimension codec, with just the minimal dimension and a single biome.
+fn minimal_dimension_codec() -> CompoundTag {
+    let mut dimension = CompoundTag::new();
+    dimension.insert_str("name", "minecraft:the_end");
+    dimension.insert_i32("id", 0);
+    dimension.insert_compound_tag("element", minimal_dimension());
+
+    let mut dimension_types = CompoundTag::new();
+    dimension_types.insert_str("type", "minecraft:dimension_type");
+    dimension_types.insert_compound_tag_vec("value", vec![dimension]);
+
+    let mut effects = CompoundTag::new();
+    effects.insert_i32("sky_color", 0);
+    effects.insert_i32("water_fog_color", 0);
+    effects.insert_i32("fog_color", 0);
+    effects.insert_i32("water_color", 0);
+
+    let mut biome_element = CompoundTag::new();
+    biome_element.insert_str("precipitation", "none");
+    biome_element.insert_f32("temperature", 0.5);
+    biome_element.insert_f32("downfall", 0.5);
+    biome_element.insert_compound_tag("effects", effects);
+
+    let mut biome = CompoundTag::new();
+    biome.insert_str("name", "minecraft:the_void");
+    biome.insert_i32("id", 0);
+    biome.insert_compound_tag("element", biome_element);
+
+    let mut biomes = CompoundTag::new();
+    biomes.insert_str("type", "minecraft:worldgen/biome");
+    biomes.insert_compound_tag_vec("value", vec![biome]);
+
+    let mut codec = CompoundTag::new();
+    codec.insert_compound_tag("minecraft:dimension_type", dimension_types);
+    codec.insert_compound_tag("minecraft:worldgen/biome", biomes);
+    codec
+}
+
+/// Minimal lobby dimension, built without resource files.
+fn minimal_dimension() -> CompoundTag {
+    let mut dimension = CompoundTag::new();
+    dimension.insert_i8("piglin_safe", 1);
+    dimension.insert_i8("natural", 0);
+    dimension.insert_f32("ambient_light", 0.0);
+    dimension.insert_str("infiniburn", "#minecraft:infiniburn_end");
+    dimension.insert_i8("respawn_anchor_works", 0);
+    dimension.insert_i8("has_skylight", 0);
+    dimension.insert_i8("bed_works", 0);
+    dimension.insert_str("effects", "minecraft:the_end");
+    dimension.insert_i64("fixed_time", 0);
+    dimension.insert_i8("has_raids", 0);
+    dimension.insert_i32("min_y", 0);
+    dimension.insert_i32("height", 16);
+    dimension.insert_i32("logical_height", 16);
+    dimension.insert_f64("coordinate_scale", 1.0);
+    dimension.insert_i8("ultrawarm", 0);
+    dimension.insert_i8("has_ceiling", 0);
+    dimension
 }
 
 /// Read NBT CompoundTag from SNBT.
-fn snbt_to_compound_tag(data: &str) -> CompoundTag {
+fn snbt_to_compound_tag(data: &str) -> Result<CompoundTag, String> {
     use quartz_nbt::io::{write_nbt, Flavor};
     use quartz_nbt::snbt;
 
     // Parse SNBT data
-    let compound = snbt::parse(data).expect("failed to parse SNBT");
+    let compound = snbt::parse(data).map_err(|err| format!("failed to parse SNBT: {err}"))?;
 
     // Encode to binary
     let mut binary = Vec::new();
     write_nbt(&mut binary, None, &compound, Flavor::Uncompressed)
-        .expect("failed to encode NBT CompoundTag as binary");
+        .map_err(|err| format!("failed to encode NBT CompoundTag as binary: {err}"))?;
 
     // Parse binary with usable NBT create
     bin_to_compound_tag(&binary)
 }
 
-/// Read NBT CompoundTag from SNBT.
-fn bin_to_compound_tag(data: &[u8]) -> CompoundTag {
+/// Read NBT CompoundTag from binary NBT.
+fn bin_to_compound_tag(data: &[u8]) -> Result<CompoundTag, String> {
     use nbt::decode::read_compound_tag;
-    read_compound_tag(&mut &*data).unwrap()
+    read_compound_tag(&mut &*data).map_err(|err| format!("failed to decode NBT: {err:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snbt_valid() {
+        let tag = snbt_to_compound_tag(r#"{name: "minecraft:the_end", height: 16}"#).unwrap();
+        assert_eq!(tag.get_str("name").unwrap(), "minecraft:the_end");
+        assert_eq!(tag.get_i32("height").unwrap(), 16);
+    }
+
+    #[test]
+    fn snbt_malformed() {
+        assert!(snbt_to_compound_tag("").is_err());
+        assert!(snbt_to_compound_tag("not snbt").is_err());
+        assert!(snbt_to_compound_tag("{name: }").is_err());
+        assert!(snbt_to_compound_tag("{name: \"a, height: 1}").is_err());
+        assert!(snbt_to_compound_tag("{values: [1, 2}").is_err());
+    }
+
+    #[test]
+    fn snbt_truncated() {
+        let data = include_str!("../../res/dimension.snbt").trim_end();
+        for len in [1, data.len() / 2, data.len() - 1] {
+            let end = (0..=len).rev().find(|i| data.is_char_boundary(*i)).unwrap();
+            assert!(snbt_to_compound_tag(&data[..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn bundled_resources_parse() {
+        assert!(snbt_to_compound_tag(include_str!("../../res/dimension.snbt")).is_ok());
+        assert!(snbt_to_compound_tag(include_str!("../../res/dimension_codec.snbt")).is_ok());
+    }
+
+    #[test]
+    fn nbt_truncated() {
+        let mut binary = Vec::new();
+        nbt::encode::write_compound_tag(&mut binary, &minimal_dimension()).unwrap();
+        assert!(bin_to_compound_tag(&binary).is_ok());
+        assert!(bin_to_compound_tag(&binary[..binary.len() / 2]).is_err());
+        assert!(bin_to_compound_tag(&[]).is_err());
+    }
 }
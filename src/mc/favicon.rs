@@ -1,4 +1,7 @@
+use std::path::Path;
+
 use base64::Engine;
+use tokio::fs;
 
 use crate::proto::client::ClientInfo;
 
@@ -21,6 +24,23 @@ pub fn encode_favicon(data: &[u8]) -> String {
     )
 }
 
+/// Load and encode favicon from the given PNG file.
+///
+/// Returns `None` if the file does not exist or could not be read.
+pub async fn load_favicon(path: &Path) -> Option<String> {
+    if !path.is_file() {
+        return None;
+    }
+
+    match fs::read(path).await {
+        Ok(data) => Some(encode_favicon(&data)),
+        Err(err) => {
+            error!(target: "lazymc::status", "Failed to read favicon from {}: {err}", path.display());
+            None
+        }
+    }
+}
+
 /// Check whether the status response favicon is supported based on the given client info.
 ///
 /// Defaults to `true` if unsure.
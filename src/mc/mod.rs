@@ -5,7 +5,7 @@ pub mod favicon;
 #[cfg(feature = "rcon")]
 pub mod rcon;
 pub mod server_properties;
-#[cfg(feature = "lobby")]
+#[cfg(any(feature = "lobby", feature = "forwarding"))]
 pub mod uuid;
 pub mod whitelist;
 
@@ -31,9 +31,17 @@ impl Rcon {
         let mut stream = TcpStream::connect(addr).await?;
 
         // Add proxy header
-        if config.rcon.send_proxy_v2 {
+        if let Some(version) = config.rcon.send_proxy().version() {
             trace!(target: "lazymc::rcon", "Sending local proxy header for RCON connection");
-            stream.write_all(&proxy::local_proxy_header()?).await?;
+            stream
+                .write_all(&proxy::local_proxy_header(version)?)
+                .await?;
+            stream.flush().await?;
+
+            // Some servers need time to process the header before the RCON handshake
+            if config.rcon.proxy_header_delay > 0 {
+                time::sleep(Duration::from_millis(config.rcon.proxy_header_delay as u64)).await;
+            }
         }
 
         // Start connection
@@ -48,7 +56,7 @@ impl Rcon {
     /// Connect to a host from the given configuration.
     pub async fn connect_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
         // RCON address
-        let mut addr = config.server.address;
+        let mut addr = config.server.address();
         addr.set_port(config.rcon.port);
         let addr = addr.to_string();
 
@@ -65,9 +73,69 @@ impl Rcon {
         self.con.cmd(cmd).await
     }
 
+    /// List online players.
+    ///
+    /// Invokes the `list` command and parses its output.
+    pub async fn list_players(&mut self) -> Result<PlayerList, RconError> {
+        let output = self.cmd("list").await?;
+        Ok(PlayerList::parse(&output))
+    }
+
+    /// Get the position of the given entity or player.
+    ///
+    /// Returns the raw position as reported by the server, or `None` if the entity is unknown.
+    pub async fn entity_pos(&mut self, entity: &str) -> Result<Option<String>, RconError> {
+        let output = self.cmd(&format!("data get entity {entity} Pos")).await?;
+        Ok(output
+            .split_once(": ")
+            .map(|(_, pos)| pos.trim().to_string())
+            .filter(|pos| pos.starts_with('[')))
+    }
+
     /// Close connection.
     pub async fn close(self) {
         // Minecraft quirk
         time::sleep(QUIRK_RCON_GRACE_TIME).await;
     }
 }
+
+/// Player list, as reported by the `list` command.
+#[derive(Debug, Default)]
+pub struct PlayerList {
+    /// Number of online players.
+    pub online: u32,
+
+    /// Maximum number of players.
+    #[allow(unused)]
+    pub max: u32,
+
+    /// Names of online players.
+    pub names: Vec<String>,
+}
+
+impl PlayerList {
+    /// Parse `list` command output.
+    ///
+    /// Supports formats such as:
+    /// - `There are 1 of a max of 20 players online: Steve`
+    /// - `There are 1/20 players online:\nSteve`
+    pub fn parse(output: &str) -> Self {
+        let (counts, names) = output.split_once(':').unwrap_or((output, ""));
+
+        // Take first two numbers as online and max count
+        let mut numbers = counts
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|n| n.parse::<u32>().ok());
+        let online = numbers.next().unwrap_or(0);
+        let max = numbers.next().unwrap_or(0);
+
+        // Take comma separated player names
+        let names = names
+            .split(',')
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .collect();
+
+        Self { online, max, names }
+    }
+}
@@ -82,6 +82,17 @@ pub fn load_dir(path: &Path) -> Result<Whitelist, Box<dyn Error>> {
     Ok(Whitelist { whitelist, ops })
 }
 
+/// Load OP usernames from directory.
+///
+/// Returns an empty list if there is no OPs file.
+pub fn load_ops_dir(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let ops_file = path.join(OPS_FILE);
+    if !ops_file.is_file() {
+        return Ok(vec![]);
+    }
+    load_ops(&ops_file)
+}
+
 /// Load whitelist from file.
 fn load_whitelist(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
     // Load file contents
@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Histogram bucket upper bounds in seconds.
+const BUCKETS: [u64; 10] = [1, 2, 5, 10, 20, 30, 60, 120, 300, 600];
+
+/// Runtime metrics.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Time held clients waited before being relayed to the server.
+    pub hold_wait: Histogram,
+
+    /// Time lobby clients waited before being relayed to the server.
+    pub lobby_wait: Histogram,
+}
+
+/// A simple duration histogram with fixed buckets.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    /// Observation count per bucket, last bucket is for everything above the largest bound.
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+
+    /// Total number of observations.
+    count: AtomicU64,
+
+    /// Sum of all observations in milliseconds.
+    sum_millis: AtomicU64,
+}
+
+impl Histogram {
+    /// Record an observed duration.
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|b| secs <= *b as f64)
+            .unwrap_or(BUCKETS.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_millis
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Total number of observations.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Average observed duration, `None` if nothing was observed.
+    pub fn average(&self) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        Some(Duration::from_millis(
+            self.sum_millis.load(Ordering::Relaxed) / count,
+        ))
+    }
+
+    /// Get cumulative bucket counts.
+    ///
+    /// Returns list of bucket upper bounds in seconds with number of observations at or below it.
+    /// The last bucket has no upper bound and always contains all observations.
+    pub fn buckets(&self) -> Vec<(Option<u64>, u64)> {
+        let mut total = 0;
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                total += count.load(Ordering::Relaxed);
+                (BUCKETS.get(i).copied(), total)
+            })
+            .collect()
+    }
+}
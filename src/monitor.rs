@@ -14,13 +14,15 @@ use tokio::net::TcpStream;
 use tokio::time;
 
 use crate::config::Config;
+use crate::os;
+use crate::probe;
 use crate::proto::client::{Client, ClientState};
 use crate::proto::{packet, packets};
 use crate::proxy;
 use crate::server::{Server, State};
 
-/// Monitor ping inverval in seconds.
-const MONITOR_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Minimum monitor ping interval in live status mode while players are online.
+const MONITOR_POLL_INTERVAL_LIVE_MIN: Duration = Duration::from_millis(100);
 
 /// Status request timeout in seconds.
 const STATUS_TIMEOUT: u64 = 20;
@@ -28,50 +30,182 @@ const STATUS_TIMEOUT: u64 = 20;
 /// Ping request timeout in seconds.
 const PING_TIMEOUT: u64 = 10;
 
+/// Timeout for checking whether the server port is open.
+const PORT_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Monitor server.
 pub async fn monitor_server(config: Arc<Config>, server: Arc<Server>) {
-    // Server address
-    let addr = config.server.address;
-
-    let mut poll_interval = time::interval(MONITOR_POLL_INTERVAL);
+    let target = config.server.log_target("lazymc::monitor");
+    let mut state = server.state_receiver();
 
     loop {
-        poll_interval.tick().await;
-
         // Poll server state and update internal status
-        trace!(target: "lazymc::monitor", "Fetching status for {} ... ", addr);
-        let status = poll_server(&config, &server, addr).await;
+        let status = poll_server(&config, &server, config.server.address()).await;
         match status {
             // Got status, update
-            Ok(Some(status)) => server.update_status(&config, Some(status)).await,
+            Ok(Some(status)) => {
+                if config.server.reprobe_on_version_change
+                    && server.protocol_changed(&status.version).await
+                {
+                    reprobe(&config, &server, &status, &target).await;
+                }
+                server.update_status(&config, Some(status)).await;
+            }
 
             // Error, reset status
             Err(_) => server.update_status(&config, None).await,
 
             // Didn't get status, but ping fallback worked, leave as-is, show warning
             Ok(None) => {
-                warn!(target: "lazymc::monitor", "Failed to poll server status, ping fallback succeeded");
+                warn!(target: &target, "Failed to poll server status, ping fallback succeeded");
             }
         }
 
+        // Poll player activity to detect AFK players
+        #[cfg(feature = "rcon")]
+        server.poll_activity(&config).await;
+
         // Sleep server when it's bedtime
         if server.should_sleep(&config).await {
-            info!(target: "lazymc::monitor", "Server has been idle, sleeping...");
+            info!(target: &target, "Server has been idle, sleeping...");
             server.stop(&config).await;
         }
 
+        // Log start diagnostics if the server is starting for long
+        if server.should_watchdog().await {
+            warn!(target: &target, "Server still not reachable after {} seconds, it may be stuck starting", config.server.start_watchdog);
+            log_start_diagnostics(&config, &server, &target).await;
+        }
+
         // Check whether we should force kill server
         if server.should_kill().await {
-            error!(target: "lazymc::monitor", "Force killing server, took too long to start or stop");
-            if !server.force_kill().await {
-                warn!(target: "lazymc", "Failed to force kill server");
+            if server.state() == State::Starting {
+                log_start_diagnostics(&config, &server, &target).await;
             }
+
+            if config.server.force_kill_on_timeout {
+                error!(target: &target, "Force killing server, took too long to start or stop");
+                if !server.force_kill().await {
+                    warn!(target: &target, "Failed to force kill server");
+                }
+            } else {
+                warn!(target: &target, "Server is taking long to start or stop, not force killing because it is disabled, waiting longer...");
+                server.extend_kill_at(&config).await;
+            }
+        }
+
+        // Sample server process resource usage
+        #[cfg(feature = "proc-stats")]
+        server.sample_process_usage(&config).await;
+
+        // Ping systemd watchdog, poll often enough to keep it satisfied
+        #[cfg(all(unix, feature = "systemd"))]
+        os::systemd::watchdog();
+
+        // Wait until next poll, poll early on state change
+        state.borrow_and_update();
+        #[allow(unused_mut)]
+        let mut poll_interval = poll_interval(&config, &server).await;
+        #[cfg(all(unix, feature = "systemd"))]
+        if let Some(watchdog) = os::systemd::watchdog_interval() {
+            poll_interval = poll_interval.min(watchdog / 2);
+        }
+        tokio::select! {
+            _ = time::sleep(poll_interval) => {},
+            _ = state.changed() => {},
         }
     }
 }
 
+/// Re-probe the server after its version changed.
+///
+/// Resets stale probed details first, so the lobby doesn't use them while probing.
+async fn reprobe(config: &Arc<Config>, server: &Arc<Server>, status: &ServerStatus, target: &str) {
+    if let Some(probed) = server.probed_version.read().await.as_ref() {
+        info!(
+            target: target,
+            "Server version changed from {} (protocol {}) to {} (protocol {}), re-probing...",
+            probed.name, probed.protocol, status.version.name, status.version.protocol,
+        );
+    }
+    server.reset_probed(status.version.clone()).await;
+
+    let (config, server, target) = (config.clone(), server.clone(), target.to_string());
+    tokio::spawn(async move {
+        match probe::probe(config, server.clone()).await {
+            Ok(_) => {
+                info!(target: &target, "Succesfully re-probed server");
+                server.set_probe_failed(false);
+            }
+            Err(_) => {
+                error!(target: &target, "Failed to re-probe server, this may limit lazymc features");
+                server.set_probe_failed(true);
+            }
+        }
+    });
+}
+
+/// Get the interval to poll the server status at, depending on the server state.
+async fn poll_interval(config: &Config, server: &Server) -> Duration {
+    let interval = match server.state() {
+        State::Stopped => config.advanced.poll_interval_sleeping,
+        State::Starting | State::Stopping => config.advanced.poll_interval_transition,
+        State::Started if config.advanced.live_status => {
+            let players_online = server
+                .status()
+                .await
+                .as_ref()
+                .map(|status| status.players.online > 0)
+                .unwrap_or(false);
+            if players_online {
+                return Duration::from_millis(config.advanced.live_status_interval_ms as u64)
+                    .max(MONITOR_POLL_INTERVAL_LIVE_MIN);
+            }
+            config.advanced.poll_interval
+        }
+        State::Started => config.advanced.poll_interval,
+    };
+
+    // Never poll in a tight loop
+    Duration::from_secs(interval.max(1) as u64)
+}
+
+/// Log diagnostics for a starting server.
+///
+/// Reports whether the server process is alive and whether the server port is open, to help
+/// distinguish a slow start from a start command that is stuck before launching the server.
+async fn log_start_diagnostics(config: &Config, server: &Server, target: &str) {
+    match server.pid().await {
+        Some(pid) => match os::is_alive(pid) {
+            Some(true) => {
+                warn!(target: target, "Diagnostics: server process (PID {}) is alive", pid)
+            }
+            Some(false) => {
+                warn!(target: target, "Diagnostics: server process (PID {}) is not alive", pid)
+            }
+            None => {
+                warn!(target: target, "Diagnostics: unknown whether server process (PID {}) is alive", pid)
+            }
+        },
+        None => warn!(target: target, "Diagnostics: no server process running"),
+    }
+
+    let addr = config.server.address();
+    let open = time::timeout(PORT_CHECK_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map(|result| result.is_ok())
+        .unwrap_or(false);
+    if open {
+        warn!(target: target, "Diagnostics: server port {} is open, but the server doesn't respond to status requests", addr);
+    } else {
+        warn!(target: target, "Diagnostics: server port {} is not open, the server may not have been launched yet, the start command may be stuck (for example a wrapper script waiting on a lock) or the configured server address may be wrong", addr);
+    }
+}
+
 /// Poll server state.
 ///
+/// Only poll the server managed by lazymc, fallback addresses must not affect its state.
+///
 /// Returns `Ok` if status/ping succeeded, includes server status most of the time.
 /// Returns `Err` if no connection could be established or if an error occurred.
 pub async fn poll_server(
@@ -79,29 +213,62 @@ pub async fn poll_server(
     server: &Server,
     addr: SocketAddr,
 ) -> Result<Option<ServerStatus>, ()> {
+    trace!(target: "lazymc::monitor", "Fetching status for {} ... ", addr);
+    let protocol = server.protocol(config).await;
+
     // Fetch status
-    if let Ok(status) = fetch_status(config, addr).await {
+    if let Ok(status) = fetch_status(config, addr, protocol).await {
+        #[cfg(feature = "rcon")]
+        let status = with_rcon_player_count(config, server, status).await;
         return Ok(Some(status));
     }
 
     // Try ping fallback if server is currently started
     if server.state() == State::Started {
         debug!(target: "lazymc::monitor", "Failed to get status from started server, trying ping...");
-        do_ping(config, addr).await?;
+        do_ping(config, addr, protocol).await?;
     }
 
     Err(())
 }
 
+/// Replace the online player count in the given status with the count reported through RCON.
+///
+/// Status responses may hide players, such as with vanish plugins. Only if enabled and the server
+/// is started, the status is left as-is if RCON fails.
+#[cfg(feature = "rcon")]
+async fn with_rcon_player_count(
+    config: &Config,
+    server: &Server,
+    mut status: ServerStatus,
+) -> ServerStatus {
+    if !config.rcon.enabled || !config.rcon.player_count || server.state() != State::Started {
+        return status;
+    }
+
+    if let Some(online) = server.rcon_player_count(config).await {
+        if online != status.players.online {
+            trace!(target: "lazymc::monitor", "Status reports {} players online, RCON reports {}, using RCON", status.players.online, online);
+        }
+        status.players.online = online;
+    }
+    status
+}
+
 /// Attemp to fetch status from server.
-async fn fetch_status(config: &Config, addr: SocketAddr) -> Result<ServerStatus, ()> {
+async fn fetch_status(
+    config: &Config,
+    addr: SocketAddr,
+    protocol: u32,
+) -> Result<ServerStatus, ()> {
+    // Internal status polls are not subject to the outbound connection limit
     let mut stream = TcpStream::connect(addr).await.map_err(|_| ())?;
 
     // Add proxy header
-    if config.server.send_proxy_v2 {
+    if let Some(version) = config.server.send_proxy().version() {
         trace!(target: "lazymc::monitor", "Sending local proxy header for server connection");
         stream
-            .write_all(&proxy::local_proxy_header().map_err(|_| ())?)
+            .write_all(&proxy::local_proxy_header(version).map_err(|_| ())?)
             .await
             .map_err(|_| ())?;
     }
@@ -109,20 +276,21 @@ async fn fetch_status(config: &Config, addr: SocketAddr) -> Result<ServerStatus,
     // Dummy client
     let client = Client::dummy();
 
-    send_handshake(&client, &mut stream, config, addr).await?;
+    send_handshake(&client, &mut stream, protocol, addr).await?;
     request_status(&client, &mut stream).await?;
     wait_for_status_timeout(&client, &mut stream).await
 }
 
 /// Attemp to ping server.
-async fn do_ping(config: &Config, addr: SocketAddr) -> Result<(), ()> {
+async fn do_ping(config: &Config, addr: SocketAddr, protocol: u32) -> Result<(), ()> {
+    // Internal status polls are not subject to the outbound connection limit
     let mut stream = TcpStream::connect(addr).await.map_err(|_| ())?;
 
     // Add proxy header
-    if config.server.send_proxy_v2 {
+    if let Some(version) = config.server.send_proxy().version() {
         trace!(target: "lazymc::monitor", "Sending local proxy header for server connection");
         stream
-            .write_all(&proxy::local_proxy_header().map_err(|_| ())?)
+            .write_all(&proxy::local_proxy_header(version).map_err(|_| ())?)
             .await
             .map_err(|_| ())?;
     }
@@ -130,7 +298,7 @@ async fn do_ping(config: &Config, addr: SocketAddr) -> Result<(), ()> {
     // Dummy client
     let client = Client::dummy();
 
-    send_handshake(&client, &mut stream, config, addr).await?;
+    send_handshake(&client, &mut stream, protocol, addr).await?;
     let token = send_ping(&client, &mut stream).await?;
     wait_for_ping_timeout(&client, &mut stream, token).await
 }
@@ -139,12 +307,12 @@ async fn do_ping(config: &Config, addr: SocketAddr) -> Result<(), ()> {
 async fn send_handshake(
     client: &Client,
     stream: &mut TcpStream,
-    config: &Config,
+    protocol: u32,
     addr: SocketAddr,
 ) -> Result<(), ()> {
     packet::write_packet(
         Handshake {
-            protocol_version: config.public.protocol as i32,
+            protocol_version: protocol as i32,
             server_addr: addr.ip().to_string(),
             server_port: addr.port(),
             next_state: ClientState::Status.to_id(),
@@ -178,7 +346,7 @@ async fn wait_for_status(client: &Client, stream: &mut TcpStream) -> Result<Serv
         let (packet, _raw) = match packet::read_packet(client, &mut buf, &mut reader).await {
             Ok(Some(packet)) => packet,
             Ok(None) => break,
-            Err(_) => continue,
+            Err(_) => break,
         };
 
         // Catch status response
@@ -214,7 +382,7 @@ async fn wait_for_ping(client: &Client, stream: &mut TcpStream, token: u64) -> R
         let (packet, _raw) = match packet::read_packet(client, &mut buf, &mut reader).await {
             Ok(Some(packet)) => packet,
             Ok(None) => break,
-            Err(_) => continue,
+            Err(_) => break,
         };
 
         // Catch ping response
@@ -1,7 +1,83 @@
 use std::error::Error;
 use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time;
+
+/// Global limit for concurrent outbound server connections, unlimited if not set.
+static OUTBOUND_LIMIT: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+/// Permit for an outbound server connection.
+///
+/// Must be kept alive for as long as the connection is open. `None` if there is no limit.
+pub type OutboundPermit = Option<OwnedSemaphorePermit>;
+
+/// Limit the number of concurrent outbound server connections.
+///
+/// Should be called once at startup, before any connection is made.
+pub fn set_outbound_limit(max: usize) {
+    if OUTBOUND_LIMIT.set(Arc::new(Semaphore::new(max))).is_err() {
+        warn!(target: "lazymc", "Outbound connection limit already set, ignoring");
+    }
+}
+
+/// Acquire a permit for a new outbound server connection.
+///
+/// Waits until a permit is available if the limit is reached.
+async fn outbound_permit() -> OutboundPermit {
+    let limit = OUTBOUND_LIMIT.get()?;
+    if limit.available_permits() == 0 {
+        debug!(target: "lazymc", "Outbound server connection limit reached, waiting for a free slot...");
+    }
+    limit.clone().acquire_owned().await.ok()
+}
+
+/// Connect to the first reachable address.
+///
+/// Tries each address in order, failing over to the next if connecting fails. Returns the error of
+/// the last attempt if no address is reachable.
+///
+/// Respects the outbound connection limit, the returned permit must be kept for as long as the
+/// connection is open. Internal connections such as status polls and probes should connect
+/// directly instead, so they aren't stalled by a full limit.
+pub async fn connect_first(addrs: &[SocketAddr]) -> Result<(TcpStream, OutboundPermit), io::Error> {
+    let (stream, _, permit) = connect_first_within(addrs, None).await?;
+    Ok((stream, permit))
+}
+
+/// Connect to the first reachable address, waiting at most `timeout` for each if set.
+///
+/// Like `connect_first`, but also returns the address that was connected to.
+pub async fn connect_first_within(
+    addrs: &[SocketAddr],
+    timeout: Option<Duration>,
+) -> Result<(TcpStream, SocketAddr, OutboundPermit), io::Error> {
+    let permit = outbound_permit().await;
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to");
+
+    for addr in addrs {
+        let result = match timeout {
+            Some(timeout) => time::timeout(timeout, TcpStream::connect(addr))
+                .await
+                .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+            None => TcpStream::connect(addr).await,
+        };
+        match result {
+            Ok(stream) => return Ok((stream, *addr, permit)),
+            Err(err) => {
+                debug!(target: "lazymc", "Failed to connect to {}, trying next address if any: {}", addr, err);
+                last_err = err;
+            }
+        }
+    }
+
+    Err(last_err)
+}
 
 /// Gracefully close given TCP stream.
 ///
@@ -20,3 +96,16 @@ pub async fn close_tcp_stream_ref(stream: &mut TcpStream) -> Result<(), Box<dyn
         Err(err) => Err(err.into()),
     }
 }
+
+/// Get the canonical form of an IP address, unmapping IPv4-mapped IPv6 addresses.
+///
+/// Dual-stack sockets report IPv4 peers as IPv4-mapped IPv6 address.
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
@@ -1,12 +1,27 @@
+#[cfg(feature = "proc-stats")]
+pub mod proc;
+#[cfg(all(unix, feature = "systemd"))]
+pub mod systemd;
 #[cfg(windows)]
 pub mod windows;
 
+#[cfg(unix)]
+use std::io;
+
 #[cfg(unix)]
 use nix::{
     sys::signal::{self, Signal},
     unistd::Pid,
 };
 
+use tokio::process::Command;
+
+/// Highest process priority nice value.
+pub const NICE_MIN: i32 = -20;
+
+/// Lowest process priority nice value.
+pub const NICE_MAX: i32 = 19;
+
 /// Force kill process.
 ///
 /// Results in undefined behavior if PID is invalid.
@@ -42,30 +57,167 @@ pub fn kill_gracefully(pid: u32) -> bool {
 /// Results in undefined behavior if PID is invaild.
 ///
 /// # Panics
-/// Panics on platforms other than Unix.
+/// Panics on platforms other than Unix and Windows.
 #[allow(unreachable_code)]
 pub fn freeze(pid: u32) -> bool {
     #[cfg(unix)]
     return unix_signal(pid, Signal::SIGSTOP);
 
-    unimplemented!(
-        "freezing the Minecraft server process is not implemented on non-Unix platforms"
-    );
+    #[cfg(windows)]
+    unsafe {
+        return windows::freeze(pid);
+    }
+
+    unimplemented!("freezing the Minecraft server process is not implemented on this platform");
 }
 
 /// Unfreeze process.
 /// Results in undefined behavior if PID is invaild.
 ///
 /// # Panics
-/// Panics on platforms other than Unix.
+/// Panics on platforms other than Unix and Windows.
 #[allow(unreachable_code)]
 pub fn unfreeze(pid: u32) -> bool {
     #[cfg(unix)]
     return unix_signal(pid, Signal::SIGCONT);
 
-    unimplemented!(
-        "unfreezing the Minecraft server process is not implemented on non-Unix platforms"
-    );
+    #[cfg(windows)]
+    unsafe {
+        return windows::unfreeze(pid);
+    }
+
+    unimplemented!("unfreezing the Minecraft server process is not implemented on this platform");
+}
+
+/// Check whether process is alive.
+///
+/// Returns `None` if unknown.
+#[allow(unreachable_code)]
+pub fn is_alive(pid: u32) -> Option<bool> {
+    #[cfg(unix)]
+    return match signal::kill(Pid::from_raw(pid as i32), None) {
+        Ok(()) => Some(true),
+        Err(nix::errno::Errno::EPERM) => Some(true),
+        Err(nix::errno::Errno::ESRCH) => Some(false),
+        Err(_) => None,
+    };
+
+    #[cfg(windows)]
+    unsafe {
+        return windows::is_alive(pid);
+    }
+
+    None
+}
+
+/// Set the priority of the process spawned by the given command, as nice value.
+///
+/// The value is clamped to the valid range. On Windows it maps to the closest priority class.
+#[allow(unreachable_code, unused_variables)]
+pub fn set_priority(cmd: &mut Command, nice: i32) {
+    let nice = nice.clamp(NICE_MIN, NICE_MAX);
+
+    #[cfg(unix)]
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+        return;
+    }
+
+    #[cfg(windows)]
+    {
+        cmd.creation_flags(windows::priority_class(nice));
+        return;
+    }
+
+    warn!(target: "lazymc", "Setting server process priority is not supported on this platform, ignoring");
+}
+
+/// Bind the process spawned by the given command to the given CPU cores.
+///
+/// On Linux this is applied before the process starts. On Windows it must be applied to the
+/// spawned process with `set_process_cpu_affinity` instead.
+#[allow(unreachable_code, unused_variables)]
+pub fn set_cpu_affinity(cmd: &mut Command, cores: &[usize]) {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for core in cores
+            .iter()
+            .filter(|core| **core < libc::CPU_SETSIZE as usize)
+        {
+            libc::CPU_SET(*core, &mut set);
+        }
+        if libc::CPU_COUNT(&set) == 0 {
+            warn!(target: "lazymc", "No valid CPU cores to bind server process to, ignoring");
+            return;
+        }
+
+        cmd.pre_exec(move || {
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+        return;
+    }
+
+    #[cfg(windows)]
+    return;
+
+    warn!(target: "lazymc", "Setting server process CPU affinity is not supported on this platform, ignoring");
+}
+
+/// Bind the running process to the given CPU cores.
+///
+/// Only required on Windows, see `set_cpu_affinity`. Results in undefined behavior if PID is
+/// invalid.
+#[allow(unreachable_code, unused_variables)]
+pub fn set_process_cpu_affinity(pid: u32, cores: &[usize]) -> bool {
+    #[cfg(windows)]
+    unsafe {
+        return windows::set_cpu_affinity(pid, cores);
+    }
+
+    true
+}
+
+/// Get available system memory in megabytes.
+///
+/// Returns `None` if unknown or unsupported on this platform.
+#[allow(unreachable_code)]
+pub fn free_memory_mb() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    return linux_free_memory_mb();
+
+    #[cfg(windows)]
+    unsafe {
+        return windows::free_memory_mb();
+    }
+
+    None
+}
+
+/// Get available system memory in megabytes on Linux, from `/proc/meminfo`.
+#[cfg(target_os = "linux")]
+fn linux_free_memory_mb() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kb| kb / 1024)
 }
 
 #[cfg(unix)]
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+/// Resource usage of a process.
+#[derive(Debug, Copy, Clone)]
+pub struct ProcessUsage {
+    /// CPU usage in percent since the previous sample, 100% is one fully used core.
+    pub cpu: f32,
+
+    /// Resident memory in bytes.
+    pub memory: u64,
+}
+
+/// Samples the resource usage of a process.
+///
+/// CPU usage is measured between consecutive samples of the same process.
+#[derive(Debug, Default)]
+pub struct Sampler {
+    /// PID, time and total CPU time of the previous sample.
+    last: Option<(u32, Instant, Duration)>,
+}
+
+impl Sampler {
+    /// Sample resource usage of the given process.
+    ///
+    /// Returns `None` if unsupported on this platform, if the process is unknown, or if this is the
+    /// first sample of this process.
+    pub fn sample(&mut self, pid: u32) -> Option<ProcessUsage> {
+        let (cpu_time, memory) = cpu_time_and_memory(pid)?;
+        let now = Instant::now();
+
+        let last = self.last.replace((pid, now, cpu_time));
+        let (last_pid, last_at, last_cpu_time) = last?;
+        if last_pid != pid {
+            return None;
+        }
+
+        let elapsed = now.duration_since(last_at).as_secs_f32();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        let cpu = cpu_time.saturating_sub(last_cpu_time).as_secs_f32() / elapsed * 100.0;
+
+        Some(ProcessUsage { cpu, memory })
+    }
+}
+
+/// Get total CPU time and resident memory in bytes of the given process.
+#[allow(unreachable_code, unused_variables)]
+fn cpu_time_and_memory(pid: u32) -> Option<(Duration, u64)> {
+    #[cfg(target_os = "linux")]
+    return linux_cpu_time_and_memory(pid);
+
+    #[cfg(windows)]
+    unsafe {
+        return super::windows::cpu_time_and_memory(pid);
+    }
+
+    None
+}
+
+/// Get total CPU time and resident memory of a process on Linux, from `/proc/<pid>`.
+#[cfg(target_os = "linux")]
+fn linux_cpu_time_and_memory(pid: u32) -> Option<(Duration, u64)> {
+    // CPU time from user and system time in clock ticks, fields 14 and 15
+    // Skip past process name, it may contain spaces and parentheses
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks <= 0 {
+        return None;
+    }
+    let cpu_time = Duration::from_secs_f64((utime + stime) as f64 / ticks as f64);
+
+    // Resident memory in pages, second field
+    let statm = std::fs::read_to_string(format!("/proc/{pid}/statm")).ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size <= 0 {
+        return None;
+    }
+
+    Some((cpu_time, pages * page_size as u64))
+}
@@ -0,0 +1,82 @@
+use std::env;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/// Environment variable holding the systemd notification socket path.
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// Environment variable holding the systemd watchdog interval in microseconds.
+const WATCHDOG_USEC: &str = "WATCHDOG_USEC";
+
+/// Environment variable holding the PID the systemd watchdog applies to.
+const WATCHDOG_PID: &str = "WATCHDOG_PID";
+
+/// Notify systemd that lazymc is ready.
+pub fn ready() {
+    notify("READY=1");
+}
+
+/// Notify systemd of the current status, shown in `systemctl status`.
+pub fn status(status: &str) {
+    notify(&format!("STATUS={status}"));
+}
+
+/// Notify systemd that lazymc is stopping.
+pub fn stopping() {
+    notify("STOPPING=1");
+}
+
+/// Ping the systemd watchdog.
+pub fn watchdog() {
+    notify("WATCHDOG=1");
+}
+
+/// Get the systemd watchdog interval, `None` if the watchdog is not enabled for lazymc.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var(WATCHDOG_PID) {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+
+    env::var(WATCHDOG_USEC)
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
+
+/// Send the given state to the systemd notification socket.
+///
+/// Does nothing if not run by systemd with notify support.
+fn notify(state: &str) {
+    let Some(path) = env::var_os(NOTIFY_SOCKET) else {
+        return;
+    };
+
+    if let Err(err) = send(&path.to_string_lossy(), state) {
+        debug!(target: "lazymc::systemd", "Failed to notify systemd: {}", err);
+    }
+}
+
+/// Send a message to the given notification socket.
+///
+/// Paths starting with `@` are abstract sockets, which are Linux only.
+fn send(path: &str, message: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+
+        let addr = SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(message.as_bytes(), &addr)?;
+        return Ok(());
+    }
+
+    socket.send_to(message.as_bytes(), path)?;
+    Ok(())
+}
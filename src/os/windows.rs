@@ -1,8 +1,28 @@
 use winapi::shared::minwindef::{FALSE, TRUE};
 use winapi::shared::ntdef::NULL;
-use winapi::um::handleapi::CloseHandle;
-use winapi::um::processthreadsapi::{OpenProcess, TerminateProcess};
-use winapi::um::winnt::PROCESS_TERMINATE;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::processthreadsapi::{
+    GetExitCodeProcess, OpenProcess, OpenThread, ResumeThread, SuspendThread, TerminateProcess,
+};
+use winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+};
+use winapi::um::winbase::SetProcessAffinityMask;
+use winapi::um::winnt::{
+    PROCESS_QUERY_INFORMATION, PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SET_INFORMATION,
+    PROCESS_TERMINATE, THREAD_SUSPEND_RESUME,
+};
+
+/// Process priority classes, see `SetPriorityClass`.
+const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+const NORMAL_PRIORITY_CLASS: u32 = 0x0000_0020;
+const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x0000_8000;
+const HIGH_PRIORITY_CLASS: u32 = 0x0000_0080;
+
+/// Exit code of a process that is still running.
+const STILL_ACTIVE: u32 = 259;
 
 /// Force kill process on Windows.
 ///
@@ -20,3 +40,170 @@ pub unsafe fn force_kill(pid: u32) -> bool {
 
     terminated && closed
 }
+
+/// Freeze process on Windows, by suspending all its threads.
+///
+/// This is unsafe because the PID isn't checked.
+pub unsafe fn freeze(pid: u32) -> bool {
+    debug!(target: "lazymc", "Suspending threads of {} to freeze server", pid);
+    suspend_threads(pid, true)
+}
+
+/// Unfreeze process on Windows, by resuming all its threads.
+///
+/// This is unsafe because the PID isn't checked.
+pub unsafe fn unfreeze(pid: u32) -> bool {
+    debug!(target: "lazymc", "Resuming threads of {} to unfreeze server", pid);
+    suspend_threads(pid, false)
+}
+
+/// Suspend or resume all threads of a process on Windows.
+///
+/// Each suspend must be matched by a resume, as threads keep a suspend count.
+///
+/// This is unsafe because the PID isn't checked.
+unsafe fn suspend_threads(pid: u32, suspend: bool) -> bool {
+    let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+    if snapshot == INVALID_HANDLE_VALUE {
+        warn!(target: "lazymc", "Failed to list process threads in order to suspend or resume them");
+        return false;
+    }
+
+    let mut entry: THREADENTRY32 = std::mem::zeroed();
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+
+    let mut found = false;
+    let mut success = true;
+    let mut more = Thread32First(snapshot, &mut entry) == TRUE;
+    while more {
+        if entry.th32OwnerProcessID == pid {
+            found = true;
+            let thread = OpenThread(THREAD_SUSPEND_RESUME, FALSE, entry.th32ThreadID);
+            if thread == NULL {
+                success = false;
+            } else {
+                let count = if suspend {
+                    SuspendThread(thread)
+                } else {
+                    ResumeThread(thread)
+                };
+                success &= count != u32::MAX;
+                CloseHandle(thread);
+            }
+        }
+        more = Thread32Next(snapshot, &mut entry) == TRUE;
+    }
+    CloseHandle(snapshot);
+
+    found && success
+}
+
+/// Check whether process is alive on Windows.
+///
+/// This is unsafe because the PID isn't checked.
+pub unsafe fn is_alive(pid: u32) -> Option<bool> {
+    let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+    if handle == NULL {
+        return Some(false);
+    }
+
+    let mut code = 0;
+    let result = GetExitCodeProcess(handle, &mut code) == TRUE;
+    CloseHandle(handle);
+
+    result.then_some(code == STILL_ACTIVE)
+}
+
+/// Bind process to the given CPU cores on Windows.
+///
+/// Cores beyond the affinity mask size are ignored.
+///
+/// This is unsafe because the PID isn't checked.
+pub unsafe fn set_cpu_affinity(pid: u32, cores: &[usize]) -> bool {
+    let mask = cores
+        .iter()
+        .filter(|core| **core < usize::BITS as usize)
+        .fold(0usize, |mask, core| mask | (1 << core));
+    if mask == 0 {
+        warn!(target: "lazymc", "No valid CPU cores to bind server process to");
+        return false;
+    }
+
+    let handle = OpenProcess(
+        PROCESS_SET_INFORMATION | PROCESS_QUERY_INFORMATION,
+        FALSE,
+        pid,
+    );
+    if handle == NULL {
+        warn!(target: "lazymc", "Failed to open process handle in order to set its CPU affinity");
+        return false;
+    }
+
+    let set = SetProcessAffinityMask(handle, mask) == TRUE;
+    let closed = CloseHandle(handle) == TRUE;
+
+    set && closed
+}
+
+/// Get the process priority class closest to the given Unix nice value.
+pub fn priority_class(nice: i32) -> u32 {
+    match nice {
+        15.. => IDLE_PRIORITY_CLASS,
+        5..=14 => BELOW_NORMAL_PRIORITY_CLASS,
+        -4..=4 => NORMAL_PRIORITY_CLASS,
+        -14..=-5 => ABOVE_NORMAL_PRIORITY_CLASS,
+        _ => HIGH_PRIORITY_CLASS,
+    }
+}
+
+/// Get available system memory in megabytes on Windows.
+pub unsafe fn free_memory_mb() -> Option<u64> {
+    let mut status: MEMORYSTATUSEX = std::mem::zeroed();
+    status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+    if GlobalMemoryStatusEx(&mut status) != TRUE {
+        return None;
+    }
+
+    Some(status.ullAvailPhys / 1024 / 1024)
+}
+
+/// Get total CPU time and resident memory in bytes of a process on Windows.
+///
+/// This is unsafe because the PID isn't checked.
+#[cfg(feature = "proc-stats")]
+pub unsafe fn cpu_time_and_memory(pid: u32) -> Option<(std::time::Duration, u64)> {
+    use winapi::shared::minwindef::FILETIME;
+    use winapi::um::processthreadsapi::GetProcessTimes;
+    use winapi::um::psapi::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use winapi::um::winnt::PROCESS_VM_READ;
+
+    let handle = OpenProcess(
+        PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ,
+        FALSE,
+        pid,
+    );
+    if handle == NULL {
+        return None;
+    }
+
+    // CPU time from kernel and user time, in 100 nanosecond units
+    let mut creation: FILETIME = std::mem::zeroed();
+    let mut exit: FILETIME = std::mem::zeroed();
+    let mut kernel: FILETIME = std::mem::zeroed();
+    let mut user: FILETIME = std::mem::zeroed();
+    let times = GetProcessTimes(handle, &mut creation, &mut exit, &mut kernel, &mut user) == TRUE;
+
+    let mut counters: PROCESS_MEMORY_COUNTERS = std::mem::zeroed();
+    counters.cb = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    let memory = GetProcessMemoryInfo(handle, &mut counters, counters.cb) == TRUE;
+    CloseHandle(handle);
+
+    if !times || !memory {
+        return None;
+    }
+
+    let ticks = |time: FILETIME| (time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64;
+    let cpu_time = std::time::Duration::from_nanos((ticks(kernel) + ticks(user)) * 100);
+
+    Some((cpu_time, counters.WorkingSetSize as u64))
+}
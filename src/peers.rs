@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Time after which a peer is forgotten when it isn't seen again.
+const PEER_EXPIRE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Interval to forget expired peers at.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum number of tracked peers.
+///
+/// Anyone can send status requests, this bounds memory usage when flooded by scanners.
+const MAX_PEERS: usize = 4096;
+
+/// What has been seen from a peer.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PeerKind {
+    /// Only requested server status, such as server list pings and scanners.
+    StatusOnly,
+
+    /// Attempted to log in, a genuine player.
+    Login,
+}
+
+/// A tracked peer.
+#[derive(Debug, Copy, Clone)]
+pub struct Peer {
+    /// What has been seen from this peer.
+    pub kind: PeerKind,
+
+    /// Number of status requests.
+    pub status_count: u32,
+
+    /// Last time this peer was seen.
+    pub last_seen: Instant,
+}
+
+/// Peer tracker.
+///
+/// Tracks whether IPs only request the server status, or also log in. Used to tell scanners apart
+/// from players.
+#[derive(Debug, Default)]
+pub struct Peers {
+    /// Tracked peers by IP.
+    peers: HashMap<IpAddr, Peer>,
+
+    /// Last time expired peers were forgotten.
+    last_expire: Option<Instant>,
+}
+
+impl Peers {
+    /// Record a status request from the given IP.
+    pub fn record_status(&mut self, ip: IpAddr) -> Peer {
+        self.make_room(ip);
+        let peer = self.peers.entry(ip).or_insert_with(|| Peer {
+            kind: PeerKind::StatusOnly,
+            status_count: 0,
+            last_seen: Instant::now(),
+        });
+        peer.status_count = peer.status_count.saturating_add(1);
+        peer.last_seen = Instant::now();
+        *peer
+    }
+
+    /// Record a login attempt from the given IP.
+    ///
+    /// Returns the peer as it was before this login, if it was known.
+    pub fn record_login(&mut self, ip: IpAddr) -> Option<Peer> {
+        self.make_room(ip);
+        let previous = self.peers.get(&ip).copied();
+        self.peers.insert(
+            ip,
+            Peer {
+                kind: PeerKind::Login,
+                status_count: previous.map(|p| p.status_count).unwrap_or(0),
+                last_seen: Instant::now(),
+            },
+        );
+        previous
+    }
+
+    /// Whether the given IP attempted to log in recently.
+    pub fn is_player(&mut self, ip: IpAddr) -> bool {
+        self.expire();
+        self.peers
+            .get(&ip)
+            .is_some_and(|peer| peer.kind == PeerKind::Login)
+    }
+
+    /// List tracked peers, most recently seen first.
+    pub fn list(&mut self) -> Vec<(IpAddr, Peer)> {
+        self.expire();
+        let mut peers: Vec<_> = self.peers.iter().map(|(ip, peer)| (*ip, *peer)).collect();
+        peers.sort_by_key(|(_, peer)| std::cmp::Reverse(peer.last_seen));
+        peers
+    }
+
+    /// Make room to track the given IP.
+    ///
+    /// If the limit is reached, a quarter of the peers is evicted at once. Status-only peers are
+    /// evicted first, least recently seen first.
+    fn make_room(&mut self, ip: IpAddr) {
+        self.expire();
+        if self.peers.len() < MAX_PEERS || self.peers.contains_key(&ip) {
+            return;
+        }
+
+        let mut peers: Vec<_> = self.peers.iter().map(|(ip, peer)| (*ip, *peer)).collect();
+        peers.sort_by_key(|(_, peer)| (peer.kind == PeerKind::Login, peer.last_seen));
+        for (ip, _) in peers.into_iter().take(MAX_PEERS / 4) {
+            self.peers.remove(&ip);
+        }
+    }
+
+    /// Forget peers that weren't seen for a while.
+    ///
+    /// Only runs once every `EXPIRE_INTERVAL`.
+    fn expire(&mut self) {
+        if self
+            .last_expire
+            .is_some_and(|last| last.elapsed() < EXPIRE_INTERVAL)
+        {
+            return;
+        }
+        self.last_expire = Some(Instant::now());
+
+        self.peers
+            .retain(|_, peer| peer.last_seen.elapsed() < PEER_EXPIRE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn ip(n: u32) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(n))
+    }
+
+    #[test]
+    fn bounded_evicts_status_only_first() {
+        let mut peers = Peers::default();
+        peers.record_login(ip(0));
+        for n in 1..=MAX_PEERS as u32 * 2 {
+            peers.record_status(ip(n));
+        }
+
+        assert!(peers.peers.len() <= MAX_PEERS);
+        assert!(peers.is_player(ip(0)));
+        assert!(!peers.is_player(ip(1)));
+    }
+
+    #[test]
+    fn login_after_status() {
+        let mut peers = Peers::default();
+        assert_eq!(peers.record_status(ip(1)).status_count, 1);
+        assert_eq!(peers.record_status(ip(1)).status_count, 2);
+        assert!(!peers.is_player(ip(1)));
+
+        let previous = peers.record_login(ip(1)).unwrap();
+        assert_eq!(previous.kind, PeerKind::StatusOnly);
+        assert!(peers.is_player(ip(1)));
+    }
+}
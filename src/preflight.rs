@@ -0,0 +1,164 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, Preflight, Server as ConfigServer};
+use crate::os;
+use crate::util::error::{quit_error_msg, ErrorHintsBuilder};
+
+/// Run preflight checks on the configuration before starting.
+///
+/// Depending on the configured strictness, problems are logged as warning or make lazymc quit.
+pub fn check(config: &Config) {
+    if config.advanced.preflight == Preflight::Off {
+        return;
+    }
+
+    let problems = problems(config);
+    if problems.is_empty() {
+        debug!(target: "lazymc", "Preflight checks passed");
+        return;
+    }
+
+    match config.advanced.preflight {
+        Preflight::Strict => {
+            let mut hints = ErrorHintsBuilder::default();
+            hints.config(true).config_test(true);
+            for problem in &problems[1..] {
+                hints = hints.add_info(problem.clone());
+            }
+            hints = hints.add_info(
+                "Set 'advanced.preflight = \"warn\"' to start with these problems anyway".into(),
+            );
+            quit_error_msg(
+                format!("Preflight check failed: {}", problems[0]),
+                hints.build().unwrap(),
+            );
+        }
+        Preflight::Warn => {
+            for problem in problems {
+                warn!(target: "lazymc", "Preflight check: {}", problem);
+            }
+        }
+        Preflight::Off => {}
+    }
+}
+
+/// Collect configuration problems.
+fn problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // Only check server process related things if we manage it
+    if let Some(command) = &config.server.command {
+        let dir = ConfigServer::server_directory(config);
+        match &dir {
+            Some(dir) if !dir.is_dir() => problems.push(format!(
+                "server directory does not exist: {}",
+                dir.display()
+            )),
+            _ => {}
+        }
+
+        match shlex::split(command).as_ref().and_then(|args| args.first()) {
+            Some(program) => {
+                if !is_resolvable(program, dir.as_deref()) {
+                    problems.push(format!(
+                        "server command program cannot be found: {}",
+                        program
+                    ));
+                }
+            }
+            None => problems.push("server command is empty or invalid".into()),
+        }
+    }
+
+    // Process priority must be a valid nice value
+    if let Some(nice) = config.server.nice {
+        if !(os::NICE_MIN..=os::NICE_MAX).contains(&nice) {
+            problems.push(format!(
+                "server nice value {} is out of range, must be from {} to {}",
+                nice,
+                os::NICE_MIN,
+                os::NICE_MAX
+            ));
+        }
+    }
+
+    // RCON must have a password, conflicting ports are checked on start
+    if cfg!(feature = "rcon")
+        && config.rcon.enabled
+        && !config.rcon.randomize_password
+        && config.rcon.password.is_empty()
+    {
+        problems.push("RCON password is empty, set one or enable randomize_password".into());
+    }
+
+    // Hook commands must be valid
+    let hooks = [
+        &config.hooks.on_start,
+        &config.hooks.on_stop,
+        &config.hooks.on_state_change,
+    ];
+    for command in hooks.into_iter().flatten() {
+        if !shlex::split(command).is_some_and(|args| !args.is_empty()) {
+            problems.push(format!("hook command is empty or invalid: {}", command));
+        }
+    }
+
+    // Server process CPU usage can only be sampled in some builds and platforms
+    if config.time.sleep_when_idle_cpu_below.is_some()
+        && !cfg!(all(
+            feature = "proc-stats",
+            any(target_os = "linux", windows)
+        ))
+    {
+        problems.push("sleep_when_idle_cpu_below is set, but server process CPU usage can't be sampled in this build or on this platform, it is ignored".into());
+    }
+
+    // Inbound PROXY headers are only accepted from trusted proxies
+    if config.public.accept_proxy_header && config.public.trusted_proxies.is_empty() {
+        problems.push(
+            "accepting PROXY headers is enabled, but no proxies are trusted to send them".into(),
+        );
+    }
+
+    problems
+}
+
+/// Check whether the given program can be resolved.
+///
+/// Paths are resolved relative to the server directory, bare names are searched in `PATH`.
+fn is_resolvable(program: &str, dir: Option<&Path>) -> bool {
+    let path = Path::new(program);
+
+    // Resolve paths relative to server directory
+    if path.components().count() > 1 {
+        return match dir {
+            Some(dir) => dir.join(path).is_file(),
+            None => path.is_file(),
+        };
+    }
+
+    // Search bare program names in PATH
+    let paths = match env::var_os("PATH") {
+        Some(paths) => paths,
+        None => return true,
+    };
+    env::split_paths(&paths).any(|p| {
+        candidates(&p.join(program))
+            .iter()
+            .any(|candidate| candidate.is_file())
+    })
+}
+
+/// Candidate program paths, includes executable extensions on Windows.
+fn candidates(path: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![path.to_path_buf()];
+    if cfg!(windows) {
+        candidates.extend(
+            ["exe", "bat", "cmd"]
+                .iter()
+                .map(|ext| path.with_extension(ext)),
+        );
+    }
+    candidates
+}
@@ -13,21 +13,19 @@ use tokio::time;
 
 use crate::config::Config;
 use crate::forge;
+#[cfg(feature = "forwarding")]
+use crate::forwarding;
+use crate::monitor;
 use crate::net;
 use crate::proto::client::{Client, ClientInfo, ClientState};
 use crate::proto::packets::play::join_game::JoinGameData;
-use crate::proto::{self, packet, packets};
+use crate::proto::unhandled::Unhandled;
+use crate::proto::{packet, packets};
 use crate::server::{Server, State};
 
-/// Minecraft username to use for probing the server.
-const PROBE_USER: &str = "_lazymc_probe";
-
 /// Timeout for probe user connecting to the server.
 const PROBE_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 
-/// Maximum time the probe may wait for the server to come online.
-const PROBE_ONLINE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
-
 /// Timeout for receiving join game packet.
 ///
 /// When the play state is reached, the server should immeditely respond with a join game packet.
@@ -44,11 +42,15 @@ pub async fn probe(config: Arc<Config>, server: Arc<Server>) -> Result<(), ()> {
     }
 
     // Wait for server to come online
-    if !wait_until_online(&server).await? {
+    let timeout = Duration::from_secs(config.server.probe_timeout as u64);
+    if !wait_until_online(&server, timeout).await? {
         warn!(target: "lazymc::probe", "Couldn't probe server, failed to wait for server to come online");
         return Err(());
     }
 
+    // Record real server version from its status
+    record_version(&config, &server).await;
+
     debug!(target: "lazymc::probe", "Connecting to server to probe details...");
 
     // Connect to server, record Forge payload
@@ -58,10 +60,34 @@ pub async fn probe(config: Arc<Config>, server: Arc<Server>) -> Result<(), ()> {
     Ok(())
 }
 
+/// Record the real server version from the server status.
+///
+/// Warns if the protocol differs from the configured protocol hint.
+async fn record_version(config: &Config, server: &Server) {
+    let version = match monitor::poll_server(config, server, config.server.address()).await {
+        Ok(Some(status)) => status.version,
+        _ => {
+            warn!(target: "lazymc::probe", "Couldn't probe server version, failed to get server status");
+            return;
+        }
+    };
+
+    debug!(target: "lazymc::probe", "Probed server version: {} (protocol {})", version.name, version.protocol);
+    if version.protocol != config.public.protocol {
+        warn!(
+            target: "lazymc::probe",
+            "Configured 'public.protocol' ({}) differs from real server protocol ({}), using the real version. Set 'public.version = \"{}\"' and 'public.protocol = {}' to remove this warning",
+            config.public.protocol, version.protocol, version.name, version.protocol,
+        );
+    }
+
+    server.probed_version.write().await.replace(version);
+}
+
 /// Wait for the server to come online.
 ///
-/// Returns `true` when it is online.
-async fn wait_until_online<'a>(server: &Server) -> Result<bool, ()> {
+/// Returns `true` when it is online, `false` if it didn't come online within the timeout.
+async fn wait_until_online<'a>(server: &Server, timeout: Duration) -> Result<bool, ()> {
     trace!(target: "lazymc::probe", "Waiting for server to come online...");
 
     // A task to wait for suitable server state
@@ -69,14 +95,9 @@ async fn wait_until_online<'a>(server: &Server) -> Result<bool, ()> {
     let task_wait = async {
         let mut state = server.state_receiver();
         loop {
-            // Wait for state change
-            state.changed().await.unwrap();
-
-            match state.borrow().deref() {
+            match state.borrow_and_update().deref() {
                 // Still waiting on server start
-                State::Starting => {
-                    continue;
-                }
+                State::Starting => {}
 
                 // Server started, start relaying and proxy
                 State::Started => {
@@ -95,16 +116,19 @@ async fn wait_until_online<'a>(server: &Server) -> Result<bool, ()> {
                     break false;
                 }
             }
+
+            // Wait for state change
+            state.changed().await.unwrap();
         }
     };
 
     // Wait for server state with timeout
-    match time::timeout(PROBE_ONLINE_TIMEOUT, task_wait).await {
+    match time::timeout(timeout, task_wait).await {
         Ok(online) => Ok(online),
 
         // Timeout reached, kick with starting message
         Err(_) => {
-            warn!(target: "lazymc::probe", "Probe waited for server to come online but timed out after {}s", PROBE_ONLINE_TIMEOUT.as_secs());
+            warn!(target: "lazymc::probe", "Probe waited for server to come online but timed out after {}s", timeout.as_secs());
             Ok(false)
         }
     }
@@ -138,7 +162,8 @@ async fn connect_to_server_no_timeout(
 ) -> Result<Vec<Vec<u8>>, ()> {
     // Open connection
     // TODO: on connect fail, ping server and redirect to serve_status if offline
-    let mut outbound = TcpStream::connect(config.server.address)
+    // Internal probes are not subject to the outbound connection limit
+    let mut outbound = TcpStream::connect(config.server.address())
         .await
         .map_err(|_| ())?;
 
@@ -149,36 +174,41 @@ async fn connect_to_server_no_timeout(
     };
     tmp_client.set_state(ClientState::Login);
 
-    // Construct client info
+    // Construct client info, use the real server protocol if known
+    let protocol = server.protocol(config).await;
     let mut tmp_client_info = ClientInfo::empty();
-    tmp_client_info.protocol.replace(config.public.protocol);
+    tmp_client_info.protocol.replace(protocol);
 
     let (mut reader, mut writer) = outbound.split();
 
     // Select server address to use, add magic if Forge
-    let server_addr = if config.server.forge {
-        format!("{}{}", config.server.address.ip(), forge::STATUS_MAGIC)
-    } else {
-        config.server.address.ip().to_string()
-    };
+    let server_addr = format!(
+        "{}{}",
+        config.server.address().ip(),
+        forge::handshake_marker(config.server.mod_loader(), protocol).unwrap_or_default(),
+    );
+
+    // Player info to forward to server, the probe connects from lazymc itself
+    #[cfg(feature = "forwarding")]
+    let player =
+        forwarding::PlayerInfo::new(tmp_client.peer.ip(), config.server.probe_username.clone());
 
     // Send handshake packet
-    packet::write_packet(
-        Handshake {
-            protocol_version: config.public.protocol as i32,
-            server_addr,
-            server_port: config.server.address.port(),
-            next_state: ClientState::Login.to_id(),
-        },
-        &tmp_client,
-        &mut writer,
-    )
-    .await?;
+    #[allow(unused_mut)]
+    let mut handshake = Handshake {
+        protocol_version: protocol as i32,
+        server_addr,
+        server_port: config.server.address().port(),
+        next_state: ClientState::Login.to_id(),
+    };
+    #[cfg(feature = "forwarding")]
+    forwarding::rewrite_handshake(config, &mut handshake, &player);
+    packet::write_packet(handshake, &tmp_client, &mut writer).await?;
 
     // Request login start
     packet::write_packet(
         LoginStart {
-            name: PROBE_USER.into(),
+            name: config.server.probe_username.clone(),
         },
         &tmp_client,
         &mut writer,
@@ -188,6 +218,7 @@ async fn connect_to_server_no_timeout(
     // Incoming buffer, record Forge plugin request payload
     let mut buf = BytesMut::new();
     let mut forge_payload = Vec::new();
+    let mut unhandled = Unhandled::new("lazymc::probe", "probe connect_to_server");
 
     loop {
         // Read packet from stream
@@ -210,15 +241,8 @@ async fn connect_to_server_no_timeout(
             let set_compression =
                 SetCompression::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
 
-            // Client and server compression threshold should match, show warning if not
-            if set_compression.threshold != proto::COMPRESSION_THRESHOLD {
-                error!(
-                    target: "lazymc::forge",
-                    "Compression threshold sent to lobby client does not match threshold from server, this may cause errors (client: {}, server: {})",
-                    proto::COMPRESSION_THRESHOLD,
-                    set_compression.threshold
-                );
-            }
+            // Remember server threshold, lobby clients adopt it
+            server.set_compression_threshold(set_compression.threshold);
 
             // Set client compression
             tmp_client.set_compression(set_compression.threshold);
@@ -234,8 +258,16 @@ async fn connect_to_server_no_timeout(
                 error!(target: "lazymc::probe", "Failed to decode login plugin request from server, cannot respond properly: {:?}", err);
             })?;
 
+            // Respond with forwarded player info
+            #[cfg(feature = "forwarding")]
+            if let Some(response) = forwarding::plugin_response(config, &plugin_request, &player)? {
+                trace!(target: "lazymc::probe", "Got player info request from server, forwarding player info");
+                packet::write_packet(response, &tmp_client, &mut writer).await?;
+                continue;
+            }
+
             // Handle plugin requests for Forge
-            if config.server.forge {
+            if config.server.is_modded() && forge::is_login_wrapper(&plugin_request) {
                 // Record Forge login payload
                 forge_payload.push(raw);
 
@@ -269,6 +301,11 @@ async fn connect_to_server_no_timeout(
             // Switch to play state
             tmp_client.set_state(ClientState::Play);
 
+            // Server did not enable compression if not set yet
+            if !tmp_client.is_compressed() {
+                server.set_compression_threshold(-1);
+            }
+
             // Wait to catch join game packet
             let join_game_data =
                 wait_for_server_join_game(&tmp_client, &tmp_client_info, &mut outbound, &mut buf)
@@ -285,10 +322,7 @@ async fn connect_to_server_no_timeout(
             return Ok(forge_payload);
         }
 
-        // Show unhandled packet warning
-        debug!(target: "lazymc::forge", "Got unhandled packet from server in connect_to_server:");
-        debug!(target: "lazymc::forge", "- State: {:?}", client_state);
-        debug!(target: "lazymc::forge", "- Packet ID: 0x{:02X} ({})", packet.id, packet.id);
+        unhandled.record(client_state, packet.id);
     }
 
     // Gracefully close connection
@@ -328,6 +362,7 @@ async fn wait_for_server_join_game_no_timeout(
     buf: &mut BytesMut,
 ) -> Result<JoinGameData, ()> {
     let (mut reader, mut _writer) = outbound.split();
+    let mut unhandled = Unhandled::new("lazymc::probe", "probe wait_for_server_join_game");
 
     loop {
         // Read packet from stream
@@ -350,9 +385,7 @@ async fn wait_for_server_join_game_no_timeout(
             return Ok(join_game_data);
         }
 
-        // Show unhandled packet warning
-        debug!(target: "lazymc::probe", "Got unhandled packet from server in wait_for_server_join_game:");
-        debug!(target: "lazymc::probe", "- Packet ID: 0x{:02X} ({})", packet.id, packet.id);
+        unhandled.record(ClientState::Play, packet.id);
     }
 
     // Gracefully close connection
@@ -4,6 +4,8 @@ use std::sync::Mutex;
 
 use minecraft_protocol::version::v1_14_4::handshake::Handshake;
 
+use crate::forge;
+
 /// Client state.
 ///
 /// Note: this does not keep track of encryption states.
@@ -64,6 +66,12 @@ impl Client {
     }
 }
 
+/// Handshake next state ID for clients transferred from another server, Minecraft 1.20.5+.
+pub const TRANSFER_STATE_ID: i32 = 3;
+
+/// Client brands that can be detected before joining, see `ClientInfo::brand`.
+pub const CLIENT_BRANDS: [&str; 2] = ["vanilla", "forge"];
+
 /// Protocol state a client may be in.
 ///
 /// Note: this does not include the `play` state, because this is never used anymore when a client
@@ -86,11 +94,14 @@ pub enum ClientState {
 
 impl ClientState {
     /// From state ID.
+    ///
+    /// The transfer state ID (Minecraft 1.20.5+) maps to the login state, as a transferred client
+    /// logs in just like any other.
     pub fn from_id(id: i32) -> Option<Self> {
         match id {
             0 => Some(Self::Handshake),
             1 => Some(Self::Status),
-            2 => Some(Self::Login),
+            2 | TRANSFER_STATE_ID => Some(Self::Login),
             _ => None,
         }
     }
@@ -126,6 +137,27 @@ pub struct ClientInfo {
 }
 
 impl ClientInfo {
+    /// Best-effort client brand, derived from the handshake.
+    ///
+    /// Always one of `CLIENT_BRANDS`.
+    ///
+    /// The real brand is only sent in the play state, which clients don't reach before the server
+    /// is started. Forge clients are detected through the marker in the handshake server address,
+    /// all other clients are reported as `vanilla`. NeoForge clients on Minecraft 1.20.2 and above
+    /// don't mark their handshake, and are reported as `vanilla` too. Clients can spoof this.
+    pub fn brand(&self) -> &'static str {
+        match self.handshake {
+            Some(ref handshake)
+                if forge::HANDSHAKE_MARKERS
+                    .iter()
+                    .any(|marker| handshake.server_addr.contains(marker)) =>
+            {
+                "forge"
+            }
+            _ => "vanilla",
+        }
+    }
+
     pub fn empty() -> Self {
         Self::default()
     }
@@ -1,7 +1,10 @@
+use std::sync::OnceLock;
+
 pub mod action;
 pub mod client;
 pub mod packet;
 pub mod packets;
+pub mod unhandled;
 
 /// Default minecraft protocol version name.
 ///
@@ -19,9 +22,28 @@ pub const PROTO_DEFAULT_VERSION: &str = "1.20.3";
 /// Should be kept up-to-date with latest supported Minecraft version by lazymc.
 pub const PROTO_DEFAULT_PROTOCOL: u32 = 765;
 
-/// Compression threshold to use.
-// TODO: read this from server.properties instead
-pub const COMPRESSION_THRESHOLD: i32 = 256;
+/// Default compression threshold, used by the server if not configured in `server.properties`.
+pub const DEFAULT_COMPRESSION_THRESHOLD: i32 = 256;
+
+/// Compression threshold configured for the server, the default is used if not set.
+static COMPRESSION_THRESHOLD: OnceLock<i32> = OnceLock::new();
+
+/// Set the compression threshold configured for the server.
+///
+/// Negative values disable compression. Should be called once at startup.
+pub fn set_compression_threshold(threshold: i32) {
+    if COMPRESSION_THRESHOLD.set(threshold.max(-1)).is_err() {
+        warn!(target: "lazymc", "Compression threshold already set, ignoring");
+    }
+}
+
+/// Get the compression threshold configured for the server, negative if disabled.
+pub fn compression_threshold() -> i32 {
+    COMPRESSION_THRESHOLD
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_COMPRESSION_THRESHOLD)
+}
 
 /// Default buffer size when reading packets.
 pub(super) const BUF_SIZE: usize = 8 * 1024;
@@ -1,5 +1,7 @@
 use std::fmt::Debug;
 use std::io::prelude::*;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use bytes::BytesMut;
 use flate2::read::ZlibDecoder;
@@ -10,10 +12,31 @@ use minecraft_protocol::version::PacketId;
 use tokio::io;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::tcp::{ReadHalf, WriteHalf};
+use tokio::time::{self, Instant};
 
 use crate::proto::client::Client;
 use crate::proto::BUF_SIZE;
-use crate::types;
+use crate::types::{self, VarIntError};
+
+/// Maximum packet length, as limited by Minecraft.
+const MAX_PACKET_LEN: i32 = (1 << 21) - 1;
+
+/// Maximum uncompressed packet data length, as limited by Minecraft.
+const MAX_DATA_LEN: i32 = 1 << 23;
+
+/// Maximum time to receive an inbound client packet in once its first bytes arrived, no limit if
+/// not set.
+static READ_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+/// Limit the time to receive an inbound client packet in once its first bytes arrived.
+///
+/// Drops slow clients that dribble packets to keep connections open. Applies to
+/// `read_inbound_packet` only. Should be called once at startup, before any packet is read.
+pub fn set_read_timeout(timeout: Duration) {
+    if READ_TIMEOUT.set(timeout).is_err() {
+        warn!(target: "lazymc", "Packet read timeout already set, ignoring");
+    }
+}
 
 /// Raw Minecraft packet.
 ///
@@ -35,7 +58,7 @@ impl RawPacket {
     /// Read packet ID from buffer, use remaining buffer as data.
     fn read_packet_id_data(mut buf: &[u8]) -> Result<Self, ()> {
         // Read packet ID, select buf
-        let (read, packet_id) = types::read_var_int(buf)?;
+        let (read, packet_id) = types::read_var_int(buf).map_err(|_| ())?;
         buf = &buf[read..];
 
         Ok(Self::new(packet_id as u8, buf.to_vec()))
@@ -47,10 +70,14 @@ impl RawPacket {
     /// preference.
     pub fn decode_with_len(client: &Client, mut buf: &[u8]) -> Result<Self, ()> {
         // Read length
-        let (read, len) = types::read_var_int(buf)?;
-        buf = &buf[read..][..len as usize];
+        let (read, len) = types::read_var_int(buf).map_err(|_| ())?;
+        buf = &buf[read..];
 
-        // TODO: assert buffer length!
+        // Buffer must contain full packet
+        if len < 0 || len as usize > buf.len() {
+            return Err(());
+        }
+        buf = &buf[..len as usize];
 
         Self::decode_without_len(client, buf)
     }
@@ -68,9 +95,15 @@ impl RawPacket {
         }
 
         // Read data length
-        let (read, data_len) = types::read_var_int(buf)?;
+        let (read, data_len) = types::read_var_int(buf).map_err(|_| ())?;
         buf = &buf[read..];
 
+        // Data length must be sane
+        if !(0..=MAX_DATA_LEN).contains(&data_len) {
+            error!(target: "lazymc", "Malformed packet, invalid data length ({})", data_len);
+            return Err(());
+        }
+
         // If data length is zero, the rest is not compressed
         if data_len == 0 {
             return Self::read_packet_id_data(buf);
@@ -79,6 +112,7 @@ impl RawPacket {
         // Decompress packet ID and data section
         let mut decompressed = Vec::with_capacity(data_len as usize);
         ZlibDecoder::new(buf)
+            .take(data_len as u64 + 1)
             .read_to_end(&mut decompressed)
             .map_err(|err| {
                 error!(target: "lazymc", "Packet decompression error: {}", err);
@@ -158,57 +192,64 @@ impl RawPacket {
 }
 
 /// Read raw packet from stream.
+///
+/// No read timeout is applied, use `read_inbound_packet` for inbound client streams.
 pub async fn read_packet(
     client: &Client,
     buf: &mut BytesMut,
     stream: &mut ReadHalf<'_>,
 ) -> Result<Option<(RawPacket, Vec<u8>)>, ()> {
-    // Keep reading until we have at least 2 bytes
-    while buf.len() < 2 {
-        // Read packet from socket
-        let mut tmp = Vec::with_capacity(BUF_SIZE);
-        match stream.read_buf(&mut tmp).await {
-            Ok(_) => {}
-            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => return Ok(None),
-            Err(err) => {
-                dbg!(err);
+    read_packet_within(client, buf, stream, None).await
+}
+
+/// Read raw packet from an inbound client stream.
+///
+/// Errors if the packet read timeout is reached once the first bytes of the packet arrived.
+pub async fn read_inbound_packet(
+    client: &Client,
+    buf: &mut BytesMut,
+    stream: &mut ReadHalf<'_>,
+) -> Result<Option<(RawPacket, Vec<u8>)>, ()> {
+    read_packet_within(client, buf, stream, READ_TIMEOUT.get().copied()).await
+}
+
+/// Read raw packet from stream, within the given timeout once its first bytes arrived.
+async fn read_packet_within(
+    client: &Client,
+    buf: &mut BytesMut,
+    stream: &mut ReadHalf<'_>,
+    timeout: Option<Duration>,
+) -> Result<Option<(RawPacket, Vec<u8>)>, ()> {
+    // Deadline to receive the full packet in, set once its first bytes arrived
+    let mut deadline = None;
+
+    // Keep reading until we have the packet length
+    let (consumed, len) = loop {
+        match types::read_var_int(buf) {
+            Ok(result) => break result,
+            Err(VarIntError::Incomplete) => {}
+            Err(VarIntError::Invalid) => {
+                error!(target: "lazymc", "Malformed packet, packet length is not a valid var-int, dropping connection");
                 return Err(());
             }
         }
 
-        if tmp.is_empty() {
+        if !read_more(buf, stream, timeout, &mut deadline).await? {
             return Ok(None);
         }
-        buf.extend(tmp);
-    }
-
-    // Attempt to read packet length
-    let (consumed, len) = match types::read_var_int(buf) {
-        Ok(result) => result,
-        Err(err) => {
-            error!(target: "lazymc", "Malformed packet, could not read packet length");
-            return Err(err);
-        }
     };
 
+    // Packet length must be sane
+    if !(0..=MAX_PACKET_LEN).contains(&len) {
+        error!(target: "lazymc", "Malformed packet, invalid packet length ({}), dropping connection", len);
+        return Err(());
+    }
+
     // Keep reading until we have all packet bytes
     while buf.len() < consumed + len as usize {
-        // Read packet from socket
-        let mut tmp = Vec::with_capacity(BUF_SIZE);
-        match stream.read_buf(&mut tmp).await {
-            Ok(_) => {}
-            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => return Ok(None),
-            Err(err) => {
-                dbg!(err);
-                return Err(());
-            }
-        }
-
-        if tmp.is_empty() {
+        if !read_more(buf, stream, timeout, &mut deadline).await? {
             return Ok(None);
         }
-
-        buf.extend(tmp);
     }
 
     // Parse packet, use full buffer since we'll read the packet length again
@@ -219,6 +260,51 @@ pub async fn read_packet(
     Ok(Some((packet, raw.to_vec())))
 }
 
+/// Read more bytes from the stream into the buffer.
+///
+/// Applies the given timeout if the buffer holds a partial packet, the deadline is set on first
+/// use. Returns `false` if the stream was closed.
+async fn read_more(
+    buf: &mut BytesMut,
+    stream: &mut ReadHalf<'_>,
+    timeout: Option<Duration>,
+    deadline: &mut Option<Instant>,
+) -> Result<bool, ()> {
+    if let Some(timeout) = timeout {
+        if !buf.is_empty() && deadline.is_none() {
+            deadline.replace(Instant::now() + timeout);
+        }
+    }
+
+    // Read packet from socket
+    let mut tmp = Vec::with_capacity(BUF_SIZE);
+    let result = match deadline {
+        Some(deadline) => match time::timeout_at(*deadline, stream.read_buf(&mut tmp)).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!(target: "lazymc", "Client sent packet too slowly, dropping connection");
+                return Err(());
+            }
+        },
+        None => stream.read_buf(&mut tmp).await,
+    };
+    match result {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::ConnectionReset => return Ok(false),
+        Err(err) => {
+            dbg!(err);
+            return Err(());
+        }
+    }
+
+    if tmp.is_empty() {
+        return Ok(false);
+    }
+    buf.extend(tmp);
+
+    Ok(true)
+}
+
 /// Write packet to stream writer.
 pub async fn write_packet(
     packet: impl PacketId + Encoder + Debug,
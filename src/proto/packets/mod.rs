@@ -6,6 +6,9 @@ pub mod handshake {
     use minecraft_protocol::version::v1_14_4::handshake::*;
 
     pub const SERVER_HANDSHAKE: u8 = Handshake::PACKET_ID;
+
+    /// First byte of the legacy server list ping, sent by pre-1.7 clients instead of a handshake.
+    pub const LEGACY_PING: u8 = 0xFE;
 }
 
 pub mod status {
@@ -20,14 +23,12 @@ pub mod status {
 pub mod login {
     use minecraft_protocol::version::v1_14_4::login::*;
 
-    #[cfg(feature = "lobby")]
+    #[cfg(any(feature = "lobby", feature = "forwarding"))]
     pub const CLIENT_DISCONNECT: u8 = LoginDisconnect::PACKET_ID;
     pub const CLIENT_LOGIN_SUCCESS: u8 = LoginSuccess::PACKET_ID;
     pub const CLIENT_SET_COMPRESSION: u8 = SetCompression::PACKET_ID;
-    #[cfg(feature = "lobby")]
     pub const CLIENT_ENCRYPTION_REQUEST: u8 = EncryptionRequest::PACKET_ID;
     pub const CLIENT_LOGIN_PLUGIN_REQUEST: u8 = LoginPluginRequest::PACKET_ID;
     pub const SERVER_LOGIN_START: u8 = LoginStart::PACKET_ID;
-    #[cfg(feature = "lobby")]
     pub const SERVER_LOGIN_PLUGIN_RESPONSE: u8 = LoginPluginResponse::PACKET_ID;
 }
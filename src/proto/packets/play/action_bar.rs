@@ -0,0 +1,56 @@
+use minecraft_protocol::data::chat::{Message, Payload};
+use minecraft_protocol::version::{v1_16_3, v1_17};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::WriteHalf;
+
+use crate::proto::client::{Client, ClientInfo};
+use crate::proto::packet::{self, RawPacket};
+use crate::types;
+
+/// Set action bar text packet ID for Minecraft 1.17.
+///
+/// Not defined by the protocol crate for this version, which only has the separate title packets.
+const PACKET_ID_V1_17: u8 = 0x41;
+
+/// Send lobby action bar packet to client.
+///
+/// Lines are joined with a space as the action bar only has a single line. If an empty string is
+/// given, the action bar is cleared.
+pub async fn send(
+    client: &Client,
+    client_info: &ClientInfo,
+    writer: &mut WriteHalf<'_>,
+    text: &str,
+) -> Result<(), ()> {
+    let text = text.lines().collect::<Vec<_>>().join(" ");
+
+    // Action bar is part of title packet before 1.17, it has its own packet since
+    match client_info.protocol() {
+        Some(p) if p < v1_17::PROTOCOL => send_v1_16_3(client, writer, &text).await,
+        _ => send_v1_17(client, writer, &text).await,
+    }
+}
+
+async fn send_v1_16_3(client: &Client, writer: &mut WriteHalf<'_>, text: &str) -> Result<(), ()> {
+    use v1_16_3::game::{Title, TitleAction};
+
+    packet::write_packet(
+        Title {
+            action: TitleAction::SetActionBar {
+                text: Message::new(Payload::text(text)),
+            },
+        },
+        client,
+        writer,
+    )
+    .await
+}
+
+async fn send_v1_17(client: &Client, writer: &mut WriteHalf<'_>, text: &str) -> Result<(), ()> {
+    let json = serde_json::json!({ "text": text }).to_string();
+    let mut data = types::encode_var_int(json.len() as i32)?;
+    data.extend_from_slice(json.as_bytes());
+
+    let raw = RawPacket::new(PACKET_ID_V1_17, data).encode_with_len(client)?;
+    writer.write_all(&raw).await.map_err(|_| ())
+}
@@ -0,0 +1,65 @@
+use minecraft_protocol::version::v1_17;
+
+use crate::proto::client::ClientInfo;
+use crate::proto::packet::RawPacket;
+use crate::types;
+
+/// Protocol version of Minecraft 1.16.
+const PROTOCOL_V1_16: u32 = 735;
+
+/// Protocol version of Minecraft 1.19.
+///
+/// Starting at this version system messages use a different packet that is not supported.
+const PROTOCOL_V1_19: u32 = 759;
+
+/// Chat message packet ID for Minecraft 1.16.
+const PACKET_ID_V1_16: u8 = 0x0E;
+
+/// Chat message packet ID for Minecraft 1.17 up to 1.18.2.
+const PACKET_ID_V1_17: u8 = 0x0F;
+
+/// Chat message packet ID sent by the client, for Minecraft 1.16 up to 1.18.2.
+const SERVER_PACKET_ID_V1_16: u8 = 0x03;
+
+/// Chat message position for system messages.
+const POSITION_SYSTEM: u8 = 1;
+
+/// Build system chat message packet for the given client.
+///
+/// Returns `None` if this is not supported for the protocol version used by the client.
+pub fn system_message(client_info: &ClientInfo, msg: &str) -> Option<RawPacket> {
+    let id = match client_info.protocol()? {
+        p if p < PROTOCOL_V1_16 => return None,
+        p if p < v1_17::PROTOCOL => PACKET_ID_V1_16,
+        p if p < PROTOCOL_V1_19 => PACKET_ID_V1_17,
+        _ => return None,
+    };
+
+    // Encode JSON message, position and empty sender UUID
+    let json = serde_json::json!({ "text": msg }).to_string();
+    let mut data = types::encode_var_int(json.len() as i32).ok()?;
+    data.extend_from_slice(json.as_bytes());
+    data.push(POSITION_SYSTEM);
+    data.extend_from_slice(&[0; 16]);
+
+    Some(RawPacket::new(id, data))
+}
+
+/// Parse chat message sent by the given client.
+///
+/// Returns `None` if the packet is not a chat message, or if this is not supported for the
+/// protocol version used by the client.
+pub fn parse_message(client_info: &ClientInfo, packet: &RawPacket) -> Option<String> {
+    match client_info.protocol()? {
+        p if (PROTOCOL_V1_16..PROTOCOL_V1_19).contains(&p) => {}
+        _ => return None,
+    }
+    if packet.id != SERVER_PACKET_ID_V1_16 {
+        return None;
+    }
+
+    // Decode message string
+    let (read, len) = types::read_var_int(&packet.data).ok()?;
+    let msg = packet.data.get(read..read + usize::try_from(len).ok()?)?;
+    String::from_utf8(msg.to_vec()).ok()
+}
@@ -104,6 +104,7 @@ pub async fn lobby_send(
     client_info: &ClientInfo,
     writer: &mut WriteHalf<'_>,
     server: &Server,
+    game_mode: u8,
 ) -> Result<(), ()> {
     let status = server.status().await;
     let join_game = server.probed_join_game.read().await;
@@ -159,7 +160,7 @@ pub async fn lobby_send(
                     // in a weird state and cannot move
                     entity_id: 0,
                     hardcore,
-                    game_mode: 3,
+                    game_mode,
                     previous_game_mode: -1i8 as u8,
                     world_names,
                     dimension_codec,
@@ -185,7 +186,7 @@ pub async fn lobby_send(
                     // in a weird state and cannot move
                     entity_id: 0,
                     hardcore,
-                    game_mode: 3,
+                    game_mode,
                     previous_game_mode: -1i8 as u8,
                     world_names,
                     dimension_codec,
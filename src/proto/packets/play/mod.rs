@@ -1,3 +1,6 @@
+#[cfg(feature = "lobby")]
+pub mod action_bar;
+pub mod chat;
 pub mod join_game;
 #[cfg(feature = "lobby")]
 pub mod keep_alive;
@@ -1,24 +1,26 @@
 use minecraft_protocol::version::{v1_16_3, v1_17};
 use tokio::net::tcp::WriteHalf;
 
+use crate::config::LobbySpawn;
 use crate::proto::client::{Client, ClientInfo};
 use crate::proto::packet;
 
-/// Move player to world origin.
+/// Move player to spawn position.
 pub async fn send(
     client: &Client,
     client_info: &ClientInfo,
     writer: &mut WriteHalf<'_>,
+    spawn: &LobbySpawn,
 ) -> Result<(), ()> {
     match client_info.protocol() {
         Some(p) if p < v1_17::PROTOCOL => {
             packet::write_packet(
                 v1_16_3::game::PlayerPositionAndLook {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                    yaw: 0.0,
-                    pitch: 90.0,
+                    x: spawn.x,
+                    y: spawn.y,
+                    z: spawn.z,
+                    yaw: spawn.yaw,
+                    pitch: spawn.pitch,
                     flags: 0b00000000,
                     teleport_id: 0,
                 },
@@ -30,11 +32,11 @@ pub async fn send(
         _ => {
             packet::write_packet(
                 v1_17::game::PlayerPositionAndLook {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                    yaw: 0.0,
-                    pitch: 90.0,
+                    x: spawn.x,
+                    y: spawn.y,
+                    z: spawn.z,
+                    yaw: spawn.yaw,
+                    pitch: spawn.pitch,
                     flags: 0b00000000,
                     teleport_id: 0,
                     dismount_vehicle: true,
@@ -68,3 +68,12 @@ pub async fn lobby_send(
         }
     }
 }
+
+/// Check whether the packet ID matches.
+#[cfg(feature = "dev")]
+pub fn is_packet(client_info: &ClientInfo, packet_id: u8) -> bool {
+    match client_info.protocol() {
+        Some(p) if p < v1_17::PROTOCOL => packet_id == v1_16_3::game::Respawn::PACKET_ID,
+        _ => packet_id == v1_17::game::Respawn::PACKET_ID,
+    }
+}
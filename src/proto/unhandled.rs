@@ -0,0 +1,75 @@
+use std::env;
+
+use crate::proto::client::ClientState;
+
+/// Environment variable to log every unhandled packet, instead of a summary.
+const ENV_LOG_EACH: &str = "LAZYMC_LOG_UNHANDLED_PACKETS";
+
+/// Aggregator for unhandled packets in a packet loop.
+///
+/// Counts unhandled packets per state and packet ID, and logs a summary at debug level when
+/// dropped. Set `LAZYMC_LOG_UNHANDLED_PACKETS=1` to log every packet as it is received instead.
+pub struct Unhandled {
+    /// Log target.
+    target: &'static str,
+
+    /// Context, the packet loop this is used in.
+    context: &'static str,
+
+    /// Count of unhandled packets per state and packet ID.
+    counts: Vec<(ClientState, u8, usize)>,
+}
+
+impl Unhandled {
+    /// Construct new aggregator for the packet loop in the given context.
+    pub fn new(target: &'static str, context: &'static str) -> Self {
+        Self {
+            target,
+            context,
+            counts: Vec::new(),
+        }
+    }
+
+    /// Record an unhandled packet.
+    pub fn record(&mut self, state: ClientState, packet_id: u8) {
+        if log_each() {
+            debug!(target: self.target, "Got unhandled packet in {}:", self.context);
+            debug!(target: self.target, "- State: {:?}", state);
+            debug!(target: self.target, "- Packet ID: 0x{:02X} ({})", packet_id, packet_id);
+            return;
+        }
+
+        match self
+            .counts
+            .iter_mut()
+            .find(|(s, id, _)| *s == state && *id == packet_id)
+        {
+            Some((_, _, count)) => *count += 1,
+            None => self.counts.push((state, packet_id, 1)),
+        }
+    }
+}
+
+impl Drop for Unhandled {
+    fn drop(&mut self) {
+        if self.counts.is_empty() {
+            return;
+        }
+
+        let total: usize = self.counts.iter().map(|(_, _, count)| count).sum();
+        let summary = self
+            .counts
+            .iter()
+            .map(|(state, id, count)| format!("{:?} 0x{:02X}: {}x", state, id, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        debug!(target: self.target, "Got {} unhandled packets in {} ({})", total, self.context, summary);
+    }
+}
+
+/// Whether to log every unhandled packet.
+fn log_each() -> bool {
+    env::var(ENV_LOG_EACH)
+        .map(|v| !v.is_empty() && v != "0")
+        .unwrap_or(false)
+}
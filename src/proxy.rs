@@ -1,52 +1,205 @@
 use std::error::Error;
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
+use minecraft_protocol::decoder::Decoder;
+use minecraft_protocol::version::v1_14_4::login::SetCompression;
 use proxy_protocol::version2::{ProxyAddresses, ProxyCommand, ProxyTransportProtocol};
 use proxy_protocol::EncodeError;
 use tokio::io;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
+use tokio::time;
 
-use crate::net;
+use crate::config::{ProxyVersion, SendProxy};
+use crate::net::{self, OutboundPermit};
+use crate::proto::client::{Client, ClientState};
+use crate::proto::packet::{self, RawPacket};
+use crate::proto::packets;
+
+/// Per-connection bandwidth limit in bytes per second for each direction, unlimited if not set.
+static BANDWIDTH_LIMIT: OnceLock<u64> = OnceLock::new();
+
+/// Maximum size of chunks copied at once when the bandwidth is limited.
+const LIMITED_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Signature proxy protocol v2 headers start with.
+const PROXY_V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Maximum length of a proxy protocol v1 header, including CRLF.
+const PROXY_V1_MAX_LEN: usize = 107;
+
+/// Time to receive the proxy header of an inbound connection in.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Limit the bandwidth of each proxied connection, in bytes per second.
+///
+/// Should be called once at startup, before any connection is proxied.
+pub fn set_bandwidth_limit(bytes_per_sec: u64) {
+    if BANDWIDTH_LIMIT.set(bytes_per_sec).is_err() {
+        warn!(target: "lazymc", "Bandwidth limit already set, ignoring");
+    }
+}
 
 /// Proxy the inbound stream to a target address.
-pub async fn proxy(
+///
+/// Send the queue to the target server before proxying.
+pub async fn proxy_with_queue(
     inbound: TcpStream,
     proxy_header: ProxyHeader,
-    addr_target: SocketAddr,
+    addrs_target: &[SocketAddr],
+    queue: &[u8],
 ) -> Result<(), Box<dyn Error>> {
-    proxy_with_queue(inbound, proxy_header, addr_target, &[]).await
+    let (outbound, _permit) = connect_outbound(&inbound, proxy_header, addrs_target).await?;
+
+    // Start proxy on both streams
+    proxy_inbound_outbound_with_queue(inbound, outbound, &[], queue).await
 }
 
-/// Proxy the inbound stream to a target address.
+/// Proxy the inbound stream to an already connected outbound stream.
 ///
-/// Send the queue to the target server before proxying.
-pub async fn proxy_with_queue(
+/// Send the proxy header and the queue to the target server before proxying.
+pub async fn proxy_connected_with_queue(
     inbound: TcpStream,
+    mut outbound: TcpStream,
     proxy_header: ProxyHeader,
-    addr_target: SocketAddr,
     queue: &[u8],
 ) -> Result<(), Box<dyn Error>> {
+    write_proxy_header(&inbound, &mut outbound, proxy_header).await?;
+    proxy_inbound_outbound_with_queue(inbound, outbound, &[], queue).await
+}
+
+/// Proxy the inbound stream to a target address, injecting a packet once the client joins.
+///
+/// Send the queue to the target server before proxying. The `inject` packet is sent to the client
+/// right after the first play packet from the server, after which the connection is proxied
+/// transparently.
+pub async fn proxy_with_queue_inject(
+    inbound: TcpStream,
+    proxy_header: ProxyHeader,
+    addrs_target: &[SocketAddr],
+    queue: &[u8],
+    inject: RawPacket,
+) -> Result<(), Box<dyn Error>> {
+    let (mut outbound, _permit) = connect_outbound(&inbound, proxy_header, addrs_target).await?;
+    let mut inbound = inbound;
+
+    let (mut ri, mut wi) = inbound.split();
+    let (mut ro, mut wo) = outbound.split();
+
+    // Forward queued bytes to server once writable
+    if !queue.is_empty() {
+        wo.writable().await?;
+        trace!(target: "lazymc", "Relaying {} queued bytes to server", queue.len());
+        wo.write_all(queue).await?;
+    }
+
+    let client_to_server = async {
+        copy(&mut ri, &mut wo).await?;
+        wo.shutdown().await
+    };
+    let server_to_client = async {
+        relay_until_play_inject(&mut ro, &mut wi, &inject).await?;
+        copy(&mut ro, &mut wi).await?;
+        wi.shutdown().await
+    };
+
+    tokio::try_join!(client_to_server, server_to_client)?;
+
+    // Gracefully close connection if not done already
+    net::close_tcp_stream(inbound).await?;
+
+    Ok(())
+}
+
+/// Relay server packets to the client until the first play packet, then inject the given packet.
+///
+/// Stops relaying packet-by-packet if the connection becomes encrypted, in which case nothing is
+/// injected. Any buffered data is relayed before returning.
+async fn relay_until_play_inject(
+    reader: &mut ReadHalf<'_>,
+    writer: &mut WriteHalf<'_>,
+    inject: &RawPacket,
+) -> Result<(), io::Error> {
+    // Track server connection state as seen by the client
+    let client = Client::dummy();
+    client.set_state(ClientState::Login);
+    let mut buf = BytesMut::new();
+
+    loop {
+        // Read packet from stream
+        let (packet, raw) = match packet::read_packet(&client, &mut buf, reader).await {
+            Ok(Some(packet)) => packet,
+            Ok(None) => return Ok(()),
+            Err(_) => return Err(io::Error::other("failed to read packet from server")),
+        };
+        writer.write_all(&raw).await?;
+
+        match client.state() {
+            ClientState::Login if packet.id == packets::login::CLIENT_SET_COMPRESSION => {
+                let set_compression = SetCompression::decode(&mut packet.data.as_slice())
+                    .map_err(|_| io::Error::other("failed to decode set compression packet"))?;
+                client.set_compression(set_compression.threshold);
+            }
+            ClientState::Login if packet.id == packets::login::CLIENT_ENCRYPTION_REQUEST => {
+                debug!(target: "lazymc", "Server connection is encrypted, cannot inject packet for client");
+                break;
+            }
+            ClientState::Login if packet.id == packets::login::CLIENT_LOGIN_SUCCESS => {
+                client.set_state(ClientState::Play);
+            }
+            ClientState::Play => {
+                trace!(target: "lazymc", "Client joined server, injecting packet");
+                let data = inject
+                    .encode_with_len(&client)
+                    .map_err(|_| io::Error::other("failed to encode injected packet"))?;
+                writer.write_all(&data).await?;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    // Relay remaining buffered data
+    writer.write_all(&buf).await
+}
+
+/// Connect to the first reachable target address for the given inbound stream.
+///
+/// Sends the proxy header if configured. The returned permit must be kept while connected.
+pub async fn connect_outbound(
+    inbound: &TcpStream,
+    proxy_header: ProxyHeader,
+    addrs_target: &[SocketAddr],
+) -> Result<(TcpStream, OutboundPermit), Box<dyn Error>> {
     // Set up connection to server
     // TODO: on connect fail, ping server and redirect to serve_status if offline
-    let mut outbound = TcpStream::connect(addr_target).await?;
+    let (mut outbound, permit) = net::connect_first(addrs_target).await?;
+    write_proxy_header(inbound, &mut outbound, proxy_header).await?;
+    Ok((outbound, permit))
+}
 
-    // Add proxy header
+/// Write the given proxy header to an outbound connection for the inbound stream.
+async fn write_proxy_header(
+    inbound: &TcpStream,
+    outbound: &mut TcpStream,
+    proxy_header: ProxyHeader,
+) -> Result<(), Box<dyn Error>> {
     match proxy_header {
         ProxyHeader::None => {}
-        ProxyHeader::Local => {
-            let header = local_proxy_header()?;
+        ProxyHeader::Local(version) => {
+            let header = local_proxy_header(version)?;
             outbound.write_all(&header).await?;
         }
-        ProxyHeader::Proxy => {
-            let header = stream_proxy_header(&inbound)?;
+        ProxyHeader::Proxy(version, peer) => {
+            let header = stream_proxy_header(inbound, peer, version)?;
             outbound.write_all(&header).await?;
         }
     }
-
-    // Start proxy on both streams
-    proxy_inbound_outbound_with_queue(inbound, outbound, &[], queue).await
+    Ok(())
 }
 
 /// Proxy the inbound stream to a target address.
@@ -77,11 +230,11 @@ pub async fn proxy_inbound_outbound_with_queue(
     }
 
     let client_to_server = async {
-        io::copy(&mut ri, &mut wo).await?;
+        copy(&mut ri, &mut wo).await?;
         wo.shutdown().await
     };
     let server_to_client = async {
-        io::copy(&mut ro, &mut wi).await?;
+        copy(&mut ro, &mut wi).await?;
         wi.shutdown().await
     };
 
@@ -93,6 +246,55 @@ pub async fn proxy_inbound_outbound_with_queue(
     Ok(())
 }
 
+/// Copy all data from reader to writer, respecting the bandwidth limit.
+///
+/// Uses a plain copy if no limit is set.
+async fn copy<R, W>(reader: &mut R, writer: &mut W) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    match BANDWIDTH_LIMIT.get() {
+        Some(&limit) => copy_limited(reader, writer, limit).await,
+        None => io::copy(reader, writer).await,
+    }
+}
+
+/// Copy all data from reader to writer, limited to the given bytes per second.
+///
+/// Uses a token bucket allowing bursts of up to one second worth of data.
+async fn copy_limited<R, W>(reader: &mut R, writer: &mut W, limit: u64) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let limit = limit.max(1) as f64;
+    let mut buf = vec![0; LIMITED_CHUNK_SIZE.min(limit as usize)];
+    let mut allowance = limit;
+    let mut last = Instant::now();
+    let mut total = 0;
+
+    loop {
+        let read = reader.read(&mut buf).await?;
+        if read == 0 {
+            writer.flush().await?;
+            return Ok(total);
+        }
+
+        // Refill allowance for passed time, wait if exceeded
+        let now = Instant::now();
+        allowance = (allowance + now.duration_since(last).as_secs_f64() * limit).min(limit);
+        last = now;
+        allowance -= read as f64;
+        if allowance < 0.0 {
+            time::sleep(Duration::from_secs_f64(-allowance / limit)).await;
+        }
+
+        writer.write_all(&buf[..read]).await?;
+        total += read as u64;
+    }
+}
+
 /// Proxy header.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ProxyHeader {
@@ -101,21 +303,20 @@ pub enum ProxyHeader {
 
     /// Header for locally initiated connection.
     #[allow(unused)]
-    Local,
+    Local(ProxyVersion),
 
-    /// Header for proxied connection.
-    Proxy,
+    /// Header for proxied connection from the given client address.
+    Proxy(ProxyVersion, SocketAddr),
 }
 
 impl ProxyHeader {
-    /// Changes to `None` if `false` if given.
+    /// Header for proxied connection from the given client address with the given version.
     ///
-    /// `None` stays `None`.
-    pub fn not_none(self, not_none: bool) -> Self {
-        if not_none {
-            self
-        } else {
-            Self::None
+    /// Is `None` if no header should be sent.
+    pub fn proxy(send_proxy: SendProxy, peer: SocketAddr) -> Self {
+        match send_proxy.version() {
+            Some(version) => Self::Proxy(version, peer),
+            None => Self::None,
         }
     }
 }
@@ -123,7 +324,12 @@ impl ProxyHeader {
 /// Get the proxy header for a locally initiated connection.
 ///
 /// This header may be sent over the outbound stream to signal client information.
-pub fn local_proxy_header() -> Result<BytesMut, EncodeError> {
+pub fn local_proxy_header(version: ProxyVersion) -> Result<BytesMut, EncodeError> {
+    // Version 1 has no local command, unknown addresses must be ignored by the receiver
+    if version == ProxyVersion::V1 {
+        return Ok(BytesMut::from(&b"PROXY UNKNOWN\r\n"[..]));
+    }
+
     // Build proxy header
     let header = proxy_protocol::ProxyHeader::Version2 {
         command: ProxyCommand::Local,
@@ -134,34 +340,253 @@ pub fn local_proxy_header() -> Result<BytesMut, EncodeError> {
     proxy_protocol::encode(header)
 }
 
-/// Get the proxy header for the given inbound stream.
+/// Get the proxy header for the given inbound stream from the given client address.
 ///
 /// This header may be sent over the outbound stream to signal client information.
-pub fn stream_proxy_header(inbound: &TcpStream) -> Result<BytesMut, EncodeError> {
-    // Get peer and local address
-    let peer = inbound
-        .peer_addr()
-        .expect("Peer address not known for TCP stream");
+pub fn stream_proxy_header(
+    inbound: &TcpStream,
+    peer: SocketAddr,
+    version: ProxyVersion,
+) -> Result<BytesMut, EncodeError> {
+    // Get local address
     let local = inbound
         .local_addr()
         .expect("Local address not known for TCP stream");
 
+    if version == ProxyVersion::V1 {
+        return Ok(v1_proxy_header(peer, local));
+    }
+
     // Build proxy header
     let header = proxy_protocol::ProxyHeader::Version2 {
         command: ProxyCommand::Proxy,
         transport_protocol: ProxyTransportProtocol::Stream,
-        addresses: match (peer, local) {
-            (SocketAddr::V4(source), SocketAddr::V4(destination)) => ProxyAddresses::Ipv4 {
-                source,
-                destination,
-            },
-            (SocketAddr::V6(source), SocketAddr::V6(destination)) => ProxyAddresses::Ipv6 {
-                source,
-                destination,
-            },
-            (_, _) => unreachable!(),
-        },
+        addresses: proxy_addresses(peer, local),
     };
 
     proxy_protocol::encode(header)
 }
+
+/// Encode a proxy protocol v1 header for the given source and destination.
+///
+/// For example: `PROXY TCP4 192.168.0.1 192.168.0.11 56324 25565\r\n`.
+fn v1_proxy_header(source: SocketAddr, destination: SocketAddr) -> BytesMut {
+    let header = match proxy_addresses(source, destination) {
+        ProxyAddresses::Ipv4 {
+            source,
+            destination,
+        } => format!(
+            "PROXY TCP4 {} {} {} {}\r\n",
+            source.ip(),
+            destination.ip(),
+            source.port(),
+            destination.port(),
+        ),
+        ProxyAddresses::Ipv6 {
+            source,
+            destination,
+        } => format!(
+            "PROXY TCP6 {} {} {} {}\r\n",
+            source.ip(),
+            destination.ip(),
+            source.port(),
+            destination.port(),
+        ),
+        _ => "PROXY UNKNOWN\r\n".into(),
+    };
+    BytesMut::from(header.as_bytes())
+}
+
+/// Get proxy header addresses for the given source and destination.
+///
+/// Both must be of the same address family. On dual-stack sockets one side may be an IPv4-mapped
+/// IPv6 address while the other is not. These are unmapped if both sides are IPv4, otherwise IPv4
+/// addresses are mapped into IPv6.
+fn proxy_addresses(source: SocketAddr, destination: SocketAddr) -> ProxyAddresses {
+    match (unmap_ipv4(source), unmap_ipv4(destination)) {
+        (SocketAddr::V4(source), SocketAddr::V4(destination)) => ProxyAddresses::Ipv4 {
+            source,
+            destination,
+        },
+        (source, destination) => ProxyAddresses::Ipv6 {
+            source: map_ipv6(source),
+            destination: map_ipv6(destination),
+        },
+    }
+}
+
+/// Unmap IPv4-mapped IPv6 address into IPv4 address.
+fn unmap_ipv4(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(net::canonical_ip(addr.ip()), addr.port())
+}
+
+/// Map IPv4 address into IPv4-mapped IPv6 address.
+fn map_ipv6(addr: SocketAddr) -> SocketAddrV6 {
+    match addr {
+        SocketAddr::V4(v4) => SocketAddrV6::new(v4.ip().to_ipv6_mapped(), v4.port(), 0, 0),
+        SocketAddr::V6(v6) => v6,
+    }
+}
+
+/// Read the proxy header from an inbound stream.
+///
+/// Must only be used for connections from trusted proxies, which always send a header. Returns
+/// the client address the header describes, or `None` if it has none such as for health checks.
+pub async fn read_proxy_header(inbound: &mut TcpStream) -> Result<Option<SocketAddr>, ()> {
+    time::timeout(PROXY_HEADER_TIMEOUT, read_proxy_header_no_timeout(inbound))
+        .await
+        .map_err(|_| ())?
+}
+
+/// Read the proxy header from an inbound stream, with no timeout.
+async fn read_proxy_header_no_timeout(inbound: &mut TcpStream) -> Result<Option<SocketAddr>, ()> {
+    // Headers of both versions are at least as long as the v2 signature
+    let mut header = vec![0; PROXY_V2_SIGNATURE.len()];
+    inbound.read_exact(&mut header).await.map_err(|_| ())?;
+
+    // Version 2: read version, command, address family and length, then addresses
+    if header == PROXY_V2_SIGNATURE {
+        let mut meta = [0; 4];
+        inbound.read_exact(&mut meta).await.map_err(|_| ())?;
+        let mut addresses = vec![0; u16::from_be_bytes([meta[2], meta[3]]) as usize];
+        inbound.read_exact(&mut addresses).await.map_err(|_| ())?;
+        return parse_v2_source(meta[0], meta[1], &addresses);
+    }
+
+    // Version 1: read text line up to CRLF, byte by byte to not consume client data
+    if !header.starts_with(b"PROXY ") {
+        return Err(());
+    }
+    while !header.ends_with(b"\r\n") {
+        if header.len() >= PROXY_V1_MAX_LEN {
+            return Err(());
+        }
+        header.push(inbound.read_u8().await.map_err(|_| ())?);
+    }
+    parse_v1_source(&header)
+}
+
+/// Parse the source address from a proxy protocol v1 header.
+///
+/// For example: `PROXY TCP4 192.168.0.1 192.168.0.11 56324 25565\r\n`.
+fn parse_v1_source(header: &[u8]) -> Result<Option<SocketAddr>, ()> {
+    let header = std::str::from_utf8(header).map_err(|_| ())?;
+    let mut parts = header.trim_end_matches("\r\n").split(' ').skip(1);
+    match parts.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(()),
+    }
+
+    // Source address, skip destination address, source port
+    let ip: IpAddr = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+    let port: u16 = parts.nth(1).ok_or(())?.parse().map_err(|_| ())?;
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+/// Parse the source address from a proxy protocol v2 header.
+fn parse_v2_source(
+    version_command: u8,
+    family: u8,
+    addresses: &[u8],
+) -> Result<Option<SocketAddr>, ()> {
+    if version_command >> 4 != 2 {
+        return Err(());
+    }
+
+    // Local command is used for health checks, carries no client address
+    match version_command & 0x0F {
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(()),
+    }
+
+    // Addresses are ordered as source, destination, source port, destination port
+    match family >> 4 {
+        0x1 if addresses.len() >= 12 => {
+            let ip: [u8; 4] = addresses[..4].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port)))
+        }
+        0x2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[..16].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        0x1 | 0x2 => Err(()),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn unmap_ipv4_mapped() {
+        assert_eq!(
+            unmap_ipv4(addr("[::ffff:10.0.0.1]:25565")),
+            addr("10.0.0.1:25565")
+        );
+        assert_eq!(unmap_ipv4(addr("10.0.0.1:25565")), addr("10.0.0.1:25565"));
+        assert_eq!(
+            unmap_ipv4(addr("[2001:db8::1]:25565")),
+            addr("[2001:db8::1]:25565")
+        );
+    }
+
+    #[test]
+    fn proxy_addresses_mapped_ipv4() {
+        assert_eq!(
+            proxy_addresses(addr("[::ffff:10.0.0.1]:50000"), addr("10.0.0.2:25565")),
+            ProxyAddresses::Ipv4 {
+                source: "10.0.0.1:50000".parse().unwrap(),
+                destination: "10.0.0.2:25565".parse().unwrap(),
+            }
+        );
+        assert_eq!(
+            proxy_addresses(
+                addr("[::ffff:10.0.0.1]:50000"),
+                addr("[::ffff:10.0.0.2]:25565")
+            ),
+            ProxyAddresses::Ipv4 {
+                source: "10.0.0.1:50000".parse().unwrap(),
+                destination: "10.0.0.2:25565".parse().unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn proxy_addresses_mixed_families() {
+        assert_eq!(
+            proxy_addresses(addr("10.0.0.1:50000"), addr("[2001:db8::2]:25565")),
+            ProxyAddresses::Ipv6 {
+                source: "[::ffff:10.0.0.1]:50000".parse().unwrap(),
+                destination: "[2001:db8::2]:25565".parse().unwrap(),
+            }
+        );
+        assert_eq!(
+            proxy_addresses(addr("[2001:db8::1]:50000"), addr("[::ffff:10.0.0.2]:25565")),
+            ProxyAddresses::Ipv6 {
+                source: "[2001:db8::1]:50000".parse().unwrap(),
+                destination: "[::ffff:10.0.0.2]:25565".parse().unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn v1_proxy_header_families() {
+        assert_eq!(
+            &v1_proxy_header(addr("[::ffff:10.0.0.1]:50000"), addr("10.0.0.2:25565"))[..],
+            b"PROXY TCP4 10.0.0.1 10.0.0.2 50000 25565\r\n"
+        );
+        assert_eq!(
+            &v1_proxy_header(addr("10.0.0.1:50000"), addr("[2001:db8::2]:25565"))[..],
+            b"PROXY TCP6 ::ffff:10.0.0.1 2001:db8::2 50000 25565\r\n"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// Minimum time between admitting queued clients.
+///
+/// The online player count is only updated when the server status is polled. This gives an
+/// admitted client time to show up in it, so we don't admit more clients than there are slots.
+pub fn admit_cooldown(config: &Config) -> Duration {
+    Duration::from_secs(2 * config.advanced.poll_interval.max(1) as u64)
+}
+
+/// Queue of clients waiting for a free slot on a full server.
+#[derive(Debug, Default)]
+pub struct JoinQueue {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// ID of the next ticket.
+    next_id: u64,
+
+    /// Queued ticket IDs, first is next in line.
+    tickets: VecDeque<u64>,
+
+    /// Last time a client was admitted.
+    last_admit: Option<Instant>,
+}
+
+impl JoinQueue {
+    /// Join the queue, returns a ticket.
+    ///
+    /// The client leaves the queue when the ticket is dropped.
+    pub fn join(self: &Arc<Self>) -> QueueTicket {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.tickets.push_back(id);
+
+        QueueTicket {
+            queue: self.clone(),
+            id,
+        }
+    }
+
+    /// Number of queued clients.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().tickets.len()
+    }
+
+    /// Whether no client is queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A ticket for a queued client.
+#[derive(Debug)]
+pub struct QueueTicket {
+    queue: Arc<JoinQueue>,
+    id: u64,
+}
+
+impl QueueTicket {
+    /// Get the queue position, starting at 1.
+    pub fn position(&self) -> usize {
+        let inner = self.queue.inner.lock().unwrap();
+        inner
+            .tickets
+            .iter()
+            .position(|id| *id == self.id)
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    }
+
+    /// Try to leave the queue to take a free slot.
+    ///
+    /// Only succeeds for the client first in line, and if no other client was admitted within the
+    /// given cooldown, see `admit_cooldown`.
+    pub fn try_admit(&self, cooldown: Duration) -> bool {
+        let mut inner = self.queue.inner.lock().unwrap();
+
+        if inner.tickets.front() != Some(&self.id) {
+            return false;
+        }
+        if inner
+            .last_admit
+            .map(|t| t.elapsed() < cooldown)
+            .unwrap_or(false)
+        {
+            return false;
+        }
+
+        inner.tickets.pop_front();
+        inner.last_admit.replace(Instant::now());
+        true
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.queue.inner.lock() {
+            inner.tickets.retain(|id| *id != self.id);
+        }
+    }
+}
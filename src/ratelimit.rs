@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Result of recording a connection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RateLimit {
+    /// Connection is allowed.
+    Allow,
+
+    /// Connection exceeds the limit, first time in the current window.
+    Exceeded,
+
+    /// Connection exceeds the limit, already exceeded before in the current window.
+    Limited,
+}
+
+/// Connections seen from an IP in the current window.
+#[derive(Debug, Copy, Clone)]
+struct Window {
+    /// Number of connections in this window.
+    count: u32,
+
+    /// Start of this window.
+    start: Instant,
+}
+
+/// Connection rate limiter per source IP.
+///
+/// Counts connections per IP in fixed time windows.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// Current window by IP.
+    windows: Mutex<HashMap<IpAddr, Window>>,
+}
+
+impl RateLimiter {
+    /// Record a connection from the given IP, allowing up to `limit` connections per `window`.
+    pub fn record(&self, ip: IpAddr, limit: u32, window: Duration) -> RateLimit {
+        let mut windows = self.windows.lock().unwrap();
+
+        // Forget expired windows, start new window for this IP if needed
+        windows.retain(|_, w| w.start.elapsed() < window);
+        let current = windows.entry(ip).or_insert_with(|| Window {
+            count: 0,
+            start: Instant::now(),
+        });
+        current.count = current.count.saturating_add(1);
+
+        match current.count {
+            count if count <= limit => RateLimit::Allow,
+            count if count == limit.saturating_add(1) => RateLimit::Exceeded,
+            _ => RateLimit::Limited,
+        }
+    }
+}
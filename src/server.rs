@@ -1,26 +1,45 @@
+use std::collections::HashMap;
 use std::net::IpAddr;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use chrono::Local;
 use futures::FutureExt;
+use minecraft_protocol::data::server_status::{OnlinePlayer, ServerVersion};
 use minecraft_protocol::version::v1_20_3::status::ServerStatus;
-use tokio::process::Command;
-use tokio::sync::watch;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{ChildStdin, Command};
 #[cfg(feature = "rcon")]
 use tokio::sync::Semaphore;
+use tokio::sync::{broadcast, watch};
 use tokio::sync::{Mutex, RwLock, RwLockReadGuard};
 use tokio::time;
 
-use crate::config::{Config, Server as ConfigServer};
-use crate::mc::ban::{BannedIp, BannedIps};
+#[cfg(feature = "rcon")]
+use crate::afk::{self, Activity};
+use crate::config::{Config, Method, Server as ConfigServer};
+use crate::console;
+use crate::hooks::Hooks;
+use crate::mc::ban::{BanAction, BannedIp, BannedIps, BannedPlayer, BannedPlayers};
 use crate::mc::whitelist::Whitelist;
+use crate::metrics::Metrics;
 use crate::os;
+use crate::peers::Peers;
+use crate::proto;
 use crate::proto::packets::play::join_game::JoinGameData;
+use crate::queue::JoinQueue;
+use crate::ratelimit::RateLimiter;
+
+/// Number of console lines buffered for slow console subscribers.
+const CONSOLE_BUFFER: usize = 64;
 
-/// Server cooldown after the process quit.
-/// Used to give it some more time to quit forgotten threads, such as for RCON.
-const SERVER_QUIT_COOLDOWN: Duration = Duration::from_millis(2500);
+/// Interval to check the number of online players at while draining.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Interval to check whether the server process exited at.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 /// RCON cooldown. Required period between RCON invocations.
 ///
@@ -29,11 +48,38 @@ const SERVER_QUIT_COOLDOWN: Duration = Duration::from_millis(2500);
 #[cfg(feature = "rcon")]
 const RCON_COOLDOWN: Duration = Duration::from_secs(15);
 
-/// Exit codes that are allowed.
+/// Minimum time between fetching the online player count through RCON.
+#[cfg(feature = "rcon")]
+const RCON_PLAYER_COUNT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Timeout for fetching the online player count through RCON.
+#[cfg(feature = "rcon")]
+const RCON_PLAYER_COUNT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Timeout for polling player activity through RCON, so it can't stall the monitor.
+#[cfg(feature = "rcon")]
+const RCON_ACTIVITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum time to wait before restarting a crashed server.
+const CRASH_BACKOFF_MAX: Duration = Duration::from_secs(10 * 60);
+
+/// Uptime after which the server is considered healthy, resets the consecutive crash count.
+const CRASH_HEALTHY_UPTIME: Duration = Duration::from_secs(10 * 60);
+
+/// Time the server process CPU usage must stay below the threshold before it may sleep.
+#[cfg(feature = "proc-stats")]
+const CPU_IDLE_TIME: Duration = Duration::from_secs(60);
+
+/// Minimum time between waking the server on status requests.
 ///
-/// - 143: https://github.com/timvisee/lazymc/issues/26#issuecomment-1435670029
-/// - 130: https://unix.stackexchange.com/q/386836/61092
-const ALLOWED_EXIT_CODES: [i32; 2] = [130, 143];
+/// Prevents refreshing server lists from repeatedly waking the server.
+const STATUS_WAKE_DEBOUNCE: Duration = Duration::from_secs(60);
+
+/// Maximum age of the last known player sample to still show it in status responses.
+const PLAYER_SAMPLE_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Maximum number of players in the player sample, same as the Minecraft server.
+const PLAYER_SAMPLE_MAX: usize = 12;
 
 /// Shared server state.
 #[derive(Debug)]
@@ -54,31 +100,135 @@ pub struct Server {
     /// Set if a server process is running.
     pid: Mutex<Option<u32>>,
 
+    /// Server process console input.
+    ///
+    /// Set if a server process is running and its input is piped.
+    stdin: Mutex<Option<ChildStdin>>,
+
     /// Last known server status.
     ///
     /// Will remain set once known, not cleared if server goes offline.
     status: RwLock<Option<ServerStatus>>,
 
+    /// Last known non-empty player sample from the server status, and when it was received.
+    ///
+    /// Cleared once no players are online.
+    player_sample: RwLock<Option<(Vec<OnlinePlayer>, Instant)>>,
+
     /// Last active time.
     ///
     /// The last time there was activity on the server. Also set at the moment the server comes
     /// online.
     last_active: RwLock<Option<Instant>>,
 
+    /// Time the server came online, set while it is started.
+    started_at: RwLock<Option<Instant>>,
+
     /// Force server to stay online until.
     keep_online_until: RwLock<Option<Instant>>,
 
+    /// Whether the server was started without a player, and no player has been active since.
+    ///
+    /// Used to apply the `sleep_after_boot` time.
+    idle_boot: AtomicBool,
+
+    /// Whether the server has been online at least once since lazymc started.
+    ///
+    /// Used to show cold start messages while it hasn't.
+    started_once: AtomicBool,
+
+    /// Whether the server was marked ready from its console output, and it didn't respond to a
+    /// status request since.
+    ///
+    /// Prevents the monitor from considering it stopped while its port isn't open yet.
+    ready_from_console: AtomicBool,
+
+    /// Until when the console output of the server process is watched for the ready pattern, if it
+    /// didn't match yet.
+    ///
+    /// Prevents the monitor from considering the starting server started before it matched, until
+    /// the ready timeout passes.
+    awaiting_ready_until: RwLock<Option<Instant>>,
+
+    /// Whether the server is draining, new players are refused until it is stopped.
+    draining: AtomicBool,
+
+    /// Whether lazymc is quitting, the server won't be started anymore.
+    quitting: AtomicBool,
+
+    /// Last time the server process quit.
+    ///
+    /// Used to enforce the restart cooldown.
+    last_quit: Mutex<Option<Instant>>,
+
+    /// Whether a server start is delayed by the restart cooldown, no process is running yet.
+    ///
+    /// Stopping the server in this period cancels the start.
+    cooling_down: AtomicBool,
+
+    /// Number of server starts delayed by the restart cooldown so far.
+    ///
+    /// Used to detect that a delayed start was superseded by a newer one while waiting.
+    cooldown_starts: AtomicU32,
+
     /// Time to force kill the server process at.
     ///
     /// Used as starting/stopping timeout.
     kill_at: RwLock<Option<Instant>>,
 
+    /// Time to log start diagnostics at if the server is still starting.
+    watchdog_at: RwLock<Option<Instant>>,
+
     /// List of banned IPs.
     banned_ips: RwLock<BannedIps>,
 
+    /// List of banned players.
+    banned_players: RwLock<BannedPlayers>,
+
     /// Whitelist if enabled.
     whitelist: RwLock<Option<Whitelist>>,
 
+    /// OP usernames, only loaded if required by configured features.
+    ops: RwLock<Vec<String>>,
+
+    /// Captured server console lines, broadcast to subscribers.
+    pub console: broadcast::Sender<String>,
+
+    /// Last time each IP woke the server, used for wake throttling.
+    wake_times: Mutex<HashMap<IpAddr, Instant>>,
+
+    /// IP and username of the client that woke the server, if woken by a client.
+    ///
+    /// Used to only allow this client while starting, if exclusive start is enabled.
+    wake_initiator: Mutex<Option<(IpAddr, Option<String>)>>,
+
+    /// Last time the server was woken on a status request.
+    status_wake: Mutex<Option<Instant>>,
+
+    /// Peers that recently requested the server status or logged in.
+    pub peers: Mutex<Peers>,
+
+    /// Connection rate limiter per source IP.
+    pub rate_limiter: RateLimiter,
+
+    /// Clients queued for a free slot while the server is full.
+    pub join_queue: Arc<JoinQueue>,
+
+    /// Number of active connections proxied to the server.
+    active_connections: AtomicUsize,
+
+    /// Number of clients held while the server starts.
+    held_clients: AtomicUsize,
+
+    /// Number of consecutive crash restarts.
+    crash_restarts: AtomicU32,
+
+    /// Whether probing the server failed, cleared once probed successfully.
+    probe_failed: AtomicBool,
+
+    /// Whether the probe failure was reported to a joining client already.
+    probe_failed_reported: AtomicBool,
+
     /// Lock for exclusive RCON operations.
     #[cfg(feature = "rcon")]
     rcon_lock: Semaphore,
@@ -87,13 +237,45 @@ pub struct Server {
     #[cfg(feature = "rcon")]
     rcon_last_stop: Mutex<Option<Instant>>,
 
+    /// Player activity, used to detect AFK players.
+    #[cfg(feature = "rcon")]
+    activity: Mutex<Activity>,
+
+    /// Last online player count fetched through RCON, and when it was fetched.
+    #[cfg(feature = "rcon")]
+    rcon_player_count: Mutex<Option<(Instant, u32)>>,
+
     /// Probed join game data.
     pub probed_join_game: RwLock<Option<JoinGameData>>,
 
+    /// Probed server version, the real version and protocol of the server.
+    pub probed_version: RwLock<Option<ServerVersion>>,
+
     /// Forge payload.
     ///
     /// Sent to clients when they connect to lobby. Recorded from server by probe.
     pub forge_payload: RwLock<Vec<Vec<u8>>>,
+
+    /// Runtime metrics.
+    pub metrics: Metrics,
+
+    /// Maximum number of players as configured in `server.properties`.
+    max_players: RwLock<Option<u32>>,
+
+    /// Last known server compression threshold, negative if disabled.
+    ///
+    /// Assumes the configured threshold until the server is connected to.
+    compression_threshold: AtomicI32,
+
+    /// Last known start progress, parsed from the server console.
+    progress: RwLock<Option<String>>,
+
+    /// Registered lifecycle hooks.
+    hooks: std::sync::RwLock<Vec<Arc<dyn Hooks>>>,
+
+    /// Resource usage of the server process.
+    #[cfg(feature = "proc-stats")]
+    process_stats: std::sync::Mutex<ProcessStats>,
 }
 
 impl Server {
@@ -145,30 +327,51 @@ impl Server {
 
         // Broadcast change
         let _ = self.state_watch_sender.send(new);
+        self.invoke_hooks(|hooks| hooks.on_state_change(old, new));
+        #[cfg(all(unix, feature = "systemd"))]
+        os::systemd::status(&format!("Server {}", new.name()));
 
         // Update kill at time for starting/stopping state
-        *self.kill_at.write().await = match new {
-            State::Starting if config.server.start_timeout > 0 => {
-                Some(Instant::now() + Duration::from_secs(config.server.start_timeout as u64))
-            }
-            State::Stopping if config.server.stop_timeout > 0 => {
-                Some(Instant::now() + Duration::from_secs(config.server.stop_timeout as u64))
+        *self.kill_at.write().await = kill_at_for_state(new, config);
+        *self.watchdog_at.write().await = match new {
+            State::Starting if config.server.start_watchdog > 0 => {
+                Some(Instant::now() + Duration::from_secs(config.server.start_watchdog as u64))
             }
             _ => None,
         };
 
+        // Forget start progress and readiness of previous start
+        if new == State::Starting {
+            self.progress.write().await.take();
+            self.ready_from_console.store(false, Ordering::Relaxed);
+        }
+
+        *self.started_at.write().await = (new == State::Started).then(Instant::now);
+
+        if new == State::Started {
+            self.started_once.store(true, Ordering::Relaxed);
+            self.awaiting_ready_until.write().await.take();
+        }
+
         // Online/offline messages
         match new {
-            State::Started => info!(target: "lazymc::monitor", "Server is now online"),
-            State::Stopped => info!(target: "lazymc::monitor", "Server is now sleeping"),
+            State::Started => {
+                info!(target: &config.server.log_target("lazymc::monitor"), "Server is now online")
+            }
+            State::Stopped => {
+                info!(target: &config.server.log_target("lazymc::monitor"), "Server is now sleeping")
+            }
             _ => {}
         }
 
         // If Starting -> Started, update active time and keep it online for configured time
+        // Don't keep online if started without player and a boot sleep time is configured
         if old == State::Starting && new == State::Started {
             self.update_last_active().await;
-            self.keep_online_for(Some(config.time.min_online_time))
-                .await;
+            if !self.is_idle_boot(config) {
+                self.keep_online_for(Some(config.time.min_online_time))
+                    .await;
+            }
         }
 
         true
@@ -180,11 +383,14 @@ impl Server {
     /// status.
     pub async fn update_status(&self, config: &Config, status: Option<ServerStatus>) {
         // Update state based on curren
+        let awaiting_ready = status.is_some() && self.is_awaiting_ready().await;
         match (self.state(), &status) {
+            // If awaiting it, only the console ready pattern marks a starting server as started
+            (State::Starting, Some(_)) if awaiting_ready => {}
             (State::Stopped | State::Starting, Some(_)) => {
                 self.update_state(State::Started, config).await;
             }
-            (State::Started, None) => {
+            (State::Started, None) if !self.ready_from_console.load(Ordering::Relaxed) => {
                 self.update_state(State::Stopped, config).await;
             }
             _ => {}
@@ -192,19 +398,97 @@ impl Server {
 
         // Update last status if known
         if let Some(status) = status {
-            // Update last active time if there are online players
-            if status.players.online > 0 {
+            self.ready_from_console.store(false, Ordering::Relaxed);
+
+            // Update last active time if there are online players that aren't AFK
+            if status.players.online > 0 && !self.players_afk(config).await {
                 self.update_last_active().await;
+                self.idle_boot.store(false, Ordering::Relaxed);
+            }
+
+            // Remember last player sample, forget it once nobody is online
+            if !status.players.sample.is_empty() {
+                self.player_sample
+                    .write()
+                    .await
+                    .replace((status.players.sample.clone(), Instant::now()));
+            } else if status.players.online == 0 {
+                self.player_sample.write().await.take();
             }
 
             self.status.write().await.replace(status);
         }
     }
 
+    /// Get the last known player sample, if it is recent.
+    ///
+    /// Trimmed to the number of players the Minecraft server shows.
+    pub async fn player_sample(&self) -> Option<Vec<OnlinePlayer>> {
+        let player_sample = self.player_sample.read().await;
+        let (sample, time) = player_sample.as_ref()?;
+        if time.elapsed() >= PLAYER_SAMPLE_MAX_AGE {
+            return None;
+        }
+
+        Some(sample.iter().take(PLAYER_SAMPLE_MAX).cloned().collect())
+    }
+
+    /// Set whether the console output of the server process is watched for the ready pattern, and
+    /// for how long.
+    pub async fn set_awaiting_ready(&self, timeout: Option<Duration>) {
+        *self.awaiting_ready_until.write().await = timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Whether the console output of the server process is still watched for the ready pattern.
+    ///
+    /// Stops awaiting it with a warning once the ready timeout has passed.
+    async fn is_awaiting_ready(&self) -> bool {
+        let mut awaiting_ready_until = self.awaiting_ready_until.write().await;
+        match *awaiting_ready_until {
+            Some(until) if until > Instant::now() => true,
+            Some(_) => {
+                warn!(target: "lazymc", "Server console output didn't match ready pattern in time, using status requests instead");
+                awaiting_ready_until.take();
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Mark the starting server as ready, as detected from its console output.
+    ///
+    /// Does nothing if not in starting state.
+    pub async fn mark_ready(&self, config: &Config) {
+        self.ready_from_console.store(true, Ordering::Relaxed);
+        if !self
+            .update_state_from(Some(State::Starting), State::Started, config)
+            .await
+        {
+            self.ready_from_console.store(false, Ordering::Relaxed);
+        }
+    }
+
     /// Try to start the server.
     ///
     /// Does nothing if currently not in stopped state.
     pub async fn start(config: Arc<Config>, server: Arc<Server>, username: Option<String>) -> bool {
+        // Server process must be managed by us
+        if !config.server.is_managed() {
+            trace!(target: "lazymc", "Not starting server, no server command configured");
+            return false;
+        }
+
+        // Never start again while quitting
+        if server.quitting.load(Ordering::Relaxed) {
+            debug!(target: "lazymc", "Not starting server, lazymc is quitting");
+            return false;
+        }
+
+        // Require enough free memory
+        if server.state() == State::Stopped && !has_free_memory(&config) {
+            return false;
+        }
+
         // Must set state from stopped to starting
         if !server
             .update_state_from(Some(State::Stopped), State::Starting, &config)
@@ -213,14 +497,22 @@ impl Server {
             return false;
         }
 
-        // Log starting message
+        // Remember whether we're started without a player
+        server
+            .idle_boot
+            .store(username.is_none(), Ordering::Relaxed);
+        server.wake_initiator.lock().await.take();
+
+        // Invoke hooks, log starting message
+        server.invoke_hooks(|hooks| hooks.on_start(username.as_deref()));
+        let target = config.server.log_target("lazymc");
         match username {
-            Some(username) => info!(target: "lazymc", "Starting server for '{}'...", username),
-            None => info!(target: "lazymc", "Starting server..."),
+            Some(username) => info!(target: &target, "Starting server for '{}'...", username),
+            None => info!(target: &target, "Starting server..."),
         }
 
         // Unfreeze server if it is frozen
-        #[cfg(unix)]
+        #[cfg(any(unix, windows))]
         if config.server.freeze_process && unfreeze_server_signal(&config, &server).await {
             return true;
         }
@@ -230,116 +522,819 @@ impl Server {
         true
     }
 
-    /// Spawn the server task.
-    ///
-    /// This should not be called directly.
-    fn spawn_server_task(config: Arc<Config>, server: Arc<Server>) {
-        tokio::spawn(invoke_server_cmd(config, server).map(|_| ()));
+    /// Spawn the server task.
+    ///
+    /// This should not be called directly.
+    fn spawn_server_task(config: Arc<Config>, server: Arc<Server>) {
+        let task = async move {
+            if !server.wait_restart_cooldown(&config).await {
+                return Ok(());
+            }
+            invoke_server_cmd(config, server).await
+        };
+        tokio::spawn(task.map(|_| ()));
+    }
+
+    /// Wait until the restart cooldown has passed since the server process last quit.
+    ///
+    /// Resets the start timeout if waited. Returns `false` if the start was cancelled while
+    /// waiting, because the server was stopped, lockout is enabled or lazymc is quitting.
+    async fn wait_restart_cooldown(&self, config: &Config) -> bool {
+        let cooldown = Duration::from_secs(config.time.restart_cooldown as u64);
+        let remaining = match *self.last_quit.lock().await {
+            Some(last_quit) => cooldown.saturating_sub(last_quit.elapsed()),
+            None => return true,
+        };
+        if remaining.is_zero() {
+            return true;
+        }
+
+        let target = config.server.log_target("lazymc");
+        info!(target: &target, "Delaying server start by {:.1}s, restart cooldown after last stop", remaining.as_secs_f32());
+        let start = self.cooldown_starts.fetch_add(1, Ordering::Relaxed) + 1;
+        self.cooling_down.store(true, Ordering::Relaxed);
+        time::sleep(remaining).await;
+
+        // A newer start may have taken over after this one was cancelled
+        if self.cooldown_starts.load(Ordering::Relaxed) != start {
+            return false;
+        }
+        self.cooling_down.store(false, Ordering::Relaxed);
+
+        // Things may have changed while waiting, don't start if no longer wanted
+        let cancelled = self.state() != State::Starting
+            || self.quitting.load(Ordering::Relaxed)
+            || config.lockout.enabled;
+        if cancelled {
+            info!(target: &target, "Not starting server, start was cancelled during restart cooldown");
+            self.update_state_from(Some(State::Starting), State::Stopped, config)
+                .await;
+            return false;
+        }
+
+        self.extend_kill_at(config).await;
+        true
+    }
+
+    /// Stop running server.
+    ///
+    /// This will attempt to stop the server with all available methods. The server process is
+    /// frozen instead if configured.
+    pub async fn stop(&self, config: &Config) -> bool {
+        self.stop_with(config, config.server.freeze_process).await
+    }
+
+    /// Stop running server, never freezing the server process.
+    ///
+    /// Like `stop`, but a frozen process is resumed and stopped instead. Used to fully restart the
+    /// server.
+    pub async fn stop_forced(&self, config: &Config) -> bool {
+        self.resume_frozen(config).await;
+        self.stop_with(config, false).await
+    }
+
+    /// Stop running server, freeze the server process instead if `freeze` is set.
+    ///
+    /// This will attempt to stop the server with all available methods. Hooks are invoked once
+    /// stopping succeeded.
+    async fn stop_with(&self, config: &Config, freeze: bool) -> bool {
+        let stopping = self.try_stop(config, freeze).await;
+        if stopping {
+            self.invoke_hooks(|hooks| hooks.on_stop());
+        }
+        stopping
+    }
+
+    /// Try all available methods to stop the server, or to freeze it if `freeze` is set.
+    #[allow(unused_variables)]
+    async fn try_stop(&self, config: &Config, freeze: bool) -> bool {
+        // Server process must be managed by us
+        if !config.server.is_managed() {
+            trace!(target: "lazymc", "Not stopping server, no server command configured");
+            return false;
+        }
+
+        // Cancel start delayed by the restart cooldown, no process is running yet
+        if self.cooling_down.load(Ordering::Relaxed) {
+            debug!(target: "lazymc", "Cancelling server start delayed by restart cooldown");
+            return self
+                .update_state_from(Some(State::Starting), State::Stopped, config)
+                .await;
+        }
+
+        // Try to freeze process
+        #[cfg(any(unix, windows))]
+        if freeze && freeze_server_signal(config, self).await {
+            return true;
+        }
+
+        // Try to stop through RCON if started
+        #[cfg(feature = "rcon")]
+        if self.state() == State::Started && stop_server_rcon(config, self).await {
+            return true;
+        }
+
+        // Try to stop through console input
+        if stop_server_stdin(config, self).await {
+            return true;
+        }
+
+        // Try to stop through signal
+        #[cfg(unix)]
+        if stop_server_signal(config, self).await {
+            return true;
+        }
+
+        warn!(target: "lazymc", "Failed to stop server, no more suitable stopping method to use");
+        false
+    }
+
+    /// Stop running server because lazymc is quitting.
+    ///
+    /// Like `stop_forced`, the server process is never frozen. New players are refused, and the
+    /// server won't be started again.
+    ///
+    /// Returns `false` if no server process is running or if it failed to stop.
+    pub async fn stop_for_quit(&self, config: &Config) -> bool {
+        self.quitting.store(true, Ordering::Relaxed);
+        self.draining.store(true, Ordering::Relaxed);
+
+        // Server process must be managed by us and running
+        if !config.server.is_managed() || self.pid.lock().await.is_none() {
+            return false;
+        }
+
+        self.stop_forced(config).await
+    }
+
+    /// Wait for the server process to exit.
+    pub async fn wait_for_exit(&self) {
+        while self.pid.lock().await.is_some() {
+            time::sleep(EXIT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Resume the server process if it is frozen.
+    ///
+    /// Used when quitting lazymc, so the server process isn't left frozen forever.
+    #[allow(unused_variables)]
+    pub async fn resume_frozen(&self, config: &Config) {
+        if !config.server.freeze_process || self.state() != State::Stopped {
+            return;
+        }
+
+        // A known PID while stopped means the process is frozen
+        #[cfg(any(unix, windows))]
+        if let Some(pid) = *self.pid.lock().await {
+            info!(target: "lazymc", "Resuming frozen server process before quitting");
+            if !os::unfreeze(pid) {
+                error!(target: "lazymc", "Failed to unfreeze server process.");
+            }
+        }
+    }
+
+    /// Drain and stop the server.
+    ///
+    /// Refuses new players, and waits for all online players to leave before stopping the server.
+    /// The server is stopped anyway once the given timeout is reached.
+    ///
+    /// The server process is never frozen if `force` is set, see `stop_forced`.
+    ///
+    /// Returns `false` if the server is not online, is already draining, or failed to stop.
+    pub async fn drain_and_stop(&self, config: &Config, timeout: Duration, force: bool) -> bool {
+        // Server must be online and not draining yet
+        if self.state() != State::Started || self.draining.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+
+        info!(target: "lazymc", "Draining server, refusing new players, stopping once empty or after {}s", timeout.as_secs());
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Stop waiting if server went offline in the meantime
+            if self.state() != State::Started {
+                self.end_drain();
+                return false;
+            }
+
+            let online = self
+                .status
+                .read()
+                .await
+                .as_ref()
+                .map(|status| status.players.online)
+                .unwrap_or(0);
+            if online == 0 {
+                info!(target: "lazymc", "All players left drained server, stopping...");
+                break;
+            }
+            if Instant::now() >= deadline {
+                info!(target: "lazymc", "Drain timeout reached with {} players online, stopping server...", online);
+                break;
+            }
+
+            time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        let stopping = if force {
+            self.stop_forced(config).await
+        } else {
+            self.stop(config).await
+        };
+        self.end_drain();
+        stopping
+    }
+
+    /// Stop draining, keeps refusing new players if quitting.
+    fn end_drain(&self) {
+        if !self.quitting.load(Ordering::Relaxed) {
+            self.draining.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Send a chat message to all online players.
+    ///
+    /// Uses RCON if enabled, or the server console input otherwise. Returns `false` if the message
+    /// could not be sent.
+    #[allow(unused_variables)]
+    pub async fn say(&self, config: &Config, message: &str) -> bool {
+        #[cfg(feature = "rcon")]
+        if config.rcon.enabled {
+            use crate::mc::rcon::Rcon;
+
+            // Grab RCON lock
+            let rcon_lock = self.rcon_lock.acquire().await.unwrap();
+
+            let result = match Rcon::connect_config(config).await {
+                Ok(mut rcon) => {
+                    let result = rcon.cmd(&format!("say {}", message)).await;
+                    rcon.close().await;
+                    result.map(|_| ()).map_err(|err| err.to_string())
+                }
+                Err(err) => Err(err.to_string()),
+            };
+
+            drop(rcon_lock);
+
+            match result {
+                Ok(()) => return true,
+                Err(err) => warn!(target: "lazymc", "Failed to send message through RCON: {}", err),
+            }
+        }
+
+        self.send_command(&format!("say {}", message)).await
+    }
+
+    /// Whether a player was online since the server started.
+    pub fn had_players(&self) -> bool {
+        !self.idle_boot.load(Ordering::Relaxed)
+    }
+
+    /// Whether the server is draining, refusing new players.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Send a command to the server process console input.
+    ///
+    /// Returns `false` if no server process is running, or if its input is not piped.
+    pub async fn send_command(&self, line: &str) -> bool {
+        let mut stdin = self.stdin.lock().await;
+        let Some(input) = stdin.as_mut() else {
+            return false;
+        };
+
+        let result: std::io::Result<()> = async {
+            input.write_all(line.as_bytes()).await?;
+            input.write_all(b"\n").await?;
+            input.flush().await
+        }
+        .await;
+        if let Err(err) = result {
+            warn!(target: "lazymc", "Failed to write to server console input: {}", err);
+            stdin.take();
+            return false;
+        }
+
+        true
+    }
+
+    /// Register lifecycle hooks.
+    pub fn add_hooks(&self, hooks: Arc<dyn Hooks>) {
+        self.hooks.write().unwrap().push(hooks);
+    }
+
+    /// Invoke all registered lifecycle hooks with the given function.
+    fn invoke_hooks(&self, f: impl Fn(&dyn Hooks)) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            f(hooks.as_ref());
+        }
+    }
+
+    /// Force kill running server.
+    ///
+    /// This requires the server PID to be known.
+    pub async fn force_kill(&self) -> bool {
+        if let Some(pid) = *self.pid.lock().await {
+            return os::force_kill(pid);
+        }
+        false
+    }
+
+    /// Decide whether the server should sleep.
+    ///
+    /// Always returns false if it is currently not online.
+    pub async fn should_sleep(&self, config: &Config) -> bool {
+        // Server must be online and managed by us
+        if self.state() != State::Started || !config.server.is_managed() {
+            return false;
+        }
+
+        // Never sleep if players are online
+        let players_online = self
+            .status
+            .read()
+            .await
+            .as_ref()
+            .map(|status| status.players.online > 0)
+            .unwrap_or(false);
+        if players_online {
+            if !self.players_afk(config).await {
+                trace!(target: "lazymc", "Not sleeping because players are online");
+                return false;
+            }
+            trace!(target: "lazymc", "Players are online but all AFK, may sleep");
+        }
+
+        // Never sleep while connections are proxied, player count may be flaky, unless all AFK
+        if config.time.keep_online_while_connected {
+            let connections = self.active_connections.load(Ordering::Relaxed);
+            if connections > 0 && !self.players_afk(config).await {
+                trace!(target: "lazymc", "Not sleeping because {} connections are proxied", connections);
+                return false;
+            }
+        }
+
+        // Don't sleep within scheduled wake or sleep blocked time ranges
+        if config.time.is_sleep_blocked(Local::now().time()) {
+            trace!(target: "lazymc", "Not sleeping because of wake or sleep blocked schedule");
+            return false;
+        }
+
+        // Don't sleep when keep online until isn't expired
+        let keep_online = self
+            .keep_online_until
+            .read()
+            .await
+            .map(|i| i >= Instant::now())
+            .unwrap_or(false);
+        if keep_online {
+            trace!(target: "lazymc", "Not sleeping because of keep online");
+            return false;
+        }
+
+        // Don't sleep while the server process is busy, such as while generating its world
+        #[cfg(feature = "proc-stats")]
+        if config.time.sleep_when_idle_cpu_below.is_some() && !self.is_cpu_idle() {
+            trace!(target: "lazymc", "Not sleeping because server process CPU usage is not idle");
+            return false;
+        }
+
+        // Last active time must have passed sleep threshold, use boot time if no player was active
+        let sleep_after = match config.time.sleep_after_boot {
+            Some(sleep_after_boot) if self.is_idle_boot(config) => sleep_after_boot,
+            _ => config.time.sleep_after,
+        };
+        if let Some(last_idle) = self.last_active.read().await.as_ref() {
+            return last_idle.elapsed() >= Duration::from_secs(sleep_after as u64);
+        }
+
+        false
+    }
+
+    /// Decide whether to force kill the server process.
+    pub async fn should_kill(&self) -> bool {
+        self.kill_at
+            .read()
+            .await
+            .map(|t| t <= Instant::now())
+            .unwrap_or(false)
+    }
+
+    /// Decide whether to log start diagnostics.
+    ///
+    /// Returns true once if the server is still starting when the start watchdog time is reached.
+    pub async fn should_watchdog(&self) -> bool {
+        let mut watchdog_at = self.watchdog_at.write().await;
+        if watchdog_at.map(|t| t <= Instant::now()).unwrap_or(false) {
+            watchdog_at.take();
+            return self.state() == State::Starting;
+        }
+        false
+    }
+
+    /// Get the PID of the running server process, if any.
+    pub async fn pid(&self) -> Option<u32> {
+        *self.pid.lock().await
+    }
+
+    /// Extend the time to force kill the server process at.
+    ///
+    /// Resets the starting/stopping timeout based on the current state, used when force killing
+    /// is disabled.
+    pub async fn extend_kill_at(&self, config: &Config) {
+        *self.kill_at.write().await = kill_at_for_state(self.state(), config);
+    }
+
+    /// Poll player activity through RCON to detect AFK players.
+    ///
+    /// Does nothing if AFK detection is disabled, or if activity was polled recently.
+    #[cfg(feature = "rcon")]
+    pub async fn poll_activity(&self, config: &Config) {
+        // AFK detection must be enabled
+        if !config.time.afk_sleep || !config.rcon.enabled {
+            return;
+        }
+
+        // Server must be online, forget activity otherwise
+        if self.state() != State::Started {
+            self.activity.lock().await.clear();
+            return;
+        }
+
+        if !self.activity.lock().await.try_poll() {
+            return;
+        }
+
+        trace!(target: "lazymc", "Polling player activity through RCON...");
+
+        // Grab RCON lock, don't wait on other RCON operations, forget stale positions if busy so
+        // they don't age into AFK
+        let Ok(rcon_lock) = self.rcon_lock.try_acquire() else {
+            trace!(target: "lazymc", "RCON busy, skipping player activity poll");
+            self.activity.lock().await.invalidate();
+            return;
+        };
+
+        let result = time::timeout(RCON_ACTIVITY_TIMEOUT, afk::poll_positions(config)).await;
+        drop(rcon_lock);
+
+        match result {
+            Ok(Ok(positions)) => {
+                self.activity.lock().await.update(positions);
+                return;
+            }
+            Ok(Err(err)) => {
+                warn!(target: "lazymc", "Failed to poll player activity through RCON: {}", err)
+            }
+            Err(_) => {
+                warn!(target: "lazymc", "Failed to poll player activity through RCON: timed out")
+            }
+        }
+
+        // Poll failed, forget stale positions so they don't age into AFK
+        self.activity.lock().await.invalidate();
+    }
+
+    /// Get the number of online players through RCON, using the `list` command.
+    ///
+    /// The count is cached for `RCON_PLAYER_COUNT_INTERVAL`. The last known count is returned if
+    /// RCON is busy with another operation.
+    ///
+    /// Returns `None` if it failed.
+    #[cfg(feature = "rcon")]
+    pub async fn rcon_player_count(&self, config: &Config) -> Option<u32> {
+        use crate::mc::rcon::Rcon;
+
+        let mut cached = self.rcon_player_count.lock().await;
+        if let Some((at, online)) = *cached {
+            if at.elapsed() < RCON_PLAYER_COUNT_INTERVAL {
+                return Some(online);
+            }
+        }
+
+        // Grab RCON lock, don't wait on other RCON operations
+        let Ok(rcon_lock) = self.rcon_lock.try_acquire() else {
+            trace!(target: "lazymc", "RCON busy, using last known player count");
+            return cached.map(|(_, online)| online);
+        };
+
+        let result = time::timeout(RCON_PLAYER_COUNT_TIMEOUT, async {
+            match Rcon::connect_config(config).await {
+                Ok(mut rcon) => {
+                    let result = rcon.list_players().await;
+                    rcon.close().await;
+                    result.map_err(|err| err.to_string())
+                }
+                Err(err) => Err(err.to_string()),
+            }
+        })
+        .await
+        .unwrap_or_else(|_| Err("timed out".into()));
+
+        drop(rcon_lock);
+
+        match result {
+            Ok(list) => {
+                cached.replace((Instant::now(), list.online));
+                Some(list.online)
+            }
+            Err(err) => {
+                warn!(target: "lazymc", "Failed to get player count through RCON: {}", err);
+                cached.take();
+                None
+            }
+        }
+    }
+
+    /// Test whether the server is reachable through RCON.
+    ///
+    /// Connects and disconnects again, without sending any command.
+    #[cfg(feature = "rcon")]
+    pub async fn check_rcon(&self, config: &Config) -> Result<(), String> {
+        use crate::mc::rcon::Rcon;
+
+        // Grab RCON lock
+        let rcon_lock = self.rcon_lock.acquire().await.unwrap();
+
+        let result = match Rcon::connect_config(config).await {
+            Ok(rcon) => {
+                rcon.close().await;
+                Ok(())
+            }
+            Err(err) => Err(err.to_string()),
+        };
+
+        drop(rcon_lock);
+        result
+    }
+
+    /// Check whether all online players are AFK.
+    ///
+    /// Always returns `false` if AFK detection is disabled.
+    #[allow(unused_variables)]
+    async fn players_afk(&self, config: &Config) -> bool {
+        #[cfg(feature = "rcon")]
+        if config.time.afk_sleep {
+            let afk_after = Duration::from_secs(config.time.afk_after as u64);
+            return self.activity.lock().await.all_afk(afk_after);
+        }
+
+        false
+    }
+
+    /// Read last known server status.
+    pub async fn status(&self) -> RwLockReadGuard<'_, Option<ServerStatus>> {
+        self.status.read().await
+    }
+
+    /// Get the maximum number of players as configured in `server.properties`.
+    pub async fn max_players(&self) -> Option<u32> {
+        *self.max_players.read().await
+    }
+
+    /// Update the maximum number of players as configured in `server.properties`.
+    pub fn set_max_players_blocking(&self, max_players: Option<u32>) {
+        futures::executor::block_on(async { *self.max_players.write().await = max_players })
+    }
+
+    /// Get the server version.
+    ///
+    /// Uses the probed version if known, or the configured version hint otherwise.
+    pub async fn version(&self, config: &Config) -> ServerVersion {
+        self.probed_version
+            .read()
+            .await
+            .clone()
+            .unwrap_or_else(|| ServerVersion {
+                name: config.public.version.clone(),
+                protocol: config.public.protocol,
+            })
+    }
+
+    /// Get the server protocol version.
+    ///
+    /// Uses the probed protocol if known, or the configured protocol hint otherwise.
+    pub async fn protocol(&self, config: &Config) -> u32 {
+        self.version(config).await.protocol
+    }
+
+    /// Whether the server protocol differs from the protocol recorded when probing.
+    ///
+    /// Only compares the protocol number, the version name is free-form and may change without
+    /// affecting the protocol. Returns `false` if the server wasn't probed.
+    pub async fn protocol_changed(&self, version: &ServerVersion) -> bool {
+        self.probed_version
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|probed| probed.protocol != version.protocol)
+    }
+
+    /// Reset probed server details that may be stale after a server version change.
+    ///
+    /// Records the new server version.
+    pub async fn reset_probed(&self, version: ServerVersion) {
+        self.probed_version.write().await.replace(version);
+        self.probed_join_game.write().await.take();
+        self.forge_payload.write().await.clear();
+        self.set_compression_threshold(proto::compression_threshold());
+    }
+
+    /// Whether the server is online and full, based on the last known status.
+    pub async fn is_full(&self) -> bool {
+        if self.state() != State::Started {
+            return false;
+        }
+        match self.status.read().await.as_ref() {
+            Some(status) => status.players.max > 0 && status.players.online >= status.players.max,
+            None => false,
+        }
+    }
+
+    /// Whether joining clients should be queued.
+    ///
+    /// True if the queue join method is used, and if the server is full or other clients are
+    /// queued already.
+    pub async fn should_queue(&self, config: &Config) -> bool {
+        config.join.has_method(Method::Queue)
+            && (!self.join_queue.is_empty() || self.is_full().await)
+    }
+
+    /// Get the last known server compression threshold, negative if disabled.
+    pub fn compression_threshold(&self) -> i32 {
+        self.compression_threshold.load(Ordering::Relaxed)
+    }
+
+    /// Set the server compression threshold as observed on a server connection.
+    pub fn set_compression_threshold(&self, threshold: i32) {
+        let old = self
+            .compression_threshold
+            .swap(threshold, Ordering::Relaxed);
+        if old != threshold {
+            debug!(target: "lazymc", "Server uses compression threshold of {}, was {}", threshold, old);
+        }
+    }
+
+    /// Get the last known start progress.
+    pub async fn progress(&self) -> Option<String> {
+        self.progress.read().await.clone()
+    }
+
+    /// Set the start progress.
+    pub async fn set_progress(&self, progress: String) {
+        self.progress.write().await.replace(progress);
+    }
+
+    /// Get the time the server has been online for, `None` if not started.
+    pub async fn uptime(&self) -> Option<Duration> {
+        self.started_at
+            .read()
+            .await
+            .map(|started_at| started_at.elapsed())
     }
 
-    /// Stop running server.
+    /// Get the time since the last activity on the server, if known.
+    pub async fn idle_time(&self) -> Option<Duration> {
+        self.last_active.read().await.map(|t| t.elapsed())
+    }
+
+    /// Get the time to wait before restarting the server after a crash.
     ///
-    /// This will attempt to stop the server with all available methods.
-    #[allow(unused_variables)]
-    pub async fn stop(&self, config: &Config) -> bool {
-        // Try to freeze through signal
-        #[cfg(unix)]
-        if config.server.freeze_process && freeze_server_signal(config, self).await {
-            return true;
+    /// Doubles on each consecutive crash. The count is reset if the server was up long enough
+    /// before crashing. Returns `None` if the maximum number of restarts is reached.
+    fn crash_restart_delay(&self, config: &Config, uptime: Option<Duration>) -> Option<Duration> {
+        if uptime
+            .map(|uptime| uptime >= CRASH_HEALTHY_UPTIME)
+            .unwrap_or(false)
+        {
+            self.crash_restarts.store(0, Ordering::Relaxed);
         }
 
-        // Try to stop through RCON if started
-        #[cfg(feature = "rcon")]
-        if self.state() == State::Started && stop_server_rcon(config, self).await {
-            return true;
+        let restarts = self.crash_restarts.load(Ordering::Relaxed);
+        if config.server.max_crash_restarts > 0 && restarts >= config.server.max_crash_restarts {
+            return None;
         }
+        self.crash_restarts.store(restarts + 1, Ordering::Relaxed);
 
-        // Try to stop through signal
-        #[cfg(unix)]
-        if stop_server_signal(config, self).await {
-            return true;
-        }
+        let backoff = Duration::from_secs(config.server.crash_backoff as u64)
+            .saturating_mul(2u32.saturating_pow(restarts));
+        Some(backoff.min(CRASH_BACKOFF_MAX))
+    }
 
-        warn!(target: "lazymc", "Failed to stop server, no more suitable stopping method to use");
-        false
+    /// Update the last active time.
+    async fn update_last_active(&self) {
+        self.last_active.write().await.replace(Instant::now());
     }
 
-    /// Force kill running server.
+    /// Track an active connection proxied to the server.
     ///
-    /// This requires the server PID to be known.
-    pub async fn force_kill(&self) -> bool {
-        if let Some(pid) = *self.pid.lock().await {
-            return os::force_kill(pid);
-        }
-        false
+    /// The connection is counted for as long as the returned guard is kept.
+    pub fn track_connection(server: &Arc<Server>) -> ActiveConnection {
+        server.active_connections.fetch_add(1, Ordering::Relaxed);
+        ActiveConnection(server.clone())
     }
 
-    /// Decide whether the server should sleep.
+    /// Track a client held while the server starts.
     ///
-    /// Always returns false if it is currently not online.
-    pub async fn should_sleep(&self, config: &Config) -> bool {
-        // Server must be online
-        if self.state() != State::Started {
-            return false;
-        }
+    /// The client is counted for as long as the returned guard is kept.
+    pub fn track_held_client(server: &Arc<Server>) -> HeldClient {
+        server.held_clients.fetch_add(1, Ordering::Relaxed);
+        HeldClient(server.clone())
+    }
 
-        // Never sleep if players are online
-        let players_online = self
-            .status
-            .read()
-            .await
-            .as_ref()
-            .map(|status| status.players.online > 0)
-            .unwrap_or(false);
-        if players_online {
-            trace!(target: "lazymc", "Not sleeping because players are online");
-            return false;
-        }
+    /// Get the number of clients held while the server starts.
+    pub fn held_clients(&self) -> usize {
+        self.held_clients.load(Ordering::Relaxed)
+    }
 
-        // Don't sleep when keep online until isn't expired
-        let keep_online = self
-            .keep_online_until
-            .read()
-            .await
-            .map(|i| i >= Instant::now())
-            .unwrap_or(false);
-        if keep_online {
-            trace!(target: "lazymc", "Not sleeping because of keep online");
-            return false;
-        }
+    /// Sample resource usage of the server process.
+    ///
+    /// Should be called regularly, usage is only sampled while the server is started.
+    #[cfg(feature = "proc-stats")]
+    pub async fn sample_process_usage(&self, config: &Config) {
+        let pid = match *self.pid.lock().await {
+            Some(pid) if self.state() == State::Started => pid,
+            _ => {
+                *self.process_stats.lock().unwrap() = ProcessStats::default();
+                return;
+            }
+        };
 
-        // Last active time must have passed sleep threshold
-        if let Some(last_idle) = self.last_active.read().await.as_ref() {
-            return last_idle.elapsed() >= Duration::from_secs(config.time.sleep_after as u64);
+        let mut stats = self.process_stats.lock().unwrap();
+        stats.usage = stats.sampler.sample(pid);
+
+        // Track since when CPU usage is below the sleep threshold
+        // No constraint if usage couldn't be sampled, so we don't keep the server awake forever
+        let idle = match (stats.usage, config.time.sleep_when_idle_cpu_below) {
+            (Some(usage), Some(threshold)) => usage.cpu < threshold,
+            _ => true,
+        };
+        if !idle {
+            stats.cpu_idle_since = None;
+        } else if stats.cpu_idle_since.is_none() {
+            stats.cpu_idle_since = Some(Instant::now());
         }
+    }
 
-        false
+    /// Get the last sampled resource usage of the server process, if known.
+    #[cfg(feature = "proc-stats")]
+    pub fn process_usage(&self) -> Option<os::proc::ProcessUsage> {
+        self.process_stats.lock().unwrap().usage
     }
 
-    /// Decide whether to force kill the server process.
-    pub async fn should_kill(&self) -> bool {
-        self.kill_at
-            .read()
-            .await
-            .map(|t| t <= Instant::now())
+    /// Whether the server process CPU usage stayed below the sleep threshold long enough.
+    #[cfg(feature = "proc-stats")]
+    fn is_cpu_idle(&self) -> bool {
+        self.process_stats
+            .lock()
+            .unwrap()
+            .cpu_idle_since
+            .map(|since| since.elapsed() >= CPU_IDLE_TIME)
             .unwrap_or(false)
     }
 
-    /// Read last known server status.
-    pub async fn status(&self) -> RwLockReadGuard<'_, Option<ServerStatus>> {
-        self.status.read().await
+    /// Whether probing the server failed.
+    pub fn probe_failed(&self) -> bool {
+        self.probe_failed.load(Ordering::Relaxed)
     }
 
-    /// Update the last active time.
-    async fn update_last_active(&self) {
-        self.last_active.write().await.replace(Instant::now());
+    /// Set whether probing the server failed.
+    pub fn set_probe_failed(&self, failed: bool) {
+        self.probe_failed.store(failed, Ordering::Relaxed);
+        if !failed {
+            self.probe_failed_reported.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Mark the probe failure as reported, returns `true` if it was not reported before.
+    pub fn report_probe_failed(&self) -> bool {
+        !self.probe_failed_reported.swap(true, Ordering::Relaxed)
+    }
+
+    /// Whether the server has never been online since lazymc started.
+    pub fn is_cold_start(&self) -> bool {
+        !self.started_once.load(Ordering::Relaxed)
+    }
+
+    /// Whether the server was started without a player and no player has been active since.
+    ///
+    /// Always false if no `sleep_after_boot` time is configured.
+    fn is_idle_boot(&self, config: &Config) -> bool {
+        config.time.sleep_after_boot.is_some() && self.idle_boot.load(Ordering::Relaxed)
     }
 
     /// Force the server to be online for the given number of seconds.
-    async fn keep_online_for(&self, duration: Option<u32>) {
-        *self.keep_online_until.write().await = duration
+    ///
+    /// Does not shorten a longer keep online time that is still active.
+    pub async fn keep_online_for(&self, duration: Option<u32>) {
+        let until = duration
             .filter(|d| *d > 0)
             .map(|d| Instant::now() + Duration::from_secs(d as u64));
+        let mut keep_online_until = self.keep_online_until.write().await;
+        if until > *keep_online_until {
+            *keep_online_until = until;
+        }
     }
 
     /// Check whether the given IP is banned.
@@ -355,6 +1350,31 @@ impl Server {
         self.banned_ips.read().await.get(ip)
     }
 
+    /// Decide what to do with a client from the given IP based on bans.
+    ///
+    /// Banned clients are dropped if `drop_banned_ips` is enabled, and are kicked with the ban
+    /// reason otherwise. This uses the latest known `banned-ips.json` contents if known.
+    pub async fn ban_action(&self, config: &Config, ip: &IpAddr) -> BanAction {
+        match self.ban_entry(ip).await {
+            Some(ban) if ban.is_banned() && config.server.drop_banned_ips => {
+                BanAction::Drop(ban.reason().into())
+            }
+            Some(ban) if ban.is_banned() => BanAction::Kick(ban.reason().into()),
+            _ => BanAction::Allow,
+        }
+    }
+
+    /// Get the ban entry of the given player, if currently banned.
+    ///
+    /// This uses the latest known `banned-players.json` contents if known.
+    pub async fn player_ban(&self, username: &str) -> Option<BannedPlayer> {
+        self.banned_players
+            .read()
+            .await
+            .get(username)
+            .filter(|ban| ban.is_banned())
+    }
+
     /// Check whether the given IP is banned.
     ///
     /// This uses the latest known `banned-ips.json` contents if known.
@@ -375,6 +1395,83 @@ impl Server {
             .unwrap_or(true)
     }
 
+    /// Whether a whitelist is currently used.
+    pub async fn has_whitelist(&self) -> bool {
+        self.whitelist.read().await.is_some()
+    }
+
+    /// Check whether the given IP may wake the server.
+    ///
+    /// Each IP may wake the server once per configured `wake_throttle` time, see `record_wake`.
+    /// Always allowed if throttling is disabled or if the server is not sleeping, as it won't be
+    /// woken then.
+    pub async fn may_wake_throttled(&self, config: &Config, ip: IpAddr) -> bool {
+        if config.server.wake_throttle == 0 || self.state() != State::Stopped {
+            return true;
+        }
+
+        let throttle = Duration::from_secs(config.server.wake_throttle as u64);
+        let mut wake_times = self.wake_times.lock().await;
+
+        // Forget expired wakes, check if this IP woke recently
+        wake_times.retain(|_, time| time.elapsed() < throttle);
+        !wake_times.contains_key(&ip)
+    }
+
+    /// Record that the given IP woke the server, for wake throttling.
+    ///
+    /// Must only be called once the server was actually started.
+    pub async fn record_wake(&self, config: &Config, ip: IpAddr) {
+        if config.server.wake_throttle > 0 {
+            self.wake_times.lock().await.insert(ip, Instant::now());
+        }
+    }
+
+    /// Check whether the server may be woken on a status request.
+    ///
+    /// The server is woken at most once per `STATUS_WAKE_DEBOUNCE` on status requests.
+    pub async fn may_status_wake(&self) -> bool {
+        !self
+            .status_wake
+            .lock()
+            .await
+            .is_some_and(|time| time.elapsed() < STATUS_WAKE_DEBOUNCE)
+    }
+
+    /// Record that the server was woken on a status request, see `may_status_wake`.
+    pub async fn record_status_wake(&self) {
+        self.status_wake.lock().await.replace(Instant::now());
+    }
+
+    /// Remember the client that woke the server.
+    pub async fn set_wake_initiator(&self, ip: IpAddr, username: Option<String>) {
+        self.wake_initiator.lock().await.replace((ip, username));
+    }
+
+    /// Whether the given client may join while the server is starting.
+    ///
+    /// If exclusive start is enabled, only the client that woke the server may join. Clients
+    /// match on IP or username. Always allowed if the server is not starting or if it wasn't
+    /// woken by a client.
+    pub async fn may_join_starting(
+        &self,
+        config: &Config,
+        ip: IpAddr,
+        username: Option<&str>,
+    ) -> bool {
+        if !config.join.exclusive_start || self.state() != State::Starting {
+            return true;
+        }
+
+        match self.wake_initiator.lock().await.as_ref() {
+            Some((initiator_ip, initiator_username)) => {
+                *initiator_ip == ip
+                    || (username.is_some() && initiator_username.as_deref() == username)
+            }
+            None => true,
+        }
+    }
+
     /// Update the list of banned IPs.
     pub async fn set_banned_ips(&self, ips: BannedIps) {
         *self.banned_ips.write().await = ips;
@@ -385,6 +1482,11 @@ impl Server {
         futures::executor::block_on(async { self.set_banned_ips(ips).await })
     }
 
+    /// Update the list of banned players.
+    pub fn set_banned_players_blocking(&self, players: BannedPlayers) {
+        futures::executor::block_on(async { *self.banned_players.write().await = players })
+    }
+
     /// Update the whitelist.
     pub async fn set_whitelist(&self, whitelist: Option<Whitelist>) {
         *self.whitelist.write().await = whitelist;
@@ -394,6 +1496,16 @@ impl Server {
     pub fn set_whitelist_blocking(&self, whitelist: Option<Whitelist>) {
         futures::executor::block_on(async { self.set_whitelist(whitelist).await })
     }
+
+    /// Check whether the given username is an OP.
+    pub async fn is_op(&self, username: &str) -> bool {
+        self.ops.read().await.iter().any(|u| u == username)
+    }
+
+    /// Update the list of OP usernames.
+    pub fn set_ops_blocking(&self, ops: Vec<String>) {
+        futures::executor::block_on(async { *self.ops.write().await = ops })
+    }
 }
 
 impl Default for Server {
@@ -405,22 +1517,99 @@ impl Default for Server {
             state_watch_sender,
             state_watch_receiver,
             pid: Default::default(),
+            stdin: Default::default(),
             status: Default::default(),
+            player_sample: Default::default(),
             last_active: Default::default(),
+            started_at: Default::default(),
             keep_online_until: Default::default(),
+            idle_boot: AtomicBool::new(false),
+            started_once: AtomicBool::new(false),
+            ready_from_console: AtomicBool::new(false),
+            awaiting_ready_until: Default::default(),
+            draining: AtomicBool::new(false),
+            quitting: AtomicBool::new(false),
+            last_quit: Default::default(),
+            cooling_down: AtomicBool::new(false),
+            cooldown_starts: AtomicU32::new(0),
             kill_at: Default::default(),
+            watchdog_at: Default::default(),
             banned_ips: Default::default(),
+            banned_players: Default::default(),
             whitelist: Default::default(),
+            ops: Default::default(),
+            console: broadcast::channel(CONSOLE_BUFFER).0,
+            wake_times: Default::default(),
+            wake_initiator: Default::default(),
+            status_wake: Default::default(),
+            peers: Default::default(),
+            rate_limiter: Default::default(),
+            join_queue: Default::default(),
+            active_connections: AtomicUsize::new(0),
+            held_clients: AtomicUsize::new(0),
+            crash_restarts: AtomicU32::new(0),
+            probe_failed: AtomicBool::new(false),
+            probe_failed_reported: AtomicBool::new(false),
             #[cfg(feature = "rcon")]
             rcon_lock: Semaphore::new(1),
             #[cfg(feature = "rcon")]
             rcon_last_stop: Default::default(),
+            #[cfg(feature = "rcon")]
+            activity: Default::default(),
+            #[cfg(feature = "rcon")]
+            rcon_player_count: Default::default(),
             probed_join_game: Default::default(),
+            probed_version: Default::default(),
             forge_payload: Default::default(),
+            metrics: Default::default(),
+            max_players: Default::default(),
+            compression_threshold: AtomicI32::new(proto::compression_threshold()),
+            progress: Default::default(),
+            hooks: Default::default(),
+            #[cfg(feature = "proc-stats")]
+            process_stats: Default::default(),
         }
     }
 }
 
+/// Active connection proxied to the server.
+///
+/// Counted as active connection on the server until dropped.
+#[derive(Debug)]
+pub struct ActiveConnection(Arc<Server>);
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Resource usage of the server process.
+#[cfg(feature = "proc-stats")]
+#[derive(Debug, Default)]
+struct ProcessStats {
+    /// Process usage sampler.
+    sampler: os::proc::Sampler,
+
+    /// Last sampled usage.
+    usage: Option<os::proc::ProcessUsage>,
+
+    /// Since when the CPU usage is below the sleep threshold.
+    cpu_idle_since: Option<Instant>,
+}
+
+/// Client held while the server starts.
+///
+/// Counted as held client on the server until dropped.
+#[derive(Debug)]
+pub struct HeldClient(Arc<Server>);
+
+impl Drop for HeldClient {
+    fn drop(&mut self) {
+        self.0.held_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 /// Server state.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum State {
@@ -458,6 +1647,49 @@ impl State {
             Self::Stopping => 3,
         }
     }
+
+    /// Get lowercase state name, as used in the API and webhooks.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Stopped => "stopped",
+            Self::Starting => "starting",
+            Self::Started => "started",
+            Self::Stopping => "stopping",
+        }
+    }
+}
+
+/// Get the time to force kill the server process at for the given state.
+///
+/// Used as starting/stopping timeout, `None` if there is no timeout.
+fn kill_at_for_state(state: State, config: &Config) -> Option<Instant> {
+    match state {
+        State::Starting if config.server.start_timeout > 0 => {
+            Some(Instant::now() + Duration::from_secs(config.server.start_timeout as u64))
+        }
+        State::Stopping if config.server.stop_timeout > 0 => {
+            Some(Instant::now() + Duration::from_secs(config.server.stop_timeout as u64))
+        }
+        _ => None,
+    }
+}
+
+/// Check whether there is enough free system memory to start the server.
+///
+/// Always `true` if no minimum is configured or if free memory is unknown on this platform.
+pub fn has_free_memory(config: &Config) -> bool {
+    let required = config.server.min_free_memory_mb;
+    if required == 0 {
+        return true;
+    }
+
+    match os::free_memory_mb() {
+        Some(free) if free < required => {
+            warn!(target: "lazymc", "Not starting server, only {} MB of memory available, {} MB required", free, required);
+            false
+        }
+        _ => true,
+    }
 }
 
 /// Invoke server command, store PID and wait for it to quit.
@@ -466,16 +1698,49 @@ pub async fn invoke_server_cmd(
     state: Arc<Server>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Configure command
-    let args = shlex::split(&config.server.command).expect("invalid server command");
+    let command = match config.server.command.as_ref() {
+        Some(command) => command,
+        None => {
+            error!(target: "lazymc", "Cannot start server process, no server command configured");
+            return Ok(());
+        }
+    };
+    let args = shlex::split(command).expect("invalid server command");
     let mut cmd = Command::new(&args[0]);
     cmd.args(args.iter().skip(1));
-    cmd.kill_on_drop(true);
+    cmd.kill_on_drop(config.advanced.kill_server_on_exit);
+
+    // Detach server from our process group, so it survives signals sent to lazymc's group
+    #[cfg(unix)]
+    if !config.advanced.kill_server_on_exit {
+        cmd.process_group(0);
+    }
+
+    // Set process priority
+    if let Some(nice) = config.server.nice {
+        os::set_priority(&mut cmd, nice);
+    }
+
+    // Bind process to CPU cores
+    if !config.server.cpu_affinity.is_empty() {
+        os::set_cpu_affinity(&mut cmd, &config.server.cpu_affinity);
+    }
 
     // Set working directory
     if let Some(ref dir) = ConfigServer::server_directory(&config) {
         cmd.current_dir(dir);
     }
 
+    // Capture console output if required
+    if console::must_capture(&config) {
+        cmd.stdout(Stdio::piped());
+    }
+
+    // Pipe console input to allow lazymc console commands and stopping through it
+    if config.advanced.stdin_commands || config.server.stop_command.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+
     // Spawn process
     let mut child = match cmd.spawn() {
         Ok(child) => child,
@@ -486,11 +1751,23 @@ pub async fn invoke_server_cmd(
     };
 
     // Remember PID
-    state
-        .pid
-        .lock()
-        .await
-        .replace(child.id().expect("unknown server PID"));
+    let pid = child.id().expect("unknown server PID");
+    state.pid.lock().await.replace(pid);
+
+    // Bind spawned process to CPU cores, if not done on spawn
+    if !config.server.cpu_affinity.is_empty()
+        && !os::set_process_cpu_affinity(pid, &config.server.cpu_affinity)
+    {
+        warn!(target: "lazymc", "Failed to set server process CPU affinity");
+    }
+
+    // Remember console input
+    *state.stdin.lock().await = child.stdin.take();
+
+    // Capture console output
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(console::capture(config.clone(), state.clone(), stdout));
+    }
 
     // Wait for process to exit, handle status
     let crashed = match child.wait().await {
@@ -501,10 +1778,10 @@ pub async fn invoke_server_cmd(
         Ok(status)
             if status
                 .code()
-                .map(|ref code| ALLOWED_EXIT_CODES.contains(code))
+                .map(|ref code| config.server.allowed_exit_codes.contains(code))
                 .unwrap_or(false) =>
         {
-            debug!(target: "lazymc", "Server process stopped successfully by SIGTERM ({})", status);
+            debug!(target: "lazymc", "Server process stopped successfully with allowed exit code ({})", status);
             false
         }
         Ok(status) => {
@@ -518,19 +1795,29 @@ pub async fn invoke_server_cmd(
         }
     };
 
-    // Forget server PID
+    // Forget server PID and console input
     state.pid.lock().await.take();
+    state.stdin.lock().await.take();
 
-    // Give server a little more time to quit forgotten threads
-    time::sleep(SERVER_QUIT_COOLDOWN).await;
+    // Remember quit time, a new start must wait for the restart cooldown
+    state.last_quit.lock().await.replace(Instant::now());
+    let uptime = state.uptime().await;
 
     // Set server state to stopped
     state.update_state(State::Stopped, &config).await;
 
     // Restart on crash
     if crashed && config.server.wake_on_crash {
-        warn!(target: "lazymc", "Server crashed, restarting...");
-        Server::start(config, state, None).await;
+        match state.crash_restart_delay(&config, uptime) {
+            Some(delay) => {
+                warn!(target: "lazymc", "Server crashed, restarting in {}s...", delay.as_secs());
+                time::sleep(delay).await;
+                Server::start(config, state, None).await;
+            }
+            None => {
+                error!(target: "lazymc", "Server crashed {} times in a row, not restarting it anymore", config.server.max_crash_restarts);
+            }
+        }
     }
 
     Ok(())
@@ -571,8 +1858,15 @@ async fn stop_server_rcon(config: &Config, server: &Server) -> bool {
         }
     };
 
+    // Invoke pre-stop commands, continue stopping if any fails
+    for cmd in &config.rcon.pre_stop_commands {
+        if let Err(err) = rcon.cmd(cmd).await {
+            warn!(target: "lazymc", "Failed to invoke pre-stop command '{}' through RCON: {}", cmd, err);
+        }
+    }
+
     // Invoke stop
-    if let Err(err) = rcon.cmd("stop").await {
+    if let Err(err) = rcon.cmd(&config.rcon.stop_command).await {
         error!(target: "lazymc", "Failed to invoke stop through RCON: {}", err);
         return false;
     }
@@ -589,6 +1883,28 @@ async fn stop_server_rcon(config: &Config, server: &Server) -> bool {
     true
 }
 
+/// Stop server by writing the stop command to its console input.
+async fn stop_server_stdin(config: &Config, server: &Server) -> bool {
+    // Stop command must be configured
+    let Some(command) = &config.server.stop_command else {
+        return false;
+    };
+
+    if !server.send_command(command).await {
+        warn!(target: "lazymc", "Failed to send stop command to server console input");
+        return false;
+    }
+
+    server
+        .update_state_from(Some(State::Starting), State::Stopping, config)
+        .await;
+    server
+        .update_state_from(Some(State::Started), State::Stopping, config)
+        .await;
+
+    true
+}
+
 /// Stop server by sending SIGTERM signal.
 ///
 /// Only available on Unix.
@@ -618,22 +1934,22 @@ async fn stop_server_signal(config: &Config, server: &Server) -> bool {
     true
 }
 
-/// Freeze server by sending SIGSTOP signal.
+/// Freeze server process, by sending SIGSTOP signal on Unix or suspending it on Windows.
 ///
-/// Only available on Unix.
-#[cfg(unix)]
+/// Only available on Unix and Windows.
+#[cfg(any(unix, windows))]
 async fn freeze_server_signal(config: &Config, server: &Server) -> bool {
     // Grab PID
     let pid = match *server.pid.lock().await {
         Some(pid) => pid,
         None => {
-            debug!(target: "lazymc", "Could not send freeze signal to server process, PID unknown");
+            debug!(target: "lazymc", "Could not freeze server process, PID unknown");
             return false;
         }
     };
 
     if !os::freeze(pid) {
-        error!(target: "lazymc", "Failed to send freeze signal to server process.");
+        error!(target: "lazymc", "Failed to freeze server process.");
     }
 
     server
@@ -646,22 +1962,22 @@ async fn freeze_server_signal(config: &Config, server: &Server) -> bool {
     true
 }
 
-/// Unfreeze server by sending SIGCONT signal.
+/// Unfreeze server process, by sending SIGCONT signal on Unix or resuming it on Windows.
 ///
-/// Only available on Unix.
-#[cfg(unix)]
+/// Only available on Unix and Windows.
+#[cfg(any(unix, windows))]
 async fn unfreeze_server_signal(config: &Config, server: &Server) -> bool {
     // Grab PID
     let pid = match *server.pid.lock().await {
         Some(pid) => pid,
         None => {
-            debug!(target: "lazymc", "Could not send unfreeze signal to server process, PID unknown");
+            debug!(target: "lazymc", "Could not unfreeze server process, PID unknown");
             return false;
         }
     };
 
     if !os::unfreeze(pid) {
-        error!(target: "lazymc", "Failed to send unfreeze signal to server process.");
+        error!(target: "lazymc", "Failed to unfreeze server process.");
     }
 
     server
@@ -673,3 +1989,139 @@ async fn unfreeze_server_signal(config: &Config, server: &Server) -> bool {
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse config for a managed server, with the given extra TOML appended.
+    fn config(extra: &str) -> Config {
+        toml::from_str(&format!(
+            "[server]\ncommand = \"java -jar server.jar\"\n{extra}"
+        ))
+        .unwrap()
+    }
+
+    /// Bring the server online, as if started with or without a player.
+    async fn boot(server: &Server, config: &Config, with_player: bool) {
+        server.idle_boot.store(!with_player, Ordering::Relaxed);
+        server.update_state(State::Starting, config).await;
+        server.update_state(State::Started, config).await;
+    }
+
+    #[tokio::test]
+    async fn restart_cooldown_then_start() {
+        let config = config("[time]\nrestart_cooldown = 1\n");
+        let server = Server::default();
+        server.last_quit.lock().await.replace(Instant::now());
+        server.update_state(State::Starting, &config).await;
+
+        assert!(server.wait_restart_cooldown(&config).await);
+        assert_eq!(server.state(), State::Starting);
+    }
+
+    #[tokio::test]
+    async fn restart_cooldown_cancelled_by_stop() {
+        let config = Arc::new(config("[time]\nrestart_cooldown = 1\n"));
+        let server = Arc::new(Server::default());
+        server.last_quit.lock().await.replace(Instant::now());
+        server.update_state(State::Starting, &config).await;
+
+        let waiting = tokio::spawn({
+            let (config, server) = (config.clone(), server.clone());
+            async move { server.wait_restart_cooldown(&config).await }
+        });
+        time::sleep(Duration::from_millis(100)).await;
+
+        assert!(server.stop(&config).await);
+        assert_eq!(server.state(), State::Stopped);
+        assert!(!waiting.await.unwrap());
+        assert_eq!(server.state(), State::Stopped);
+    }
+
+    #[tokio::test]
+    async fn sleep_after_boot_without_player() {
+        let config =
+            config("[time]\nsleep_after = 600\nmin_online_time = 600\nsleep_after_boot = 0\n");
+        let server = Server::default();
+        boot(&server, &config, false).await;
+        assert!(server.should_sleep(&config).await);
+    }
+
+    #[tokio::test]
+    async fn sleep_after_boot_ignored_with_player() {
+        let config =
+            config("[time]\nsleep_after = 600\nmin_online_time = 600\nsleep_after_boot = 0\n");
+        let server = Server::default();
+        boot(&server, &config, true).await;
+        assert!(!server.should_sleep(&config).await);
+    }
+
+    #[tokio::test]
+    async fn sleep_after_boot_unset() {
+        let config = config("[time]\nsleep_after = 600\nmin_online_time = 0\n");
+        let server = Server::default();
+        boot(&server, &config, false).await;
+        assert!(!server.should_sleep(&config).await);
+    }
+
+    /// Server with the given `banned-ips.json` contents loaded.
+    async fn server_with_bans(banned_ips: &str) -> Server {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(crate::mc::ban::FILE);
+        std::fs::write(&path, banned_ips).unwrap();
+        let server = Server::default();
+        server
+            .set_banned_ips(crate::mc::ban::load(&path).unwrap())
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn ban_action_kicks_with_reason() {
+        let config = config("");
+        let server =
+            server_with_bans(r#"[{"ip": "10.0.0.1", "reason": "Griefing"}, {"ip": "10.0.0.2"}]"#)
+                .await;
+
+        let action = server
+            .ban_action(&config, &"10.0.0.1".parse().unwrap())
+            .await;
+        assert_eq!(action, BanAction::Kick("Griefing".into()));
+        let action = server
+            .ban_action(&config, &"10.0.0.2".parse().unwrap())
+            .await;
+        assert_eq!(
+            action,
+            BanAction::Kick(crate::mc::ban::DEFAULT_BAN_REASON.into())
+        );
+        let action = server
+            .ban_action(&config, &"10.0.0.3".parse().unwrap())
+            .await;
+        assert_eq!(action, BanAction::Allow);
+    }
+
+    #[tokio::test]
+    async fn ban_action_drops_if_configured() {
+        let config = config("drop_banned_ips = true\n");
+        let server = server_with_bans(r#"[{"ip": "10.0.0.1", "reason": "Griefing"}]"#).await;
+
+        let action = server
+            .ban_action(&config, &"10.0.0.1".parse().unwrap())
+            .await;
+        assert_eq!(action, BanAction::Drop("Griefing".into()));
+    }
+
+    #[tokio::test]
+    async fn ban_action_allows_expired() {
+        let config = config("");
+        let server =
+            server_with_bans(r#"[{"ip": "10.0.0.1", "expires": "2000-01-01 00:00:00 +0000"}]"#)
+                .await;
+
+        let action = server
+            .ban_action(&config, &"10.0.0.1".parse().unwrap())
+            .await;
+        assert_eq!(action, BanAction::Allow);
+    }
+}
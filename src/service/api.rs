@@ -0,0 +1,278 @@
+use std::fmt::Display;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::time;
+
+use crate::config::Config;
+use crate::server::{Server, State};
+use crate::service::stdin;
+
+/// Maximum size of a request head.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Timeout for receiving a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP API service to query and control lazymc.
+///
+/// Supports `GET /status`, `POST /wake` (or `/start`) and `POST /sleep` (or `/stop`), authenticated with a bearer token.
+/// Served on the configured address, and on the configured Unix socket if set.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    if config.api.token.is_empty() {
+        error!(target: "lazymc::api", "Not starting API, no token configured");
+        return;
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = config.api.socket.clone() {
+        tokio::spawn(service_unix(path, config.clone(), server.clone()));
+    }
+    #[cfg(not(unix))]
+    if config.api.socket.is_some() {
+        warn!(target: "lazymc::api", "API Unix socket is configured, but not supported on this platform");
+    }
+
+    let listener = match TcpListener::bind(config.api.address).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(target: "lazymc::api", "Failed to start API on {}: {}", config.api.address, err);
+            return;
+        }
+    };
+
+    info!(target: "lazymc::api", "Serving API on {}", config.api.address);
+
+    while let Ok((stream, peer)) = listener.accept().await {
+        let (config, server) = (config.clone(), server.clone());
+        tokio::spawn(async move {
+            if let Err(err) = serve(stream, peer, &config, &server).await {
+                debug!(target: "lazymc::api", "Failed to serve API request from {}: {}", peer, err);
+            }
+        });
+    }
+}
+
+/// Serve HTTP API on the given Unix socket.
+///
+/// Removes a stale socket file left behind by a previous run.
+#[cfg(unix)]
+async fn service_unix(path: PathBuf, config: Arc<Config>, server: Arc<Server>) {
+    if let Err(err) = remove_stale_socket(&path) {
+        error!(target: "lazymc::api", "Failed to start API on {}: {}", path.display(), err);
+        return;
+    }
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(target: "lazymc::api", "Failed to start API on {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    info!(target: "lazymc::api", "Serving API on {}", path.display());
+
+    while let Ok((stream, _)) = listener.accept().await {
+        let (config, server) = (config.clone(), server.clone());
+        tokio::spawn(async move {
+            if let Err(err) = serve(stream, "unix socket", &config, &server).await {
+                debug!(target: "lazymc::api", "Failed to serve API request from unix socket: {}", err);
+            }
+        });
+    }
+}
+
+/// Remove a stale Unix socket file at the given path.
+///
+/// Refuses to remove anything that is not a socket, to not delete files on a misconfigured path.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "path exists and is not a socket",
+        ));
+    }
+    std::fs::remove_file(path)
+}
+
+/// Serve a single API request.
+async fn serve<S>(
+    mut stream: S,
+    peer: impl Display,
+    config: &Arc<Config>,
+    server: &Arc<Server>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = match time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(request) => request?,
+        Err(_) => return Err("request timed out".into()),
+    };
+
+    let (status, body) = handle(&request, peer, config, server).await;
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len(),
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Read a request head from the stream.
+///
+/// The request body is ignored.
+async fn read_request<S>(stream: &mut S) -> Result<String, Box<dyn std::error::Error>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = Vec::new();
+    let mut chunk = [0; 1024];
+
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Err("request too large".into());
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err("connection closed before end of request".into());
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Handle API request, returns HTTP status and JSON body.
+async fn handle(
+    request: &str,
+    peer: impl Display,
+    config: &Arc<Config>,
+    server: &Arc<Server>,
+) -> (&'static str, serde_json::Value) {
+    let mut lines = request.lines();
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let (method, path) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+
+    // Request must be authenticated
+    let token = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .and_then(|(_, value)| value.trim().strip_prefix("Bearer "))
+        .map(str::trim);
+    if !token.is_some_and(|token| token_eq(token, &config.api.token)) {
+        warn!(target: "lazymc::api", "Unauthorized API request from {}", peer);
+        return ("401 Unauthorized", json!({ "error": "unauthorized" }));
+    }
+
+    debug!(target: "lazymc::api", "API request from {}: {} {}", peer, method, path);
+
+    let cmd = match (method, path) {
+        ("GET", "/status") => return ("200 OK", status(server).await),
+        ("POST", "/wake" | "/start") => "wake",
+        ("POST", "/sleep" | "/stop") => "sleep",
+        (_, "/status" | "/wake" | "/start" | "/sleep" | "/stop") => {
+            return (
+                "405 Method Not Allowed",
+                json!({ "error": "method not allowed" }),
+            )
+        }
+        _ => return ("404 Not Found", json!({ "error": "not found" })),
+    };
+
+    match stdin::dispatch(config, server, cmd).await {
+        Ok(message) => (
+            "200 OK",
+            json!({ "message": message, "state": server.state().name() }),
+        ),
+        Err(err) => (
+            "409 Conflict",
+            json!({ "error": err, "state": server.state().name() }),
+        ),
+    }
+}
+
+/// Compare the given token to the configured one in constant time.
+///
+/// Takes the same time for any token of the same length, not to leak how much of it matched.
+fn token_eq(token: &str, expected: &str) -> bool {
+    let (token, expected) = (token.as_bytes(), expected.as_bytes());
+    token.len() == expected.len()
+        && token
+            .iter()
+            .zip(expected)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Build JSON server status with player info.
+async fn status(server: &Server) -> serde_json::Value {
+    let state = server.state();
+
+    // Only report players while online, last known status is kept after stopping
+    let players = match server.status().await.as_ref() {
+        Some(status) if state == State::Started => json!({
+            "online": status.players.online,
+            "max": status.players.max,
+            "sample": status.players.sample.iter().map(|p| &p.name).collect::<Vec<_>>(),
+        }),
+        _ => serde_json::Value::Null,
+    };
+
+    #[allow(unused_mut)]
+    let mut status = json!({
+        "state": state.name(),
+        "draining": server.is_draining(),
+        "uptime": server.uptime().await.map(|uptime| uptime.as_secs()),
+        "players": players,
+    });
+
+    // Report server process resource usage if known
+    #[cfg(feature = "proc-stats")]
+    if let Some(usage) = server.process_usage() {
+        status["process"] = json!({
+            "cpu": usage.cpu,
+            "memory": usage.memory,
+        });
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_eq_matches() {
+        assert!(token_eq("secret", "secret"));
+        assert!(!token_eq("secreT", "secret"));
+        assert!(!token_eq("secre", "secret"));
+        assert!(!token_eq("secrets", "secret"));
+        assert!(!token_eq("", "secret"));
+    }
+}
@@ -6,7 +6,7 @@ use std::time::Duration;
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 
 use crate::config::{Config, Server as ConfigServer};
-use crate::mc::ban::{self, BannedIps};
+use crate::mc::ban::{self, BannedIps, BannedPlayers};
 use crate::mc::{server_properties, whitelist};
 use crate::server::Server;
 
@@ -15,11 +15,13 @@ const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
 
 /// Service to watch server file changes.
 pub fn service(config: Arc<Config>, server: Arc<Server>) {
+    let target = config.server.log_target("lazymc");
+
     // Ensure server directory is set, it must exist
     let dir = match ConfigServer::server_directory(&config) {
         Some(dir) if dir.is_dir() => dir,
         _ => {
-            warn!(target: "lazymc", "Server directory doesn't exist, can't watch file changes to reload whitelist and banned IPs");
+            warn!(target: &target, "Server directory doesn't exist, can't watch file changes to reload whitelist and bans");
             return;
         }
     };
@@ -29,7 +31,10 @@ pub fn service(config: Arc<Config>, server: Arc<Server>) {
     while {
         // Update all files once
         reload_bans(&config, &server, &dir.join(ban::FILE));
+        reload_banned_players(&config, &server, &dir.join(ban::PLAYERS_FILE));
         reload_whitelist(&config, &server, &dir);
+        reload_ops(&config, &server, &dir);
+        reload_properties(&server, &dir);
 
         // Watch for changes, update accordingly
         watch_server(&config, &server, &dir)
@@ -41,9 +46,11 @@ pub fn service(config: Arc<Config>, server: Arc<Server>) {
 /// Returns `true` if we should watch again.
 #[must_use]
 fn watch_server(config: &Config, server: &Server, dir: &Path) -> bool {
+    let target = config.server.log_target("lazymc");
+
     // Directory must exist
     if !dir.is_dir() {
-        error!(target: "lazymc", "Server directory does not exist at {} anymore, not watching changes", dir.display());
+        error!(target: &target, "Server directory does not exist at {} anymore, not watching changes", dir.display());
         return false;
     }
 
@@ -52,7 +59,7 @@ fn watch_server(config: &Config, server: &Server, dir: &Path) -> bool {
     let mut watcher =
         watcher(tx, WATCH_DEBOUNCE).expect("failed to create watcher for banned-ips.json");
     if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
-        error!(target: "lazymc", "An error occured while creating watcher for server files: {}", err);
+        error!(target: &target, "An error occured while creating watcher for server files: {}", err);
         return true;
     }
 
@@ -80,13 +87,13 @@ fn watch_server(config: &Config, server: &Server, dir: &Path) -> bool {
 
             // Rewatch on rescan
             DebouncedEvent::Rescan => {
-                debug!(target: "lazymc", "Rescanning server directory files due to file watching problem");
+                debug!(target: &target, "Rescanning server directory files due to file watching problem");
                 return true;
             }
 
             // Rewatch on error
             DebouncedEvent::Error(err, _) => {
-                error!(target: "lazymc", "Error occurred while watching server directory for file changes: {}", err);
+                error!(target: &target, "Error occurred while watching server directory for file changes: {}", err);
                 return true;
             }
         }
@@ -102,6 +109,11 @@ fn update(config: &Config, server: &Server, dir: &Path, path: &Path) {
         reload_bans(config, server, path);
     }
 
+    // Update banned players
+    if path.ends_with(ban::PLAYERS_FILE) {
+        reload_banned_players(config, server, path);
+    }
+
     // Update whitelist
     if path.ends_with(whitelist::WHITELIST_FILE)
         || path.ends_with(whitelist::OPS_FILE)
@@ -109,6 +121,16 @@ fn update(config: &Config, server: &Server, dir: &Path, path: &Path) {
     {
         reload_whitelist(config, server, dir);
     }
+
+    // Update OPs
+    if path.ends_with(whitelist::OPS_FILE) {
+        reload_ops(config, server, dir);
+    }
+
+    // Update server properties
+    if path.ends_with(server_properties::FILE) {
+        reload_properties(server, dir);
+    }
 }
 
 /// Reload banned IPs.
@@ -143,6 +165,62 @@ fn reload_bans(config: &Config, server: &Server, path: &Path) {
     }
 }
 
+/// Reload banned players.
+fn reload_banned_players(config: &Config, server: &Server, path: &Path) {
+    // Bans must be enabled
+    if !config.server.block_banned_players {
+        return;
+    }
+
+    trace!(target: "lazymc", "Reloading banned players...");
+
+    // File must exist, clear file otherwise
+    if !path.is_file() {
+        debug!(target: "lazymc", "No banned players, {} does not exist", ban::PLAYERS_FILE);
+        server.set_banned_players_blocking(BannedPlayers::default());
+        return;
+    }
+
+    // Load and update banned players
+    match ban::load_players(path) {
+        Ok(players) => server.set_banned_players_blocking(players),
+        Err(err) => {
+            debug!(target: "lazymc", "Failed load banned players from {}, ignoring: {}", ban::PLAYERS_FILE, err);
+        }
+    }
+}
+
+/// Reload relevant properties from `server.properties`.
+fn reload_properties(server: &Server, dir: &Path) {
+    trace!(target: "lazymc", "Reloading server properties...");
+
+    let max_players =
+        server_properties::read_property(dir.join(server_properties::FILE), "max-players")
+            .and_then(|v| v.trim().parse().ok());
+    server.set_max_players_blocking(max_players);
+}
+
+/// Check whether the whitelist is enabled in `server.properties`.
+///
+/// Only `white-list` decides whether non-whitelisted users can join. `enforce-whitelist` just
+/// makes the server kick online users that are not whitelisted when the whitelist is reloaded, it
+/// has no effect on joining users and is therefore not used to enable the whitelist here.
+fn whitelist_enabled(dir: &Path) -> bool {
+    let file = dir.join(server_properties::FILE);
+    let property = |name| {
+        server_properties::read_property(&file, name)
+            .map(|v| v.trim() == "true")
+            .unwrap_or(false)
+    };
+
+    let enabled = property("white-list");
+    if !enabled && property("enforce-whitelist") {
+        debug!(target: "lazymc", "Server has 'enforce-whitelist' enabled without 'white-list', which has no effect, not using whitelist");
+    }
+
+    enabled
+}
+
 /// Reload whitelisted users.
 fn reload_whitelist(config: &Config, server: &Server, dir: &Path) {
     // Whitelist must be enabled
@@ -151,10 +229,7 @@ fn reload_whitelist(config: &Config, server: &Server, dir: &Path) {
     }
 
     // Must be enabled in server.properties
-    let enabled = server_properties::read_property(dir.join(server_properties::FILE), "white-list")
-        .map(|v| v.trim() == "true")
-        .unwrap_or(false);
-    if !enabled {
+    if !whitelist_enabled(dir) {
         server.set_whitelist_blocking(None);
         debug!(target: "lazymc", "Not using whitelist, not enabled in {}", server_properties::FILE);
         return;
@@ -170,3 +245,56 @@ fn reload_whitelist(config: &Config, server: &Server, dir: &Path) {
         }
     }
 }
+
+/// Reload OP users.
+fn reload_ops(config: &Config, server: &Server, dir: &Path) {
+    // Only used to show the console to OPs in the lobby
+    if !config.join.lobby.console_ops {
+        return;
+    }
+
+    trace!(target: "lazymc", "Reloading OP users...");
+
+    match whitelist::load_ops_dir(dir) {
+        Ok(ops) => server.set_ops_blocking(ops),
+        Err(err) => {
+            debug!(target: "lazymc", "Failed load OPs from {}, ignoring: {}", dir.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Create a server directory holding a `server.properties` with the given contents.
+    ///
+    /// The directory is removed when dropped.
+    fn server_dir(properties: &str) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(server_properties::FILE), properties).unwrap();
+        dir
+    }
+
+    #[test]
+    fn whitelist_enabled_by_white_list() {
+        let dir = server_dir("white-list=true\nenforce-whitelist=false\n");
+        assert!(whitelist_enabled(dir.path()));
+    }
+
+    #[test]
+    fn whitelist_not_enabled_by_enforce_whitelist() {
+        let dir = server_dir("white-list=false\nenforce-whitelist=true\n");
+        assert!(!whitelist_enabled(dir.path()));
+    }
+
+    #[test]
+    fn whitelist_not_enabled_without_properties() {
+        let dir = server_dir("motd=A Minecraft Server\n");
+        assert!(!whitelist_enabled(dir.path()));
+    }
+}
@@ -1,5 +1,15 @@
+#[cfg(feature = "api")]
+pub mod api;
 pub mod file_watcher;
 pub mod monitor;
 pub mod probe;
+#[cfg(feature = "rcon")]
+pub mod rcon;
+pub mod scheduler;
 pub mod server;
 pub mod signal;
+pub mod stdin;
+#[cfg(all(feature = "tui", unix))]
+pub mod tui;
+#[cfg(feature = "webhook")]
+pub mod webhook;
@@ -1,31 +1,76 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::config::{Config, Method};
+use tokio::time;
+
+use crate::config::{Config, Probe};
+use crate::forge;
 use crate::probe;
 use crate::server::Server;
 
+/// Time to wait before retrying a failed probe.
+const PROBE_RETRY_DELAY: Duration = Duration::from_secs(10);
+
 /// Probe server.
 pub async fn service(config: Arc<Config>, state: Arc<Server>) {
+    let target = config.server.log_target("lazymc::probe");
+
+    // Can't probe the mod handshake in the configuration phase
+    if forge::is_configuration_handshake(config.server.mod_loader(), config.public.protocol) {
+        warn!(target: &target, "Not probing server, NeoForge on Minecraft 1.20.2 and above is not supported");
+        return;
+    }
+
     // Only probe if enabled or if we must
-    if !config.server.probe_on_start && !must_probe(&config) {
+    match config.server.probe_mode() {
+        Probe::Always => {}
+        Probe::Auto if must_probe(&config) => {}
+        Probe::Auto => return,
+        Probe::Never => {
+            if config.server.is_modded() && config.join.uses_lobby() {
+                warn!(target: &target, "Not probing server, but lobby with Forge may not work without it");
+            }
+            return;
+        }
+    }
+
+    // We can only probe if we manage the server process
+    if !config.server.is_managed() {
+        warn!(target: &target, "Not probing server, no server command configured");
         return;
     }
 
-    // Probe
-    match probe::probe(config, state).await {
-        Ok(_) => info!(target: "lazymc::probe", "Succesfully probed server"),
-        Err(_) => {
-            error!(target: "lazymc::probe", "Failed to probe server, this may limit lazymc features")
+    // Probe, retry a limited number of times on failure
+    let mut retries = config.server.probe_retries;
+    loop {
+        match probe::probe(config.clone(), state.clone()).await {
+            Ok(_) => {
+                info!(target: &target, "Succesfully probed server");
+                state.set_probe_failed(false);
+                return;
+            }
+            Err(_) if retries > 0 => {
+                retries -= 1;
+                warn!(target: &target, "Failed to probe server, retrying in {}s ({} retries left)...", PROBE_RETRY_DELAY.as_secs(), retries);
+                time::sleep(PROBE_RETRY_DELAY).await;
+            }
+            Err(_) => {
+                error!(target: &target, "Failed to probe server, this may limit lazymc features");
+                state.set_probe_failed(true);
+                return;
+            }
         }
     }
 }
 
 /// Check whether we must probe.
 fn must_probe(config: &Config) -> bool {
+    let target = config.server.log_target("lazymc::probe");
+
     // Must probe with lobby and Forge
-    if config.server.forge && config.join.methods.contains(&Method::Lobby) {
-        warn!(target: "lazymc::probe", "Starting server to probe for Forge lobby...");
-        warn!(target: "lazymc::probe", "Set 'server.probe_on_start = true' to remove this warning");
+    if config.server.is_modded() && config.join.uses_lobby() {
+        warn!(target: &target, "Starting server to probe for Forge lobby...");
+        warn!(target: &target, "Set 'server.probe = \"always\"' to remove this warning");
         return true;
     }
 
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+
+use crate::config::Config;
+use crate::server::{Server, State};
+
+/// Time to wait after the server came online before testing RCON.
+///
+/// The server may open its RCON port slightly after it starts responding to status requests.
+const RCON_CHECK_DELAY: Duration = Duration::from_secs(5);
+
+/// RCON check service.
+///
+/// Tests whether RCON is reachable each time the server comes online, and logs the result.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    let target = config.server.log_target("lazymc::rcon");
+    let mut state = server.state_receiver();
+
+    while state.changed().await.is_ok() {
+        if *state.borrow() != State::Started {
+            continue;
+        }
+
+        time::sleep(RCON_CHECK_DELAY).await;
+        if server.state() != State::Started {
+            continue;
+        }
+
+        match server.check_rcon(&config).await {
+            Ok(()) => debug!(target: &target, "RCON is reachable"),
+            Err(err) => {
+                warn!(target: &target, "RCON is enabled but not reachable, sleeping the server through RCON will fail: {}", err)
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Days, Local, NaiveTime};
+use tokio::time;
+
+use crate::config::Config;
+use crate::server::{Server, State};
+
+/// Maximum time to sleep before checking the clock again.
+///
+/// The system clock may jump, such as on daylight saving time changes or after suspend, this makes
+/// sure scheduled times are still met.
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time to wait for the server to stop before restarting it, in addition to the stop timeout.
+const RESTART_STOP_MARGIN: Duration = Duration::from_secs(30);
+
+/// Service to start and restart the server on the configured schedules.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    if config.time.prestart_schedule.is_empty()
+        && config.time.daily_restart.is_none()
+        && config.time.wake_schedule.is_empty()
+    {
+        return;
+    }
+
+    if !config.server.is_managed() {
+        warn!(target: &config.server.log_target("lazymc::scheduler"), "Not scheduling server starts or restarts, no server command configured");
+        return;
+    }
+
+    tokio::join!(
+        prestart(config.clone(), server.clone()),
+        daily_restart(config.clone(), server.clone()),
+        wake_schedule(config, server),
+    );
+}
+
+/// Start the server on the configured pre-start schedule.
+async fn prestart(config: Arc<Config>, server: Arc<Server>) {
+    let schedule = &config.time.prestart_schedule;
+    if schedule.is_empty() {
+        return;
+    }
+
+    let target = config.server.log_target("lazymc::scheduler");
+    loop {
+        let at = next_occurrence(schedule);
+        debug!(target: &target, "Next scheduled server start at {}", at.format("%Y-%m-%d %H:%M:%S"));
+        wait_until(at).await;
+
+        if server.state() != State::Stopped {
+            debug!(target: &target, "Not pre-starting server as scheduled, it is not sleeping");
+            continue;
+        }
+
+        info!(target: &target, "Pre-starting server as scheduled...");
+        server
+            .keep_online_for(Some(config.time.prestart_keep_online))
+            .await;
+        Server::start(config.clone(), server.clone(), None).await;
+    }
+}
+
+/// Start the server at the start of the configured wake time ranges.
+///
+/// Also starts it when lazymc starts within such a range. The server is kept awake during these
+/// ranges, see `Time::is_sleep_blocked`.
+async fn wake_schedule(config: Arc<Config>, server: Arc<Server>) {
+    let schedule = &config.time.wake_schedule;
+    if schedule.is_empty() {
+        return;
+    }
+
+    let target = config.server.log_target("lazymc::scheduler");
+    let starts: Vec<NaiveTime> = schedule.iter().map(|range| range.start).collect();
+    loop {
+        let now = Local::now().time();
+        if schedule.iter().any(|range| range.contains(now)) && server.state() == State::Stopped {
+            info!(target: &target, "Starting server for scheduled wake time...");
+            Server::start(config.clone(), server.clone(), None).await;
+        }
+
+        let at = next_occurrence(&starts);
+        debug!(target: &target, "Next scheduled server wake at {}", at.format("%Y-%m-%d %H:%M:%S"));
+        wait_until(at).await;
+    }
+}
+
+/// Restart the server daily at the configured time.
+async fn daily_restart(config: Arc<Config>, server: Arc<Server>) {
+    let Some(time) = config.time.daily_restart else {
+        return;
+    };
+
+    let target = config.server.log_target("lazymc::scheduler");
+
+    loop {
+        let at = next_occurrence(&[time]);
+        debug!(target: &target, "Next daily server restart at {}", at.format("%Y-%m-%d %H:%M:%S"));
+        wait_until(at).await;
+
+        if server.state() != State::Started {
+            debug!(target: &target, "Not restarting server as scheduled, it is not running");
+            continue;
+        }
+        if !server.had_players() {
+            info!(target: &target, "Not restarting server as scheduled, no players were online since it started, letting it sleep");
+            continue;
+        }
+
+        info!(target: &target, "Restarting server as scheduled...");
+        restart(&config, &server, &target).await;
+    }
+}
+
+/// Drain, stop and start the server again.
+///
+/// Online players are warned first.
+async fn restart(config: &Arc<Config>, server: &Arc<Server>, target: &str) {
+    let drain = config.time.daily_restart_drain;
+    let message = config
+        .time
+        .daily_restart_message
+        .replace("{seconds}", &drain.to_string());
+    if !message.is_empty() && !server.say(config, &message).await {
+        warn!(target: target, "Failed to warn online players about restart, enable RCON or console commands");
+    }
+
+    if !server
+        .drain_and_stop(config, Duration::from_secs(drain as u64), true)
+        .await
+    {
+        warn!(target: target, "Failed to stop server for restart");
+        return;
+    }
+
+    // Wait for server process to exit, then start it again
+    let timeout = Duration::from_secs(config.server.stop_timeout as u64) + RESTART_STOP_MARGIN;
+    let mut state = server.state_receiver();
+    let stopped = time::timeout(timeout, async {
+        server.wait_for_exit().await;
+        while *state.borrow_and_update() != State::Stopped {
+            if state.changed().await.is_err() {
+                break;
+            }
+        }
+    })
+    .await;
+    if stopped.is_err() || server.state() != State::Stopped {
+        warn!(target: target, "Server didn't stop in time for restart, not starting it again");
+        return;
+    }
+
+    Server::start(config.clone(), server.clone(), None).await;
+}
+
+/// Get the next occurrence of any of the given times of day, in local time.
+///
+/// Times that don't exist on a day, such as within a daylight saving time gap, are skipped.
+pub fn next_occurrence(times: &[NaiveTime]) -> DateTime<Local> {
+    let now = Local::now();
+    (0..=2)
+        .filter_map(|days| now.date_naive().checked_add_days(Days::new(days)))
+        .flat_map(|date| times.iter().map(move |time| date.and_time(*time)))
+        .filter_map(|at| at.and_local_timezone(Local).earliest())
+        .filter(|at| *at > now)
+        .min()
+        .expect("no next occurrence for schedule")
+}
+
+/// Wait until the given local time is reached.
+pub async fn wait_until(at: DateTime<Local>) {
+    loop {
+        let remaining = match (at - Local::now()).to_std() {
+            Ok(remaining) if !remaining.is_zero() => remaining,
+            _ => return,
+        };
+        time::sleep(remaining.min(CLOCK_CHECK_INTERVAL)).await;
+    }
+}
@@ -1,14 +1,27 @@
+use std::error::Error;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::BytesMut;
 use futures::FutureExt;
 use tokio::net::{TcpListener, TcpStream};
 
-use crate::config::Config;
+use crate::config::{Config, Forwarding};
+#[cfg(all(feature = "tui", unix))]
+use crate::console;
+#[cfg(feature = "forwarding")]
+use crate::forwarding;
+use crate::hooks::CommandHooks;
+use crate::mc::ban::BanAction;
+use crate::net;
+use crate::os;
+use crate::preflight;
 use crate::proto::client::Client;
+use crate::proto::packet::{self, RawPacket};
 use crate::proxy::{self, ProxyHeader};
-use crate::server::{self, Server};
+use crate::ratelimit::RateLimit;
+use crate::server::{self, ActiveConnection, Server};
 use crate::service;
 use crate::status;
 use crate::util::error::{quit_error, ErrorHints};
@@ -23,6 +36,20 @@ pub async fn service(config: Arc<Config>) -> Result<(), ()> {
     // Load server state
     let server = Arc::new(Server::default());
 
+    // Show TUI if enabled and run interactively, fall back to plain logging
+    #[cfg(all(feature = "tui", unix))]
+    let tui = config.advanced.tui && service::tui::is_supported();
+    #[cfg(not(all(feature = "tui", unix)))]
+    let tui = false;
+    #[cfg(all(feature = "tui", unix))]
+    if tui {
+        console::set_tui(true);
+        tokio::spawn(service::tui::service(config.clone(), server.clone()));
+    }
+
+    // Check for configuration problems before binding
+    preflight::check(&config);
+
     // Listen for new connections
     let listener = TcpListener::bind(config.public.address)
         .await
@@ -33,11 +60,50 @@ pub async fn service(config: Arc<Config>) -> Result<(), ()> {
             );
         })?;
 
+    // Notify systemd we're ready to accept connections
+    #[cfg(all(unix, feature = "systemd"))]
+    {
+        os::systemd::status(&format!("Server {}", server.state().name()));
+        os::systemd::ready();
+    }
+
     info!(
         target: "lazymc",
         "Proxying public {} to server {}",
-        config.public.address, config.server.address,
+        config.public.address, config.server.address(),
     );
+    if config.server.addresses.len() > 1 {
+        info!(
+            target: "lazymc",
+            "Using fallback server addresses: {:?}",
+            &config.server.addresses[1..],
+        );
+    }
+
+    // Limit concurrent server connections
+    if let Some(max) = config.server.max_connections.filter(|max| *max > 0) {
+        net::set_outbound_limit(max);
+    }
+
+    // Limit bandwidth of proxied connections
+    if let Some(limit) = config
+        .advanced
+        .max_bandwidth_per_conn
+        .filter(|limit| *limit > 0)
+    {
+        proxy::set_bandwidth_limit(limit);
+    }
+
+    // Limit time to receive packets in
+    if config.advanced.packet_read_timeout > 0 {
+        packet::set_read_timeout(Duration::from_secs(
+            config.advanced.packet_read_timeout as u64,
+        ));
+    }
+
+    if config.server.min_free_memory_mb > 0 && os::free_memory_mb().is_none() {
+        warn!(target: "lazymc", "Minimum free memory is configured, but free memory is unknown on this platform, not checking it");
+    }
 
     if config.lockout.enabled {
         warn!(
@@ -46,9 +112,38 @@ pub async fn service(config: Arc<Config>) -> Result<(), ()> {
         );
     }
 
+    // Run configured hook commands on server lifecycle events
+    if let Some(hooks) = CommandHooks::new(config.clone()) {
+        server.add_hooks(Arc::new(hooks));
+    }
+
     // Spawn services: monitor, signal handler
     tokio::spawn(service::monitor::service(config.clone(), server.clone()));
     tokio::spawn(service::signal::service(config.clone(), server.clone()));
+    tokio::spawn(service::scheduler::service(config.clone(), server.clone()));
+    if config.advanced.stdin_commands && !tui {
+        tokio::spawn(service::stdin::service(config.clone(), server.clone()));
+    }
+    #[cfg(feature = "rcon")]
+    if config.rcon.enabled {
+        tokio::spawn(service::rcon::service(config.clone(), server.clone()));
+    }
+    #[cfg(feature = "api")]
+    if config.api.enabled {
+        tokio::spawn(service::api::service(config.clone(), server.clone()));
+    }
+    #[cfg(not(feature = "api"))]
+    if config.api.enabled {
+        warn!(target: "lazymc", "API is enabled in config, but not supported in this lazymc build");
+    }
+    #[cfg(feature = "webhook")]
+    if config.webhook.url.is_some() {
+        tokio::spawn(service::webhook::service(config.clone(), server.clone()));
+    }
+    #[cfg(not(feature = "webhook"))]
+    if config.webhook.url.is_some() {
+        warn!(target: "lazymc", "Webhook is configured, but not supported in this lazymc build");
+    }
 
     // Initiate server start
     if config.server.wake_on_start {
@@ -82,18 +177,95 @@ fn route(inbound: TcpStream, config: Arc<Config>, server: Arc<Server>) {
         }
     };
 
-    // Check ban state, just drop connection if enabled
-    let banned = server.is_banned_ip_blocking(&peer.ip());
-    if banned && config.server.drop_banned_ips {
-        info!(target: "lazymc", "Connection from banned IP {}, dropping", peer.ip());
+    // Trusted proxies send a PROXY header with the real client address first
+    if config.public.accept_proxy_header && config.public.is_trusted_proxy(peer.ip()) {
+        let service = async move {
+            let mut inbound = inbound;
+            match proxy::read_proxy_header(&mut inbound).await {
+                Ok(Some(client)) => {
+                    trace!(target: "lazymc", "Connection from {} through proxy {}", client, peer);
+                    route_peer(inbound, config, server, client).await;
+                }
+                Ok(None) => route_peer(inbound, config, server, peer).await,
+                Err(()) => {
+                    warn!(target: "lazymc", "Connection from trusted proxy {} without valid PROXY header, dropping", peer);
+                }
+            }
+        };
+        tokio::spawn(service);
         return;
     }
 
+    tokio::spawn(route_peer(inbound, config, server, peer));
+}
+
+/// Route inbound TCP stream from the given client address to correct service.
+async fn route_peer(
+    inbound: TcpStream,
+    config: Arc<Config>,
+    server: Arc<Server>,
+    peer: SocketAddr,
+) {
+    // Drop connection if IP is not allowed by access lists
+    if !config.access.allows(peer.ip()) {
+        debug!(target: "lazymc", "Connection from IP {} denied by access list, dropping", peer.ip());
+        return;
+    }
+
+    // Drop connection if IP exceeds the rate limit
+    if config.advanced.rate_limit_per_ip > 0 {
+        match server.rate_limiter.record(
+            peer.ip(),
+            config.advanced.rate_limit_per_ip,
+            Duration::from_secs(config.advanced.rate_limit_window as u64),
+        ) {
+            RateLimit::Allow => {}
+            RateLimit::Exceeded => {
+                warn!(target: "lazymc", "IP {} exceeded connection rate limit, dropping its connections", peer.ip());
+                return;
+            }
+            RateLimit::Limited => {
+                trace!(target: "lazymc", "Connection from rate limited IP {}, dropping", peer.ip());
+                return;
+            }
+        }
+    }
+
+    // Check ban state, just drop connection if enabled
+    let banned = match server.ban_action(&config, &peer.ip()).await {
+        BanAction::Allow => false,
+        BanAction::Drop(reason) => {
+            info!(target: "lazymc", "Connection from banned IP {} ({}), dropping", peer.ip(), reason);
+            return;
+        }
+        BanAction::Kick(reason) => {
+            debug!(target: "lazymc", "Connection from banned IP {} ({}), kicking on login", peer.ip(), reason);
+            true
+        }
+    };
+
     // Route connection through proper channel
-    let should_proxy =
-        !banned && server.state() == server::State::Started && !config.lockout.enabled;
+    let state = server.state();
+    let draining = server.is_draining();
+    let queue = state == server::State::Started && server.should_queue(&config).await;
+    let should_proxy = !banned
+        && state == server::State::Started
+        && !draining
+        && !config.lockout.enabled
+        && !queue;
+    trace!(
+        target: &config.server.log_target("lazymc::route"),
+        "Routing {}: state={:?} banned={} lockout={} draining={} queue={} -> {}",
+        peer,
+        state,
+        banned,
+        config.lockout.enabled,
+        draining,
+        queue,
+        if should_proxy { "proxy" } else { "status" },
+    );
     if should_proxy {
-        route_proxy(inbound, config)
+        route_proxy(inbound, config, &server, peer)
     } else {
         route_status(inbound, config, server, peer)
     }
@@ -115,44 +287,102 @@ fn route_status(inbound: TcpStream, config: Arc<Config>, server: Arc<Server>, pe
 
 /// Route inbound TCP stream to proxy, spawning a new task.
 #[inline]
-fn route_proxy(inbound: TcpStream, config: Arc<Config>) {
+fn route_proxy(inbound: TcpStream, config: Arc<Config>, server: &Arc<Server>, peer: SocketAddr) {
+    let connection = Server::track_connection(server);
+
     // When server is online, proxy all
-    let service = proxy::proxy(
-        inbound,
-        ProxyHeader::Proxy.not_none(config.server.send_proxy_v2),
-        config.server.address,
-    )
-    .map(|r| {
-        if let Err(err) = r {
-            warn!(target: "lazymc", "Failed to proxy: {}", err);
-        }
-    });
+    let service = async move {
+        let _connection = connection;
+        proxy_server(&config, inbound, peer, BytesMut::new())
+            .map(|r| {
+                if let Err(err) = r {
+                    warn!(target: "lazymc", "Failed to proxy: {}", err);
+                }
+            })
+            .await
+    };
 
     tokio::spawn(service);
 }
 
 /// Route inbound TCP stream to proxy with queued data, spawning a new task.
 #[inline]
-pub fn route_proxy_queue(inbound: TcpStream, config: Arc<Config>, queue: BytesMut) {
-    route_proxy_address_queue(
-        inbound,
-        ProxyHeader::Proxy.not_none(config.server.send_proxy_v2),
-        config.server.address,
-        queue,
-    );
+pub fn route_proxy_queue(
+    inbound: TcpStream,
+    config: Arc<Config>,
+    server: &Arc<Server>,
+    peer: SocketAddr,
+    queue: BytesMut,
+) {
+    let connection = Server::track_connection(server);
+
+    let service = async move {
+        let _connection = connection;
+        proxy_server(&config, inbound, peer, queue)
+            .map(|r| {
+                if let Err(err) = r {
+                    warn!(target: "lazymc", "Failed to proxy: {}", err);
+                }
+            })
+            .await
+    };
+
+    tokio::spawn(service);
+}
+
+/// Route inbound TCP stream to proxy with queued data, spawning a new task.
+///
+/// The given packet is injected once the client has joined the server.
+#[inline]
+pub fn route_proxy_queue_inject(
+    inbound: TcpStream,
+    config: Arc<Config>,
+    server: &Arc<Server>,
+    peer: SocketAddr,
+    queue: BytesMut,
+    inject: RawPacket,
+) {
+    // Packet can't be injected while forwarding player info, proxy without it
+    if cfg!(feature = "forwarding") && config.server.forwarding != Forwarding::None {
+        debug!(target: "lazymc", "Not injecting packet for client, player info forwarding is enabled");
+        route_proxy_queue(inbound, config, server, peer, queue);
+        return;
+    }
+
+    let proxy_header = ProxyHeader::proxy(config.server.send_proxy(), peer);
+    let addrs = config.server.addresses.clone();
+    let connection = Server::track_connection(server);
+
+    let service = async move {
+        let _connection = connection;
+        proxy::proxy_with_queue_inject(inbound, proxy_header, &addrs, &queue, inject)
+            .map(|r| {
+                if let Err(err) = r {
+                    warn!(target: "lazymc", "Failed to proxy: {}", err);
+                }
+            })
+            .await
+    };
+
+    tokio::spawn(service);
 }
 
-/// Route inbound TCP stream to proxy with given address and queued data, spawning a new task.
+/// Route inbound TCP stream to proxy with given addresses and queued data, spawning a new task.
+///
+/// Connects to the first reachable address. The given active connection is kept until proxying
+/// ends.
 #[inline]
 pub fn route_proxy_address_queue(
     inbound: TcpStream,
     proxy_header: ProxyHeader,
-    addr: SocketAddr,
+    addrs: Vec<SocketAddr>,
     queue: BytesMut,
+    connection: Option<ActiveConnection>,
 ) {
     // When server is online, proxy all
     let service = async move {
-        proxy::proxy_with_queue(inbound, proxy_header, addr, &queue)
+        let _connection = connection;
+        proxy::proxy_with_queue(inbound, proxy_header, &addrs, &queue)
             .map(|r| {
                 if let Err(err) = r {
                     warn!(target: "lazymc", "Failed to proxy: {}", err);
@@ -163,3 +393,30 @@ pub fn route_proxy_address_queue(
 
     tokio::spawn(service);
 }
+
+/// Proxy inbound TCP stream to the server with queued data.
+///
+/// Forwards player info to the server if enabled.
+async fn proxy_server(
+    config: &Config,
+    inbound: TcpStream,
+    peer: SocketAddr,
+    queue: BytesMut,
+) -> Result<(), Box<dyn Error>> {
+    let proxy_header = ProxyHeader::proxy(config.server.send_proxy(), peer);
+
+    #[cfg(feature = "forwarding")]
+    if config.server.forwarding != Forwarding::None {
+        return forwarding::proxy(
+            config,
+            inbound,
+            peer,
+            proxy_header,
+            &config.server.addresses,
+            queue,
+        )
+        .await;
+    }
+
+    proxy::proxy_with_queue(inbound, proxy_header, &config.server.addresses, &queue).await
+}
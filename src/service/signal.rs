@@ -1,32 +1,78 @@
+use std::future;
 use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
 
 use crate::config::Config;
-use crate::server::{self, Server};
+use crate::server::Server;
 use crate::util::error;
 
 /// Signal handler task.
+///
+/// Gracefully stops the server before quitting, so its world data is saved. Force kills the server
+/// if it doesn't stop within the stop timeout, or if another signal is received.
 pub async fn service(config: Arc<Config>, server: Arc<Server>) {
-    loop {
-        // Wait for SIGTERM/SIGINT signal
-        tokio::signal::ctrl_c().await.unwrap();
+    // Wait for SIGTERM/SIGINT signal
+    wait_for_signal().await;
+
+    // Quit right away if no server is running
+    let running = server.pid().await.is_some();
+    if server.stop_for_quit(&config).await {
+        info!(target: "lazymc", "Stopping server before quitting, send signal again to force kill...");
+    } else if running {
+        // Quitting kills the server process without saving, wait for it to be stopped instead
+        warn!(target: "lazymc", "Failed to stop server before quitting, configure RCON or a stop command to stop it gracefully. Stop it through its console, or send signal again to force kill without saving...");
+    } else {
+        quit();
+    }
 
-        // Quit if stopped
-        if server.state() == server::State::Stopped {
-            quit();
+    // Wait for server to stop within timeout, force kill on second signal
+    let timeout = async {
+        match config.server.stop_timeout {
+            0 => future::pending().await,
+            timeout => time::sleep(Duration::from_secs(timeout as u64)).await,
+        }
+    };
+    tokio::select! {
+        _ = server.wait_for_exit() => {
+            info!(target: "lazymc", "Server stopped, quitting");
+        }
+        _ = timeout, if config.server.force_kill_on_timeout => {
+            warn!(target: "lazymc", "Server did not stop within {}s, force killing", config.server.stop_timeout);
+            server.force_kill().await;
+        }
+        _ = wait_for_signal() => {
+            warn!(target: "lazymc", "Force killing server");
+            server.force_kill().await;
         }
+    }
+
+    quit();
+}
 
-        // Try to stop server
-        let stopping = server.stop(&config).await;
+/// Wait for a SIGTERM/SIGINT signal, or Ctrl-C on Windows.
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
 
-        // If not stopping, maybe due to failure, just quit
-        if !stopping {
-            quit();
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
         }
     }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.unwrap();
 }
 
 /// Gracefully quit.
 fn quit() -> ! {
+    #[cfg(all(unix, feature = "systemd"))]
+    crate::os::systemd::stopping();
+
     // TODO: gracefully quit self
     error::quit();
 }
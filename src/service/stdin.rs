@@ -0,0 +1,316 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use nbt::CompoundTag;
+use tokio::io::{self, AsyncBufReadExt, BufReader};
+
+use crate::config::Config;
+use crate::peers::PeerKind;
+use crate::server::{Server, State};
+
+/// Prefix for lazymc commands in console input.
+const COMMAND_PREFIX: char = '!';
+
+/// Default time to wait for players to leave when draining, in seconds.
+const DRAIN_TIMEOUT: u64 = 5 * 60;
+
+/// Dimension codec registries to show in probe info.
+const PROBE_INFO_REGISTRIES: [&str; 4] = [
+    "minecraft:dimension_type",
+    "minecraft:worldgen/biome",
+    "minecraft:chat_type",
+    "minecraft:damage_type",
+];
+
+/// Maximum number of registry entry names to show in probe info.
+const PROBE_INFO_MAX_NAMES: usize = 8;
+
+/// Service to handle console input.
+///
+/// Lines starting with `!` are handled as lazymc command, other lines are forwarded to the server.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    let mut lines = BufReader::new(io::stdin()).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                warn!(target: "lazymc", "Failed to read console input, no longer handling commands: {}", err);
+                break;
+            }
+        };
+
+        // Forward non-command input to server
+        let Some(cmd) = line.trim().strip_prefix(COMMAND_PREFIX) else {
+            if !server.send_command(&line).await {
+                debug!(target: "lazymc", "Ignoring console input, server is not running");
+            }
+            continue;
+        };
+
+        match dispatch(&config, &server, cmd).await {
+            Ok(output) => info!(target: "lazymc", "{}", output),
+            Err(err) => warn!(target: "lazymc", "{}", err),
+        }
+    }
+
+    trace!(target: "lazymc", "Console input closed");
+}
+
+/// Run a lazymc command.
+///
+/// The command is given without prefix, along with its arguments. Returns the command output, or
+/// an error message.
+pub async fn dispatch(
+    config: &Arc<Config>,
+    server: &Arc<Server>,
+    cmd: &str,
+) -> Result<String, String> {
+    let mut args = cmd.split_whitespace();
+
+    match args.next().unwrap_or_default() {
+        "help" => Ok(help()),
+        "status" => Ok(status(server).await),
+        "wake" => wake(config, server).await,
+        "sleep" => sleep(config, server).await,
+        "drain" => drain(config, server, args.next()),
+        "probe-info" => Ok(probe_info(server).await),
+        "peers" => Ok(peers(server).await),
+        "metrics" => Ok(metrics(server)),
+        other => Err(format!(
+            "Unknown command '{COMMAND_PREFIX}{other}', use '{COMMAND_PREFIX}help' to list commands"
+        )),
+    }
+}
+
+/// Command help text.
+fn help() -> String {
+    [
+        "Commands:",
+        "  !help              show this help",
+        "  !status            show server state and online players",
+        "  !wake              start the server",
+        "  !sleep             stop the server",
+        "  !drain [seconds]   refuse new players, stop server once empty or after timeout",
+        "  !probe-info        show details probed from the server",
+        "  !peers             list recent peers, players and status only (scanners)",
+        "  !metrics           show time clients waited for the server to start",
+    ]
+    .join("\n")
+}
+
+/// Describe server state and online players.
+async fn status(server: &Server) -> String {
+    let mut output = format!("Server is {:?}", server.state());
+    if server.is_draining() {
+        output.push_str(", draining");
+    }
+
+    if server.state() == State::Started {
+        if let Some(status) = server.status().await.as_ref() {
+            let names: Vec<_> = status
+                .players
+                .sample
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect();
+            output.push_str(&format!(
+                ", {}/{} players online",
+                status.players.online, status.players.max
+            ));
+            if !names.is_empty() {
+                output.push_str(&format!(": {}", names.join(", ")));
+            }
+        }
+    }
+
+    // Show server process resource usage if known
+    #[cfg(feature = "proc-stats")]
+    if let Some(usage) = server.process_usage() {
+        output.push_str(&format!(
+            "\nServer process uses {:.1}% CPU, {} MB memory",
+            usage.cpu,
+            usage.memory / 1024 / 1024
+        ));
+    }
+
+    output
+}
+
+/// Start the server.
+async fn wake(config: &Arc<Config>, server: &Arc<Server>) -> Result<String, String> {
+    if !config.server.is_managed() {
+        return Err("Cannot wake server, no server command configured".into());
+    }
+
+    if Server::start(config.clone(), server.clone(), None).await {
+        Ok("Starting server...".into())
+    } else {
+        Err(format!("Cannot wake server, it is {:?}", server.state()))
+    }
+}
+
+/// Stop the server.
+async fn sleep(config: &Config, server: &Server) -> Result<String, String> {
+    if server.state() != State::Started {
+        return Err(format!("Cannot sleep server, it is {:?}", server.state()));
+    }
+
+    if server.stop(config).await {
+        Ok("Stopping server...".into())
+    } else {
+        Err("Failed to stop server".into())
+    }
+}
+
+/// Drain the server, stop it once empty or after the given timeout in seconds.
+fn drain(
+    config: &Arc<Config>,
+    server: &Arc<Server>,
+    timeout: Option<&str>,
+) -> Result<String, String> {
+    let timeout = match timeout {
+        Some(secs) => secs
+            .parse()
+            .map_err(|_| format!("Invalid drain timeout '{secs}', must be in seconds"))?,
+        None => DRAIN_TIMEOUT,
+    };
+    if server.state() != State::Started {
+        return Err("Cannot drain server, it is not online".into());
+    }
+    if server.is_draining() {
+        return Err("Server is already draining".into());
+    }
+
+    let (config, server) = (config.clone(), server.clone());
+    tokio::spawn(async move {
+        server
+            .drain_and_stop(&config, Duration::from_secs(timeout), false)
+            .await
+    });
+    Ok(format!(
+        "Draining server, stopping once empty or after {timeout}s"
+    ))
+}
+
+/// List recent peers, and whether they logged in or only requested status.
+async fn peers(server: &Server) -> String {
+    let peers = server.peers.lock().await.list();
+    if peers.is_empty() {
+        return "No recent peers".into();
+    }
+
+    let mut output = vec![format!("Recent peers ({}):", peers.len())];
+    output.extend(peers.iter().map(|(ip, peer)| {
+        let kind = match peer.kind {
+            PeerKind::StatusOnly => "status only",
+            PeerKind::Login => "player",
+        };
+        format!(
+            "  {ip}: {kind}, {} status requests, seen {}s ago",
+            peer.status_count,
+            peer.last_seen.elapsed().as_secs()
+        )
+    }));
+    output.join("\n")
+}
+
+/// Describe runtime metrics.
+fn metrics(server: &Server) -> String {
+    let histograms = [
+        ("Hold wait", &server.metrics.hold_wait),
+        ("Lobby wait", &server.metrics.lobby_wait),
+    ];
+
+    let mut output = vec!["Metrics:".to_string()];
+    for (name, histogram) in histograms {
+        let Some(average) = histogram.average() else {
+            output.push(format!("  {name}: no clients waited"));
+            continue;
+        };
+        output.push(format!(
+            "  {name}: {} clients, {:.1}s average",
+            histogram.count(),
+            average.as_secs_f32()
+        ));
+
+        let buckets: Vec<_> = histogram
+            .buckets()
+            .into_iter()
+            .map(|(bound, count)| match bound {
+                Some(bound) => format!("<={bound}s: {count}"),
+                None => format!("all: {count}"),
+            })
+            .collect();
+        output.push(format!("    {}", buckets.join(", ")));
+    }
+    output.join("\n")
+}
+
+/// Describe probed server details.
+async fn probe_info(server: &Server) -> String {
+    let mut output = vec!["Probe info:".to_string()];
+
+    if let Some(status) = server.status().await.as_ref() {
+        output.push(format!(
+            "  Version: {} (protocol {})",
+            status.version.name, status.version.protocol
+        ));
+    }
+
+    match server.probed_join_game.read().await.as_ref() {
+        Some(join_game) => {
+            if let Some(ref world_names) = join_game.world_names {
+                output.push(format!("  World names: {}", world_names.join(", ")));
+            }
+            if let Some(ref world_name) = join_game.world_name {
+                output.push(format!("  World name: {world_name}"));
+            }
+            if let Some(ref dimension) = join_game.dimension {
+                output.push(format!(
+                    "  Dimension: {}",
+                    dimension.get_str("effects").unwrap_or("?")
+                ));
+            }
+            if let Some(ref codec) = join_game.dimension_codec {
+                output.push("  Dimension codec:".into());
+                output.extend(codec_summary(codec).into_iter().map(|l| format!("    {l}")));
+            }
+        }
+        None => output.push("  Join game: not probed".into()),
+    }
+
+    output.push(format!(
+        "  Forge payload: {} packets",
+        server.forge_payload.read().await.len()
+    ));
+
+    output.join("\n")
+}
+
+/// Summarize known registries in dimension codec, one line per registry.
+fn codec_summary(codec: &CompoundTag) -> Vec<String> {
+    PROBE_INFO_REGISTRIES
+        .iter()
+        .filter_map(|registry| {
+            let entries = codec
+                .get_compound_tag(registry)
+                .and_then(|r| r.get_compound_tag_vec("value"))
+                .ok()?;
+            let mut names: Vec<_> = entries
+                .iter()
+                .take(PROBE_INFO_MAX_NAMES)
+                .map(|e| e.get_str("name").unwrap_or("?"))
+                .collect();
+            if entries.len() > PROBE_INFO_MAX_NAMES {
+                names.push("...");
+            }
+            Some(format!(
+                "{registry} ({}): {}",
+                entries.len(),
+                names.join(", ")
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::os::fd::FromRawFd;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::time;
+
+use crate::config::Config;
+use crate::server::Server;
+use crate::service::stdin;
+
+/// Number of recent log lines to keep.
+const LOG_LINES: usize = 500;
+
+/// Interval to redraw the screen at.
+///
+/// New log lines are shown on the next redraw, to not redraw for each line.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Number of screen rows used by the header and footer.
+const CHROME_ROWS: usize = 4;
+
+/// Terminal settings before entering the TUI, restored on exit.
+static TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+
+/// Original standard error file descriptor, restored on exit.
+static STDERR: OnceLock<libc::c_int> = OnceLock::new();
+
+/// Recent log lines.
+type Log = Arc<Mutex<VecDeque<String>>>;
+
+/// Whether the TUI can be used, standard input and output must be a terminal.
+pub fn is_supported() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Service to show lazymc and server state in a terminal user interface.
+///
+/// Takes over the terminal, our own log output and the server console are shown in it.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    let log: Log = Default::default();
+
+    // Show our own log output and server console in the TUI
+    if let Err(err) = capture_stderr(log.clone()) {
+        warn!(target: "lazymc", "Failed to capture log output for TUI, not using TUI: {}", err);
+        return;
+    }
+    enter_terminal();
+
+    let mut keys = read_keys();
+    let mut console = server.console.subscribe();
+    let mut state = server.state_receiver();
+    let mut interval = time::interval(REDRAW_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = state.changed() => {},
+            line = console.recv() => {
+                match line {
+                    Ok(line) => push_log(&log, line),
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+                continue;
+            }
+            key = keys.recv() => match key {
+                Some(b's') => run_command(&config, &server, "wake").await,
+                Some(b'x') => run_command(&config, &server, "sleep").await,
+                Some(b'q') => unsafe {
+                    libc::raise(libc::SIGINT);
+                },
+                Some(_) => continue,
+                None => break,
+            },
+        }
+
+        draw(&config, &server, &log).await;
+    }
+}
+
+/// Run a lazymc console command, log its output.
+async fn run_command(config: &Arc<Config>, server: &Arc<Server>, cmd: &str) {
+    match stdin::dispatch(config, server, cmd).await {
+        Ok(output) => info!(target: "lazymc", "{}", output),
+        Err(err) => warn!(target: "lazymc", "{}", err),
+    }
+}
+
+/// Add a line to the log, drop the oldest if full.
+fn push_log(log: &Log, line: String) {
+    let mut log = log.lock().unwrap();
+    if log.len() >= LOG_LINES {
+        log.pop_front();
+    }
+    log.push_back(line);
+}
+
+/// Draw the screen.
+async fn draw(config: &Config, server: &Server, log: &Log) {
+    let (rows, _) = terminal_size();
+
+    // Build header with server state, players and idle time
+    let mut header = format!(
+        "\x1b[1mlazymc\x1b[0m {}  state: {:?}",
+        config.server.name.as_deref().unwrap_or_default(),
+        server.state(),
+    );
+    if server.is_draining() {
+        header.push_str(" (draining)");
+    }
+    if let Some(status) = server.status().await.as_ref() {
+        header.push_str(&format!(
+            "  players: {}/{}",
+            status.players.online, status.players.max
+        ));
+    }
+    if let Some(idle) = server.idle_time().await {
+        header.push_str(&format!("  idle: {}s", idle.as_secs()));
+    }
+
+    let mut screen = String::from("\x1b[H\x1b[2J");
+    screen.push_str(&header);
+    screen.push_str("\r\n\r\n");
+    {
+        let log = log.lock().unwrap();
+        let count = rows.saturating_sub(CHROME_ROWS);
+        for line in log.iter().skip(log.len().saturating_sub(count)) {
+            screen.push_str(line);
+            screen.push_str("\x1b[0m\r\n");
+        }
+    }
+    screen.push_str(&format!(
+        "\x1b[{};1H\x1b[7m [s] start  [x] stop  [q] quit \x1b[0m",
+        rows
+    ));
+
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(screen.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// Get the terminal size in rows and columns.
+fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0
+        || size.ws_row == 0
+    {
+        return (24, 80);
+    }
+    (size.ws_row as usize, size.ws_col as usize)
+}
+
+/// Redirect standard error into the given log.
+///
+/// Captures our own log output, so it doesn't corrupt the screen.
+fn capture_stderr(log: Log) -> io::Result<()> {
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stderr = libc::dup(libc::STDERR_FILENO);
+        if stderr < 0 || libc::dup2(fds[1], libc::STDERR_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::close(fds[1]);
+        let _ = STDERR.set(stderr);
+    }
+
+    let reader = unsafe { File::from_raw_fd(fds[0]) };
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            match line {
+                Ok(line) => push_log(&log, line),
+                Err(_) => break,
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Read key presses from standard input.
+fn read_keys() -> mpsc::Receiver<u8> {
+    let (tx, rx) = mpsc::channel(16);
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut key = [0];
+        while let Ok(1) = stdin.read(&mut key) {
+            if tx.blocking_send(key[0]).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Prepare the terminal, restores it on exit.
+///
+/// Switches to the alternate screen, disables line wrapping and line buffered input.
+fn enter_terminal() {
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) == 0 {
+            let _ = TERMIOS.set(termios);
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+        }
+        libc::atexit(restore_terminal);
+    }
+
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x1b[?1049h\x1b[?7l\x1b[?25l");
+    let _ = stdout.flush();
+}
+
+/// Restore the terminal and standard error, as it was before entering the TUI.
+extern "C" fn restore_terminal() {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x1b[?25h\x1b[?7h\x1b[?1049l");
+    let _ = stdout.flush();
+
+    unsafe {
+        if let Some(termios) = TERMIOS.get() {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios);
+        }
+        if let Some(stderr) = STDERR.get() {
+            libc::dup2(*stderr, libc::STDERR_FILENO);
+        }
+    }
+}
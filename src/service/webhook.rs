@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde_json::json;
+use tokio::time;
+
+use crate::config::Config;
+use crate::server::{Server, State};
+
+/// Timeout for sending a webhook request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Webhook service, notifies the configured URL of server state changes.
+///
+/// Sends at most one request per configured interval. State changes in the meantime are
+/// coalesced, only the latest state is sent.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    let Some(url) = config.webhook.url.clone() else {
+        return;
+    };
+
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(err) => {
+            error!(target: "lazymc::webhook", "Failed to set up webhook client: {}", err);
+            return;
+        }
+    };
+
+    let interval = Duration::from_secs(config.webhook.min_interval as u64);
+    let mut state = server.state_receiver();
+    let mut last = *state.borrow_and_update();
+
+    while state.changed().await.is_ok() {
+        let new = *state.borrow_and_update();
+        if new == last {
+            continue;
+        }
+
+        let body = body(&config, &server, last, new).await;
+        let result = client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => {
+                debug!(target: "lazymc::webhook", "Sent webhook for server state change to {}", new.name())
+            }
+            Err(err) => {
+                warn!(target: "lazymc::webhook", "Failed to send webhook for server state change: {}", err)
+            }
+        }
+        last = new;
+
+        // Limit request rate, a flapping server must not spam the endpoint
+        time::sleep(interval).await;
+    }
+}
+
+/// Build webhook JSON body for the given state change.
+async fn body(config: &Config, server: &Server, old: State, new: State) -> serde_json::Value {
+    // Only report players while online, last known status is kept after stopping
+    let players_online = match server.status().await.as_ref() {
+        Some(status) if new == State::Started => status.players.online,
+        _ => 0,
+    };
+
+    if config.webhook.discord {
+        let name = config.server.name.as_deref().unwrap_or("Minecraft");
+        return json!({
+            "content": format!(
+                "Server **{}** is now {} ({} players online)",
+                name,
+                new.name(),
+                players_online,
+            ),
+        });
+    }
+
+    json!({
+        "server": config.server.name,
+        "old_state": old.name(),
+        "new_state": new.name(),
+        "players_online": players_online,
+        "timestamp": Utc::now().to_rfc3339(),
+    })
+}
@@ -1,21 +1,25 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::BytesMut;
-use minecraft_protocol::data::server_status::{OnlinePlayers, ServerVersion};
+use minecraft_protocol::data::server_status::{OnlinePlayer, OnlinePlayers};
 use minecraft_protocol::decoder::Decoder;
 use minecraft_protocol::encoder::Encoder;
 use minecraft_protocol::version::v1_14_4::handshake::Handshake;
 use minecraft_protocol::version::v1_14_4::login::LoginStart;
 use minecraft_protocol::version::v1_20_3::status::{ServerStatus, StatusResponse};
-use tokio::fs;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::time;
 
-use crate::config::{Config, Server as ConfigServer};
+use crate::config::{Config, Server as ConfigServer, UnknownHost, UsernameCheck};
 use crate::join;
+use crate::mc::ban::BanAction;
 use crate::mc::favicon;
+use crate::net;
+use crate::peers::PeerKind;
 use crate::proto::action;
-use crate::proto::client::{Client, ClientInfo, ClientState};
+use crate::proto::client::{Client, ClientInfo, ClientState, TRANSFER_STATE_ID};
 use crate::proto::packet::{self, RawPacket};
 use crate::proto::packets;
 use crate::server::{self, Server};
@@ -23,12 +27,37 @@ use crate::server::{self, Server};
 /// The ban message prefix.
 const BAN_MESSAGE_PREFIX: &str = "Your IP address is banned from this server.\nReason: ";
 
-/// Default ban reason if unknown.
-const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
+/// The player ban message prefix.
+const PLAYER_BAN_MESSAGE_PREFIX: &str = "You are banned from this server.\nReason: ";
 
 /// The not-whitelisted kick message.
 const WHITELIST_MESSAGE: &str = "You are not white-listed on this server!";
 
+/// The client brand not allowed kick message.
+const BRAND_MESSAGE: &str = "Your client is not allowed to start this server.";
+
+/// The wake throttled kick message.
+const WAKE_THROTTLE_MESSAGE: &str = "You woke this server recently.\n\nPlease try again shortly.";
+
+/// The unknown host status and kick message.
+const UNKNOWN_HOST_MESSAGE: &str = "Unknown host, connect using the server address.";
+
+/// Kick message when there is not enough memory to start the server.
+const MEMORY_MESSAGE: &str = "Server is busy.\n\nPlease try again later.";
+
+/// The draining kick message.
+const DRAIN_MESSAGE: &str =
+    "Server is restarting, not accepting new players.\n\nPlease try to reconnect in a minute.";
+
+/// The invalid username kick message.
+const USERNAME_MESSAGE: &str = "Invalid username.";
+
+/// Legacy kick packet ID, used to respond to legacy server list pings.
+const LEGACY_KICK: u8 = 0xFF;
+
+/// Timeout for reading the rest of a legacy server list ping.
+const LEGACY_PING_READ_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Server icon file path.
 const SERVER_ICON_FILE: &str = "server-icon.png";
 
@@ -40,6 +69,14 @@ pub async fn serve(
     config: Arc<Config>,
     server: Arc<Server>,
 ) -> Result<(), ()> {
+    // Respond to legacy server list ping, sent by old clients and some uptime monitors
+    let mut first = [0];
+    if inbound.peek(&mut first).await.map_err(|_| ())? == 1
+        && first[0] == packets::handshake::LEGACY_PING
+    {
+        return serve_legacy_ping(client, inbound, &config, &server).await;
+    }
+
     let (mut reader, mut writer) = inbound.split();
 
     // Incoming buffer and packet holding queue
@@ -49,9 +86,13 @@ pub async fn serve(
     let mut inbound_history = BytesMut::new();
     let mut client_info = ClientInfo::empty();
 
+    // Whether the client connected with an unknown host
+    let mut unknown_host = false;
+
     loop {
         // Read packet from stream
-        let (packet, raw) = match packet::read_packet(&client, &mut buf, &mut reader).await {
+        let (packet, raw) = match packet::read_inbound_packet(&client, &mut buf, &mut reader).await
+        {
             Ok(Some(packet)) => packet,
             Ok(None) => break,
             Err(_) => {
@@ -85,6 +126,33 @@ pub async fn serve(
                 }
             };
 
+            trace!(
+                target: &config.server.log_target("lazymc::route"),
+                "Handshake from {}: host={:?} next_state={:?}",
+                client.peer,
+                handshake.server_addr,
+                new_state,
+            );
+
+            // Handle clients connecting with a host we don't serve
+            if !config.public.serves_host(&handshake.server_addr) {
+                match config.public.unknown_host {
+                    UnknownHost::Drop => {
+                        debug!(target: "lazymc", "Client {} connected with unknown host '{}', dropping", client.peer.ip(), handshake.server_addr);
+                        break;
+                    }
+                    UnknownHost::Default => {}
+                    UnknownHost::Status => {
+                        debug!(target: "lazymc", "Client {} connected with unknown host '{}'", client.peer.ip(), handshake.server_addr);
+                        unknown_host = true;
+                    }
+                }
+            }
+
+            if handshake.next_state == TRANSFER_STATE_ID {
+                debug!(target: "lazymc", "Client {} logging in through transfer", client.peer.ip());
+            }
+
             // Update client info and client state
             client_info
                 .protocol
@@ -102,7 +170,13 @@ pub async fn serve(
 
         // Hijack server status packet
         if client_state == ClientState::Status && packet.id == packets::status::SERVER_STATUS {
-            let server_status = server_status(&client_info, &config, &server).await;
+            let peer = server.peers.lock().await.record_status(client.peer.ip());
+            trace!(target: "lazymc", "Status request from {} ({} requests, {:?})", client.peer.ip(), peer.status_count, peer.kind);
+
+            let mut server_status = server_status(&client_info, &config, &server).await;
+            if unknown_host {
+                server_status.description = UNKNOWN_HOST_MESSAGE.into();
+            }
             let packet = StatusResponse { server_status };
 
             let mut data = Vec::new();
@@ -111,6 +185,11 @@ pub async fn serve(
             let response = RawPacket::new(0, data).encode_with_len(&client)?;
             writer.write_all(&response).await.map_err(|_| ())?;
 
+            // Wake server after responding, so the client still sees it is sleeping
+            if !unknown_host {
+                wake_on_status(&client, &client_info, &config, &server).await;
+            }
+
             continue;
         }
 
@@ -129,6 +208,47 @@ pub async fn serve(
                 .map(|p| p.name);
             client_info.username = username.clone();
 
+            // Kick if client connected with unknown host
+            if unknown_host {
+                info!(target: "lazymc", "Kicked {} because it connected with an unknown host", client.peer.ip());
+                action::kick(&client, UNKNOWN_HOST_MESSAGE, &mut writer).await?;
+                break;
+            }
+
+            // Track login, distinguishes players from peers only requesting status
+            match server.peers.lock().await.record_login(client.peer.ip()) {
+                Some(peer) if peer.kind == PeerKind::StatusOnly => {
+                    debug!(target: "lazymc", "Login from {} that only requested status before ({} requests)", client.peer.ip(), peer.status_count)
+                }
+                Some(_) => {
+                    debug!(target: "lazymc", "Login from {}, logged in before", client.peer.ip())
+                }
+                None => {
+                    debug!(target: "lazymc", "Login from {} without prior status request", client.peer.ip())
+                }
+            }
+
+            // Check username, scanners and bots often send malformed ones
+            if config.join.username_check != UsernameCheck::Off
+                && !is_valid_username(&config, username.as_deref())
+            {
+                let name = username.as_deref().unwrap_or("?");
+                match config.join.username_check {
+                    UsernameCheck::Off | UsernameCheck::Log => {
+                        warn!(target: "lazymc", "Login from {} with invalid username {:?}", client.peer.ip(), name)
+                    }
+                    UsernameCheck::Kick => {
+                        info!(target: "lazymc", "Login from {} with invalid username {:?}, kicking", client.peer.ip(), name);
+                        action::kick(&client, USERNAME_MESSAGE, &mut writer).await?;
+                        break;
+                    }
+                    UsernameCheck::Drop => {
+                        info!(target: "lazymc", "Login from {} with invalid username {:?}, dropping", client.peer.ip(), name);
+                        break;
+                    }
+                }
+            }
+
             // Kick if lockout is enabled
             if config.lockout.enabled {
                 match username {
@@ -141,18 +261,42 @@ pub async fn serve(
                 break;
             }
 
-            // Kick if client is banned
-            if let Some(ban) = server.ban_entry(&client.peer.ip()).await {
-                if ban.is_banned() {
-                    let msg = if let Some(reason) = ban.reason {
-                        info!(target: "lazymc", "Login from banned IP {} ({}), disconnecting", client.peer.ip(), &reason);
-                        reason.to_string()
-                    } else {
-                        info!(target: "lazymc", "Login from banned IP {}, disconnecting", client.peer.ip());
-                        DEFAULT_BAN_REASON.to_string()
-                    };
-                    action::kick(&client, &format!("{BAN_MESSAGE_PREFIX}{msg}"), &mut writer)
-                        .await?;
+            // Kick if server is draining
+            if server.is_draining() {
+                info!(target: "lazymc", "Kicked player because server is draining");
+                action::kick(&client, DRAIN_MESSAGE, &mut writer).await?;
+                break;
+            }
+
+            // Kick or drop if client is banned
+            match server.ban_action(&config, &client.peer.ip()).await {
+                BanAction::Allow => {}
+                BanAction::Drop(reason) => {
+                    info!(target: "lazymc", "Login from banned IP {} ({}), dropping", client.peer.ip(), reason);
+                    break;
+                }
+                BanAction::Kick(reason) => {
+                    info!(target: "lazymc", "Login from banned IP {} ({}), kicking", client.peer.ip(), reason);
+                    action::kick(
+                        &client,
+                        &format!("{BAN_MESSAGE_PREFIX}{reason}"),
+                        &mut writer,
+                    )
+                    .await?;
+                    break;
+                }
+            }
+
+            // Kick if player is banned
+            if let Some(ref username) = username {
+                if let Some(ban) = server.player_ban(username).await {
+                    info!(target: "lazymc", "Login from banned player '{}' ({}), kicking", username, ban.reason());
+                    action::kick(
+                        &client,
+                        &format!("{PLAYER_BAN_MESSAGE_PREFIX}{}", ban.reason()),
+                        &mut writer,
+                    )
+                    .await?;
                     break;
                 }
             }
@@ -166,8 +310,33 @@ pub async fn serve(
                 }
             }
 
-            // Start server if not starting yet
-            Server::start(config.clone(), server.clone(), username).await;
+            // Kick if client brand is not allowed to wake server
+            let brand = client_info.brand();
+            if !config.server.may_wake_brand(brand) && server.state() == server::State::Stopped {
+                info!(target: "lazymc", "Client with brand '{}' tried to wake server but is not allowed, disconnecting", brand);
+                action::kick(&client, BRAND_MESSAGE, &mut writer).await?;
+                break;
+            }
+
+            // Kick if client woke the server too recently
+            if !server.may_wake_throttled(&config, client.peer.ip()).await {
+                info!(target: "lazymc", "IP {} tried to wake server but is throttled, disconnecting", client.peer.ip());
+                action::kick(&client, WAKE_THROTTLE_MESSAGE, &mut writer).await?;
+                break;
+            }
+
+            // Kick if there is not enough memory to start the server
+            if server.state() == server::State::Stopped && !server::has_free_memory(&config) {
+                info!(target: "lazymc", "Not enough memory to wake server for {}, disconnecting", client.peer.ip());
+                action::kick(&client, MEMORY_MESSAGE, &mut writer).await?;
+                break;
+            }
+
+            // Start server if not starting yet, remember who woke it
+            if Server::start(config.clone(), server.clone(), username.clone()).await {
+                server.record_wake(&config, client.peer.ip()).await;
+                server.set_wake_initiator(client.peer.ip(), username).await;
+            }
 
             // Remember inbound packets
             inbound_history.extend(&raw);
@@ -204,6 +373,124 @@ pub async fn serve(
     Ok(())
 }
 
+/// Respond to a legacy server list ping, from clients older than Minecraft 1.7.
+///
+/// Responds with the same status as modern clients get, in the legacy format.
+async fn serve_legacy_ping(
+    client: Client,
+    mut inbound: TcpStream,
+    config: &Config,
+    server: &Server,
+) -> Result<(), ()> {
+    let peer = server.peers.lock().await.record_status(client.peer.ip());
+    trace!(target: "lazymc", "Legacy status request from {} ({} requests, {:?})", client.peer.ip(), peer.status_count, peer.kind);
+
+    let status = server_status(&ClientInfo::empty(), config, server).await;
+    let description = serde_json::to_value(&status.description)
+        .map(|description| plain_text(&description))
+        .unwrap_or_default();
+    let response = format!(
+        "§1\0{}\0{}\0{}\0{}\0{}",
+        status.version.protocol,
+        status.version.name,
+        description.replace('\0', ""),
+        status.players.online,
+        status.players.max,
+    );
+
+    // Encode kick packet with response as UTF-16 string
+    let chars: Vec<u16> = response.encode_utf16().collect();
+    let mut packet = vec![LEGACY_KICK];
+    packet.extend((chars.len() as u16).to_be_bytes());
+    packet.extend(chars.iter().flat_map(|c| c.to_be_bytes()));
+
+    // Consume ping request, closing with unread data may reset connection before client reads
+    let mut buf = [0; 512];
+    let _ = time::timeout(LEGACY_PING_READ_TIMEOUT, inbound.read(&mut buf)).await;
+
+    inbound.write_all(&packet).await.map_err(|_| ())?;
+    net::close_tcp_stream(inbound).await.map_err(|_| ())
+}
+
+/// Get plain text from a JSON text component, or from a plain string.
+fn plain_text(text: &serde_json::Value) -> String {
+    match text {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Object(text) => {
+            let mut plain = text
+                .get("text")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string();
+            if let Some(serde_json::Value::Array(extra)) = text.get("extra") {
+                plain.extend(extra.iter().map(plain_text));
+            }
+            plain
+        }
+        serde_json::Value::Array(parts) => parts.iter().map(plain_text).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Check whether the given username is valid, as configured.
+///
+/// A missing username is invalid.
+fn is_valid_username(config: &Config, username: Option<&str>) -> bool {
+    username
+        .map(|username| config.join.username_pattern.is_match(username))
+        .unwrap_or(false)
+}
+
+/// Wake the server on a status request, if enabled and the client may wake it.
+///
+/// Applies the checks done when waking on login, as far as possible without knowing the username.
+async fn wake_on_status(
+    client: &Client,
+    client_info: &ClientInfo,
+    config: &Arc<Config>,
+    server: &Arc<Server>,
+) {
+    if !config.server.wake_on_status_ping
+        || server.state() != server::State::Stopped
+        || config.lockout.enabled
+    {
+        return;
+    }
+
+    // Client must not be banned, a whitelist can't be checked without username
+    if !matches!(
+        server.ban_action(config, &client.peer.ip()).await,
+        BanAction::Allow
+    ) || server.has_whitelist().await
+    {
+        return;
+    }
+
+    // Only wake for known players, scanners only ever request the status
+    if !server.peers.lock().await.is_player(client.peer.ip()) {
+        trace!(target: "lazymc", "Not waking server on status request from {}, it didn't log in recently", client.peer.ip());
+        return;
+    }
+
+    // Client brand must be allowed to wake server
+    if !config.server.may_wake_brand(client_info.brand()) {
+        return;
+    }
+
+    // Wake debounced, and respect wake throttling of this client
+    if !server.may_status_wake().await || !server.may_wake_throttled(config, client.peer.ip()).await
+    {
+        return;
+    }
+
+    info!(target: "lazymc", "Status request from {}, waking server", client.peer.ip());
+    if Server::start(config.clone(), server.clone(), None).await {
+        server.record_status_wake().await;
+        server.record_wake(config, client.peer.ip()).await;
+        server.set_wake_initiator(client.peer.ip(), None).await;
+    }
+}
+
 /// Build server status object to respond to client with.
 async fn server_status(client_info: &ClientInfo, config: &Config, server: &Server) -> ServerStatus {
     let status = server.status().await;
@@ -211,38 +498,61 @@ async fn server_status(client_info: &ClientInfo, config: &Config, server: &Serve
 
     // Respond with real server status if started
     if server_state == server::State::Started && status.is_some() {
-        return status.as_ref().unwrap().clone();
+        let mut status = status.as_ref().unwrap().clone();
+        if let Some(ref name) = config.motd.version_name {
+            status.version.name = name.clone();
+        }
+
+        // Fill in last known players if the server didn't include any
+        if status.players.sample.is_empty() && status.players.online > 0 {
+            status.players.sample = server.player_sample().await.unwrap_or_default();
+        }
+        return status;
     }
 
-    // Select version and player max from last known server status
-    let (version, max) = match status.as_ref() {
+    // Select version and player max from last known server status, or from probe, config and
+    // server.properties
+    let (mut version, max) = match status.as_ref() {
         Some(status) => (status.version.clone(), status.players.max),
         None => (
-            ServerVersion {
-                name: config.public.version.clone(),
-                protocol: config.public.protocol,
-            },
-            0,
+            server.version(config).await,
+            server.max_players().await.unwrap_or(0),
         ),
     };
+    if let Some(ref name) = config.motd.version_name {
+        version.name = name.clone();
+    }
 
     // Select description, use server MOTD if enabled, or use configured
     let description = {
         if config.motd.from_server && status.is_some() {
             status.as_ref().unwrap().description.clone()
         } else {
-            match server_state {
-                server::State::Stopped | server::State::Started => config.motd.sleeping.clone(),
-                server::State::Starting => config.motd.starting.clone(),
-                server::State::Stopping => config.motd.stopping.clone(),
-            }
+            let motd = match (server_state, &config.motd.cold_start) {
+                (server::State::Stopped | server::State::Starting, Some(motd))
+                    if server.is_cold_start() =>
+                {
+                    motd
+                }
+                (server::State::Stopped | server::State::Started, _) => &config.motd.sleeping,
+                (server::State::Starting, _) => &config.motd.starting,
+                (server::State::Stopping, _) => &config.motd.stopping,
+            };
+
+            // Players are only online while the server runs, last known status is kept after
+            let online = match status.as_ref() {
+                Some(status) if server_state != server::State::Stopped => status.players.online,
+                _ => 0,
+            };
+            resolve_placeholders(motd, server, online, max).await
         }
     };
 
-    // Extract favicon from real server status, load from disk, or use default
+    // Use state favicon, extract favicon from real server status, load from disk, or use default
     let mut favicon = None;
     if favicon::supports_favicon(client_info) {
-        if config.motd.from_server && status.is_some() {
+        favicon = state_favicon(config, server_state).await;
+        if favicon.is_none() && config.motd.from_server && status.is_some() {
             favicon = status.as_ref().unwrap().favicon.clone()
         }
         if favicon.is_none() {
@@ -250,19 +560,94 @@ async fn server_status(client_info: &ClientInfo, config: &Config, server: &Serve
         }
     }
 
+    // Show last known players while online, or configured line while sleeping
+    let (online, sample) = match (server_state, &config.motd.sleeping_sample) {
+        (server::State::Started, _) => match server.player_sample().await {
+            Some(sample) => {
+                let online = status.as_ref().map(|s| s.players.online).unwrap_or(0);
+                (online.max(sample.len() as u32), sample)
+            }
+            None => (0, vec![]),
+        },
+        (server::State::Stopped, Some(line)) => (
+            0,
+            vec![OnlinePlayer {
+                name: line.clone(),
+                // Nil UUID
+                id: Default::default(),
+            }],
+        ),
+        _ => (0, vec![]),
+    };
+
     // Build status resposne
     ServerStatus {
         version,
         description,
         players: OnlinePlayers {
-            online: 0,
+            online,
             max,
-            sample: vec![],
+            sample,
         },
         favicon,
     }
 }
 
+/// Resolve placeholders in the given MOTD.
+///
+/// Supports `{state}`, `{players_online}`, `{players_max}`, `{last_seen}` and `{progress}`.
+async fn resolve_placeholders(motd: &str, server: &Server, online: u32, max: u32) -> String {
+    // Skip if there are no placeholders
+    if !motd.contains('{') {
+        return motd.into();
+    }
+
+    let last_seen = match server.idle_time().await {
+        Some(idle) => format_duration(idle),
+        None => "unknown".into(),
+    };
+
+    let mut motd = motd
+        .replace("{state}", server.state().name())
+        .replace("{players_online}", &online.to_string())
+        .replace("{players_max}", &max.to_string())
+        .replace("{last_seen}", &last_seen);
+    if motd.contains("{progress}") {
+        motd = motd.replace("{progress}", &server.progress().await.unwrap_or_default());
+    }
+    motd
+}
+
+/// Format the given duration in a short human readable form, such as `2h 5m`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+/// Get configured favicon for the current server state.
+///
+/// Returns `None` if no icon is configured for this state, or if it could not be loaded.
+async fn state_favicon(config: &Config, state: server::State) -> Option<String> {
+    let icon = match state {
+        server::State::Stopped | server::State::Stopping => config.motd.icon_sleeping.as_ref()?,
+        server::State::Starting => config.motd.icon_starting.as_ref()?,
+        server::State::Started => return None,
+    };
+
+    // Relative to server directory
+    let path = match ConfigServer::server_directory(config) {
+        Some(dir) => dir.join(icon),
+        None => icon.clone(),
+    };
+
+    favicon::load_favicon(&path).await
+}
+
 /// Get server status favicon.
 ///
 /// This always returns a favicon, returning the default one if none is set.
@@ -273,17 +658,8 @@ async fn server_favicon(config: &Config) -> String {
         None => return favicon::default_favicon(),
     };
 
-    // Server icon file, ensure it exists
-    let path = dir.join(SERVER_ICON_FILE);
-    if !path.is_file() {
-        return favicon::default_favicon();
-    }
-
-    // Read icon data
-    let data = fs::read(path).await.unwrap_or_else(|err| {
-        error!(target: "lazymc::status", "Failed to read favicon from {}, using default: {err}", SERVER_ICON_FILE);
-        favicon::default_favicon().into_bytes()
-    });
-
-    favicon::encode_favicon(&data)
+    // Load server icon file, use default if not loaded
+    favicon::load_favicon(&dir.join(SERVER_ICON_FILE))
+        .await
+        .unwrap_or_else(favicon::default_favicon)
 }
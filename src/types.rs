@@ -1,6 +1,21 @@
+/// Maximum number of bytes in a var-int.
+pub const VAR_INT_MAX_LEN: usize = 5;
+
+/// Var-int read error.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VarIntError {
+    /// Buffer ends before the var-int does, more data is required.
+    Incomplete,
+
+    /// Var-int is longer than 5 bytes or doesn't fit an `i32`.
+    Invalid,
+}
+
 /// Try to read var-int from data buffer.
-pub fn read_var_int(buf: &[u8]) -> Result<(usize, i32), ()> {
-    for len in 1..=5.min(buf.len()) {
+///
+/// Returns the number of bytes read and the value.
+pub fn read_var_int(buf: &[u8]) -> Result<(usize, i32), VarIntError> {
+    for len in 1..=VAR_INT_MAX_LEN.min(buf.len()) {
         // Find var-int byte size
         let extra_byte = (buf[len - 1] & (1 << 7)) > 0;
         if extra_byte {
@@ -13,12 +28,16 @@ pub fn read_var_int(buf: &[u8]) -> Result<(usize, i32), ()> {
         // Parse var-int, return result
         return match minecraft_protocol::decoder::var_int::decode(&mut &*buf) {
             Ok(val) => Ok((len, val)),
-            Err(_) => Err(()),
+            Err(_) => Err(VarIntError::Invalid),
         };
     }
 
-    // The buffer wasn't complete or the var-int is invalid
-    Err(())
+    // The var-int is too long, or the buffer wasn't complete
+    if buf.len() >= VAR_INT_MAX_LEN {
+        Err(VarIntError::Invalid)
+    } else {
+        Err(VarIntError::Incomplete)
+    }
 }
 
 /// Encode integer into a var-int.
@@ -27,3 +46,39 @@ pub fn encode_var_int(i: i32) -> Result<Vec<u8>, ()> {
     minecraft_protocol::encoder::var_int::encode(&i, &mut buf).map_err(|_| ())?;
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_int_valid() {
+        assert_eq!(read_var_int(&[0x00]), Ok((1, 0)));
+        assert_eq!(read_var_int(&[0xac, 0x02, 0xff]), Ok((2, 300)));
+        assert_eq!(
+            read_var_int(&[0xff, 0xff, 0xff, 0xff, 0x07]),
+            Ok((5, i32::MAX))
+        );
+        assert_eq!(read_var_int(&encode_var_int(300).unwrap()), Ok((2, 300)));
+    }
+
+    #[test]
+    fn var_int_overlong() {
+        assert_eq!(read_var_int(&[0x80; 5]), Err(VarIntError::Invalid));
+        assert_eq!(read_var_int(&[0x80; 6]), Err(VarIntError::Invalid));
+        assert_eq!(
+            read_var_int(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x01]),
+            Err(VarIntError::Invalid)
+        );
+    }
+
+    #[test]
+    fn var_int_truncated() {
+        assert_eq!(read_var_int(&[]), Err(VarIntError::Incomplete));
+        assert_eq!(read_var_int(&[0x80]), Err(VarIntError::Incomplete));
+        assert_eq!(
+            read_var_int(&[0xff, 0xff, 0xff, 0xff]),
+            Err(VarIntError::Incomplete)
+        );
+    }
+}
@@ -1,8 +1,13 @@
 use std::net::{SocketAddr, ToSocketAddrs};
 
+use chrono::NaiveTime;
+use regex::Regex;
 use serde::de::{Error, Unexpected};
 use serde::{Deserialize, Deserializer};
 
+use crate::config::TimeRange;
+use crate::proto::client::CLIENT_BRANDS;
+
 /// Deserialize a `Vec` into a `HashMap` by key.
 pub fn to_socket_addrs<'de, D>(d: D) -> Result<SocketAddr, D::Error>
 where
@@ -11,6 +16,52 @@ where
     // Deserialize string
     let addr = String::deserialize(d)?;
 
+    resolve_socket_addr(addr)
+}
+
+/// Deserialize a single socket address or a list of them.
+///
+/// Errors if an empty list is given.
+pub fn to_socket_addrs_list<'de, D>(d: D) -> Result<Vec<SocketAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    // Deserialize string or list of strings
+    let addrs = match OneOrMany::deserialize(d)? {
+        OneOrMany::One(addr) => vec![addr],
+        OneOrMany::Many(addrs) => addrs,
+    };
+    if addrs.is_empty() {
+        return Err(Error::invalid_length(0, &"at least one address"));
+    }
+
+    addrs.into_iter().map(resolve_socket_addr).collect()
+}
+
+/// Deserialize a list of client brands, errors on brands that can't be detected.
+pub fn to_client_brands<'de, D>(d: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let brands = Vec::<String>::deserialize(d)?;
+    match brands
+        .iter()
+        .find(|brand| !CLIENT_BRANDS.contains(&brand.as_str()))
+    {
+        Some(brand) => Err(Error::unknown_variant(brand, &CLIENT_BRANDS)),
+        None => Ok(brands),
+    }
+}
+
+/// Resolve the given socket address string.
+fn resolve_socket_addr<E: Error>(addr: String) -> Result<SocketAddr, E> {
     // Try to socket address to resolve
     match addr.to_socket_addrs() {
         Ok(mut addr) => {
@@ -28,3 +79,72 @@ where
         Error::invalid_value(Unexpected::Str(&addr), &"IP or resolvable host and port")
     })
 }
+
+/// Deserialize a list of times of day, formatted as `HH:MM` or `HH:MM:SS`.
+pub fn to_times<'de, D>(d: D) -> Result<Vec<NaiveTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .map(|time| parse_time(time))
+        .collect()
+}
+
+/// Deserialize an optional time of day, formatted as `HH:MM` or `HH:MM:SS`.
+pub fn to_time_option<'de, D>(d: D) -> Result<Option<NaiveTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(d)?
+        .map(|time| parse_time(&time))
+        .transpose()
+}
+
+/// Deserialize a regular expression, errors if it is invalid.
+pub fn to_regex<'de, D>(d: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(d)?;
+    Regex::new(&pattern).map_err(Error::custom)
+}
+
+/// Deserialize an optional regular expression, errors if it is invalid.
+pub fn to_regex_option<'de, D>(d: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(d)?
+        .map(|pattern| Regex::new(&pattern).map_err(Error::custom))
+        .transpose()
+}
+
+/// Deserialize a list of daily time ranges, formatted as `HH:MM-HH:MM`.
+pub fn to_time_ranges<'de, D>(d: D) -> Result<Vec<TimeRange>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .map(|range| {
+            let (start, end) = range.split_once('-').ok_or_else(|| {
+                Error::invalid_value(
+                    Unexpected::Str(range),
+                    &"time range formatted as HH:MM-HH:MM",
+                )
+            })?;
+            Ok(TimeRange {
+                start: parse_time(start.trim())?,
+                end: parse_time(end.trim())?,
+            })
+        })
+        .collect()
+}
+
+/// Parse a time of day, formatted as `HH:MM` or `HH:MM:SS`.
+fn parse_time<E: Error>(time: &str) -> Result<NaiveTime, E> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M:%S"))
+        .map_err(|_| Error::invalid_value(Unexpected::Str(time), &"time formatted as HH:MM"))
+}